@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use rocket::{http::Status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{
+        attempt_model::{AttemptModel, QuestionKind},
+        info_model::ContentLevel,
+        publication_model::ContentKind,
+        review_model::review_quality,
+        template_model::QuestionTemplate,
+        tests_model::{canonical_answer, TestModel, TestModelWithActions, TestTranslation},
+    },
+    repository::{
+        attempts_repo::AttemptsRepo, publications_repo::PublicationsRepo,
+        ratings_repo::RatingsRepo, reviews_repo::ReviewsRepo, skills_repo::SkillsRepo,
+        user_repo::UserRepo,
+    },
+    utils::{
+        auth::authorize_token,
+        locale::Locales,
+    },
+};
+
+/// `GradedQuestion` is the part of a question that is needed to run an attempt, whatever the kind
+/// of the question is.
+///
+/// Properties:
+///
+/// * `kind`: The kind of the question.
+/// * `answer`: The correct answer.
+/// * `hints`: Progressive hints.
+/// * `explanation`: The worked explanation.
+/// * `hint_penalty`: Percent of the score taken off for every revealed hint.
+/// * `answers`: All the answers, used to grade answers given in another locale.
+/// * `translations`: The translations of the question.
+/// * `skills`: The ids of the skills the question exercises.
+/// * `theme`: The theme of the question.
+/// * `theme_id`: The theme id of the question.
+/// * `level`: The hand-set level of the question.
+#[derive(Debug, Clone)]
+pub struct GradedQuestion {
+    pub kind: QuestionKind,
+    pub answer: String,
+    pub hints: Vec<ContentLevel>,
+    pub explanation: Vec<ContentLevel>,
+    pub hint_penalty: Option<i32>,
+    pub answers: Vec<String>,
+    pub translations: HashMap<String, TestTranslation>,
+    pub skills: Vec<String>,
+    pub theme: String,
+    pub theme_id: Option<String>,
+    pub level: i32,
+}
+
+impl GradedQuestion {
+    /// It replaces the hints and the explanation with the translation the user prefers. The
+    /// correct answer stays in the default locale, it is what answers are graded against.
+    ///
+    /// Returns:
+    ///
+    /// The correct answer as the user has seen it.
+    pub fn localize(&mut self, locales: &Locales) -> String {
+        let translation = match locales.pick(&self.translations) {
+            Some((_, translation)) => translation,
+            None => return self.answer.clone(),
+        };
+        if let Some(hints) = &translation.hints {
+            self.hints = hints.clone();
+        }
+        if let Some(explanation) = &translation.explanation {
+            self.explanation = explanation.clone();
+        }
+        let position = self.answers.iter().position(|a| *a == self.answer);
+        match (&translation.answers, position) {
+            (Some(answers), Some(position)) if answers.len() == self.answers.len() => {
+                answers[position].clone()
+            }
+            _ => self.answer.clone(),
+        }
+    }
+
+    /// It maps the answer of the user to the default locale, see `canonical_answer`.
+    pub fn canonical_answer(&self, answer: &str) -> String {
+        canonical_answer(&self.answers, &self.translations, answer)
+    }
+}
+
+/// It finds the question with the given id in the published templates, choice tests and action
+/// tests. A template is instantiated with the given seed, so the correct answer matches the
+/// question the user has seen.
+///
+/// Arguments:
+///
+/// * `pubdb`: &PublicationsRepo - the published snapshots of the tests and templates
+/// * `test_id`: &str - the id of the test or of the template
+/// * `seed`: i64 - the seed of a question generated from a template
+///
+/// Returns:
+///
+/// The question, or None if there is no question with the given id.
+pub async fn load_question(
+    pubdb: &PublicationsRepo,
+    test_id: &str,
+    seed: i64,
+) -> Option<GradedQuestion> {
+    if let Some(template) = pubdb
+        .get_published::<QuestionTemplate>(ContentKind::Template, test_id)
+        .await
+        .unwrap()
+    {
+        let test = template.instantiate(seed).ok()?.test;
+        return Some(GradedQuestion {
+            kind: QuestionKind::Template,
+            answer: test.answer,
+            hints: test.hints,
+            explanation: test.explanation,
+            hint_penalty: test.hint_penalty,
+            answers: test.answers,
+            translations: test.translations,
+            skills: test.skills,
+            theme: test.theme,
+            theme_id: test.theme_id,
+            level: test.level,
+        });
+    }
+    if let Some(test) = pubdb
+        .get_published::<TestModel>(ContentKind::Choice, test_id)
+        .await
+        .unwrap()
+    {
+        return Some(GradedQuestion {
+            kind: QuestionKind::Choice,
+            answer: test.answer,
+            hints: test.hints,
+            explanation: test.explanation,
+            hint_penalty: test.hint_penalty,
+            answers: test.answers,
+            translations: test.translations,
+            skills: test.skills,
+            theme: test.theme,
+            theme_id: test.theme_id,
+            level: test.level,
+        });
+    }
+    pubdb
+        .get_published::<TestModelWithActions>(ContentKind::Action, test_id)
+        .await
+        .unwrap()
+        .map(|test| GradedQuestion {
+            kind: QuestionKind::Action,
+            answer: test.answer,
+            hints: test.hints,
+            explanation: test.explanation,
+            hint_penalty: test.hint_penalty,
+            answers: test.answers,
+            translations: test.translations,
+            skills: test.skills,
+            theme: test.theme,
+            theme_id: test.theme_id,
+            level: test.level,
+        })
+}
+
+/// `AttemptStart` is the request to start answering a question.
+///
+/// Properties:
+///
+/// * `test_id`: The id of the test or of the template.
+/// * `seed`: The seed of the question generated from a template.
+#[derive(Debug, Deserialize)]
+pub struct AttemptStart {
+    pub test_id: String,
+    pub seed: Option<i64>,
+}
+
+/// `AttemptStarted` is the response to `AttemptStart`.
+///
+/// Properties:
+///
+/// * `attempt_id`: The id of the new attempt.
+/// * `hints_total`: How many hints the question has.
+#[derive(Debug, Serialize)]
+pub struct AttemptStarted {
+    pub attempt_id: String,
+    pub hints_total: usize,
+}
+
+/// It starts a new attempt of the user to answer a question.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `attempts_db`: &State<AttemptsRepo> - the attempts
+/// * `user_db`: &State<UserRepo> - the users, used to check the token
+/// * `token`: the token of the user
+/// * `start`: Json<AttemptStart> - the question to answer
+///
+/// Returns:
+///
+/// The id of the attempt and the number of hints of the question.
+#[post("/user/<token>/start/attempt", data = "<start>")]
+pub async fn start_attempt(
+    pubdb: &State<PublicationsRepo>,
+    attempts_db: &State<AttemptsRepo>,
+    user_db: &State<UserRepo>,
+    token: &str,
+    start: Json<AttemptStart>,
+) -> Result<Json<AttemptStarted>, Status> {
+    let (authorized, user_id) = authorize_token(token.to_string(), user_db).await;
+    if !authorized {
+        return Err(Status::Unauthorized);
+    }
+    let start = start.into_inner();
+    let question = load_question(pubdb, &start.test_id, start.seed.unwrap_or(0))
+        .await
+        .ok_or(Status::NotFound)?;
+    let seed = match question.kind {
+        QuestionKind::Template => Some(start.seed.ok_or(Status::BadRequest)?),
+        _ => None,
+    };
+    let attempt = AttemptModel::new(user_id, start.test_id, question.kind, seed);
+    let result = attempts_db
+        .create_attempt(attempt)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(AttemptStarted {
+        attempt_id: result.inserted_id.as_object_id().unwrap().to_hex(),
+        hints_total: question.hints.len(),
+    }))
+}
+
+/// It loads an attempt and checks that it belongs to the user with the given token.
+async fn user_attempt(
+    attempts_db: &AttemptsRepo,
+    user_db: &State<UserRepo>,
+    token: &str,
+    id: &str,
+) -> Result<AttemptModel, Status> {
+    let (authorized, user_id) = authorize_token(token.to_string(), user_db).await;
+    if !authorized {
+        return Err(Status::Unauthorized);
+    }
+    let attempt = attempts_db
+        .get_attempt(id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    if attempt.user_id != user_id {
+        return Err(Status::Forbidden);
+    }
+    Ok(attempt)
+}
+
+/// `RevealedHint` is the next hint of a question.
+///
+/// Properties:
+///
+/// * `hint`: The hint.
+/// * `number`: The number of the hint, starting from 1.
+/// * `remaining`: How many hints are left.
+#[derive(Debug, Serialize)]
+pub struct RevealedHint {
+    pub hint: ContentLevel,
+    pub number: usize,
+    pub remaining: usize,
+}
+
+/// It reveals the next hint of the question and records it against the attempt.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `attempts_db`: &State<AttemptsRepo> - the attempts
+/// * `user_db`: &State<UserRepo> - the users, used to check the token
+/// * `token`: the token of the user
+/// * `id`: the id of the attempt
+/// * `locales`: the locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The next hint, 404 if there are no hints left and 409 if the attempt is already answered or
+/// the hint was revealed by another request.
+#[put("/user/<token>/attempt/hint?<id>")]
+pub async fn reveal_hint(
+    pubdb: &State<PublicationsRepo>,
+    attempts_db: &State<AttemptsRepo>,
+    user_db: &State<UserRepo>,
+    token: &str,
+    id: &str,
+    locales: Locales,
+) -> Result<Json<RevealedHint>, Status> {
+    let attempt = user_attempt(attempts_db, user_db, token, id).await?;
+    if attempt.answer.is_some() {
+        return Err(Status::Conflict);
+    }
+    let mut question = load_question(pubdb, &attempt.test_id, attempt.seed.unwrap_or(0))
+        .await
+        .ok_or(Status::NotFound)?;
+    question.localize(&locales);
+    let index = attempt.hints_used as usize;
+    let hint = question.hints.get(index).cloned().ok_or(Status::NotFound)?;
+
+    // another hint or the answer may have been taken since the attempt was loaded
+    if !attempts_db
+        .reveal_hint(&attempt)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+    {
+        return Err(Status::Conflict);
+    }
+    Ok(Json(RevealedHint {
+        hint,
+        number: index + 1,
+        remaining: question.hints.len() - index - 1,
+    }))
+}
+
+/// `AttemptAnswer` is the answer of the user.
+#[derive(Debug, Deserialize)]
+pub struct AttemptAnswer {
+    pub answer: String,
+}
+
+/// `AttemptResult` is the graded answer.
+///
+/// Properties:
+///
+/// * `correct`: Whether the answer is correct.
+/// * `score`: The score from 0 to 1, discounted for revealed hints.
+/// * `answer`: The correct answer.
+/// * `explanation`: The worked explanation of the question.
+#[derive(Debug, Serialize)]
+pub struct AttemptResult {
+    pub correct: bool,
+    pub score: f64,
+    pub answer: String,
+    pub explanation: Vec<ContentLevel>,
+}
+
+/// It records what follows from a graded attempt. A correct answer marks the test as passed. The
+/// score updates the mastery of the user for the skills of the question and the ratings of the
+/// user and of the question, and schedules the next review of a choice or action test.
+///
+/// Arguments:
+///
+/// * `attempt`: &AttemptModel - the graded attempt
+/// * `question`: &GradedQuestion - the question of the attempt
+/// * `user_db`: &UserRepo - the users
+/// * `skdb`: &SkillsRepo - the mastery of the users
+/// * `rdb`: &RatingsRepo - the ratings of the users and of the questions
+/// * `rvdb`: &ReviewsRepo - the review schedule of the users
+pub async fn record_answer(
+    attempt: &AttemptModel,
+    question: &GradedQuestion,
+    user_db: &UserRepo,
+    skdb: &SkillsRepo,
+    rdb: &RatingsRepo,
+    rvdb: &ReviewsRepo,
+) -> Result<(), Status> {
+    let correct = attempt.correct.unwrap_or(false);
+    let score = attempt.score.unwrap_or(0.0);
+    if correct {
+        user_db
+            .add_test_to_user(attempt.user_id.clone(), attempt.test_id.clone())
+            .await;
+    }
+    skdb.record_attempt(&attempt.user_id, &question.skills, score, correct)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    rdb.record_attempt(
+        &attempt.user_id,
+        &attempt.test_id,
+        question.level,
+        &question.theme,
+        &question.theme_id,
+        score,
+    )
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    // a question generated from a template is new every time, there is nothing to review
+    if question.kind != QuestionKind::Template {
+        rvdb.record_attempt(
+            &attempt.user_id,
+            &attempt.test_id,
+            question.kind,
+            review_quality(correct, score),
+        )
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    }
+    Ok(())
+}
+
+/// It grades the answer of the attempt. A correct answer also marks the test as passed. Every
+/// attempt can be answered only once. The answer can be given in any locale of the question.
+/// The score also updates the mastery of the user for the skills of the question and the
+/// ratings of the user and of the question, see `record_rating`. The next review of a choice or
+/// action test is scheduled with the grade, see `GET /user/review/due`.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `attempts_db`: &State<AttemptsRepo> - the attempts
+/// * `skdb`: &State<SkillsRepo> - the mastery of the users
+/// * `rdb`: &State<RatingsRepo> - the ratings of the users and of the questions
+/// * `rvdb`: &State<ReviewsRepo> - the review schedule of the users
+/// * `user_db`: &State<UserRepo> - the users
+/// * `token`: the token of the user
+/// * `id`: the id of the attempt
+/// * `answer`: Json<AttemptAnswer> - the answer of the user
+/// * `locales`: the locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The graded answer with the explanation of the question, or 409 if the attempt is already
+/// answered or a hint was revealed while it was graded.
+#[put("/user/<token>/attempt/answer?<id>", data = "<answer>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn answer_attempt(
+    pubdb: &State<PublicationsRepo>,
+    attempts_db: &State<AttemptsRepo>,
+    skdb: &State<SkillsRepo>,
+    rdb: &State<RatingsRepo>,
+    rvdb: &State<ReviewsRepo>,
+    user_db: &State<UserRepo>,
+    token: &str,
+    id: &str,
+    answer: Json<AttemptAnswer>,
+    locales: Locales,
+) -> Result<Json<AttemptResult>, Status> {
+    let mut attempt = user_attempt(attempts_db, user_db, token, id).await?;
+    if attempt.answer.is_some() {
+        return Err(Status::Conflict);
+    }
+    let mut question = load_question(pubdb, &attempt.test_id, attempt.seed.unwrap_or(0))
+        .await
+        .ok_or(Status::NotFound)?;
+    let shown_answer = question.localize(&locales);
+
+    let answer = question.canonical_answer(&answer.into_inner().answer);
+    attempt.grade(&answer, &question.answer, question.hint_penalty);
+    let correct = attempt.correct.unwrap_or(false);
+    let score = attempt.score.unwrap_or(0.0);
+    // another answer or hint may have been taken since the attempt was loaded
+    if !attempts_db
+        .answer_attempt(&attempt)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+    {
+        return Err(Status::Conflict);
+    }
+    record_answer(&attempt, &question, user_db, skdb, rdb, rvdb).await?;
+
+    Ok(Json(AttemptResult {
+        correct,
+        score,
+        answer: shown_answer,
+        explanation: question.explanation,
+    }))
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+
+use mongodb::bson::{from_document, Document};
+use rocket::{
+    data::Capped,
+    http::{ContentType, Header, Status},
+    serde::json::Json,
+    State,
+};
+
+use crate::{
+    api::publications_api::ContentRepos,
+    models::{
+        bundle_model::{ConflictStrategy, CourseBundle, ImportOutcome, ImportReport},
+        cource_model::CourseModel,
+        publication_model::ContentKind,
+    },
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, media_repo::MediaRepo,
+        revisions_repo::RevisionsRepo, templates_repo::TemplatesRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+    utils::auth::AuthUser,
+};
+
+/// `BundleExport` is an exported course, as JSON or as a zip archive.
+#[derive(Responder)]
+pub enum BundleExport {
+    Json(Json<Box<CourseBundle>>),
+    Zip(BundleArchive),
+}
+
+/// `BundleArchive` is a course bundle packed by `CourseBundle::to_zip`, served as a download.
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct BundleArchive {
+    data: Vec<u8>,
+    disposition: Header<'static>,
+}
+
+/// It loads the working copy of a course, 404 if there is no such course.
+pub async fn load_course(
+    repos: &ContentRepos<'_>,
+    id: &str,
+) -> Result<CourseModel, (Status, String)> {
+    repos
+        .live_document(ContentKind::Course, id)
+        .await
+        .map_err(|status| (status, String::new()))?
+        .map(from_document)
+        .transpose()
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .ok_or((Status::NotFound, format!("unknown course `{}`", id)))
+}
+
+/// It loads the working copies of a course and of everything its levels point at.
+///
+/// Arguments:
+///
+/// * `repos`: &ContentRepos - the courses, infos, tests and templates
+/// * `mdb`: &MediaRepo - the uploaded media
+/// * `id`: &str - the id of the course
+///
+/// Returns:
+///
+/// The bundle, 404 if there is no such course and 422 with the content ids that point at nothing.
+pub async fn load_bundle(
+    repos: &ContentRepos<'_>,
+    mdb: &MediaRepo,
+    id: &str,
+) -> Result<CourseBundle, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    let mut bundle = CourseBundle::new(load_course(repos, id).await?);
+    let mut dangling = vec![];
+    'ids: for content_id in bundle.course.content_ids() {
+        for kind in [
+            ContentKind::Info,
+            ContentKind::Choice,
+            ContentKind::Action,
+            ContentKind::Template,
+        ] {
+            let document = repos
+                .live_document(kind, &content_id)
+                .await
+                .map_err(|status| (status, String::new()))?;
+            if let Some(document) = document {
+                match kind {
+                    ContentKind::Info => bundle.infos.push(from_document(document).map_err(error)?),
+                    ContentKind::Choice => bundle
+                        .choice_tests
+                        .push(from_document(document).map_err(error)?),
+                    ContentKind::Action => bundle
+                        .action_tests
+                        .push(from_document(document).map_err(error)?),
+                    ContentKind::Template => bundle
+                        .templates
+                        .push(from_document(document).map_err(error)?),
+                    ContentKind::Course => unreachable!(),
+                }
+                continue 'ids;
+            }
+        }
+        dangling.push(content_id);
+    }
+    if !dangling.is_empty() {
+        return Err((
+            Status::UnprocessableEntity,
+            format!("content that does not exist: {}", dangling.join(", ")),
+        ));
+    }
+    for media_id in bundle.media_ids() {
+        let media = mdb
+            .get_media(&media_id)
+            .await
+            .map_err(|_| (Status::InternalServerError, String::new()))?;
+        if let Some(media) = media {
+            bundle.media.push(media);
+        }
+    }
+    Ok(bundle)
+}
+
+/// It reads an uploaded bundle, a zip archive or JSON.
+fn parse_bundle(content_type: &ContentType, data: &[u8]) -> Result<CourseBundle, String> {
+    if *content_type == ContentType::ZIP || data.starts_with(b"PK\x03\x04") {
+        CourseBundle::from_zip(data)
+    } else {
+        serde_json::from_slice(data).map_err(|e| e.to_string())
+    }
+}
+
+// * Admin API routes
+
+/// It exports a course with its infos, choice tests, action tests, templates and the metadata of
+/// the media they use, so the course can be imported into another database.
+///
+/// Arguments:
+///
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the courses, infos, choice tests, action tests and
+///   templates
+/// * `mdb`: &State<MediaRepo> - the uploaded media
+/// * `id`: &str - the id of the course
+/// * `format`: `json` (default) or `zip`
+///
+/// Returns:
+///
+/// The bundle, 404 if there is no such course and 422 if the course points at content that does
+/// not exist.
+#[allow(clippy::too_many_arguments)]
+#[get("/admin/courses/<id>/export?<format>")]
+pub async fn export_cource_bundle(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    mdb: &State<MediaRepo>,
+    id: &str,
+    format: Option<&str>,
+) -> Result<BundleExport, (Status, String)> {
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    let bundle = load_bundle(&repos, mdb, id).await?;
+    match format.unwrap_or("json") {
+        "json" => Ok(BundleExport::Json(Json(Box::new(bundle)))),
+        "zip" => Ok(BundleExport::Zip(BundleArchive {
+            data: bundle
+                .to_zip()
+                .map_err(|e| (Status::InternalServerError, e))?,
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"course-{}.zip\"", id),
+            ),
+        })),
+        other => Err((
+            Status::BadRequest,
+            format!("unknown format `{}`, expected json or zip", other),
+        )),
+    }
+}
+
+/// It imports a course bundle. Documents keep their ids unless the id is taken, then `strategy`
+/// decides: `skip` keeps the existing document, `overwrite` replaces it (recorded as a revision)
+/// and `duplicate` creates a copy with a new id. The levels are rewritten to the ids of the
+/// imported content. Imported documents are drafts until they are approved.
+///
+/// Arguments:
+///
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the courses, infos, choice tests, action tests and
+///   templates
+/// * `mdb`: &State<MediaRepo> - the uploaded media, media the bundle uses is not imported
+/// * `rdb`: &State<RevisionsRepo> - the history of the overwritten documents
+/// * `author`: The user from the `Authorization` header, if any.
+/// * `content_type`: `application/zip` for an archive, JSON otherwise
+/// * `strategy`: `skip` (default), `overwrite` or `duplicate`
+/// * `dry_run`: report what would be imported without writing anything
+/// * `bundle`: the bundle, limited by the `bytes` limit of Rocket
+///
+/// Returns:
+///
+/// What was (or would be) imported, 400 if the bundle can not be read, 413 if it is too big and
+/// 422 with the problems of the bundle.
+#[allow(clippy::too_many_arguments)]
+#[post("/admin/courses/import?<strategy>&<dry_run>", data = "<bundle>")]
+pub async fn import_cource_bundle(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    mdb: &State<MediaRepo>,
+    rdb: &State<RevisionsRepo>,
+    author: Option<AuthUser>,
+    content_type: &ContentType,
+    strategy: Option<&str>,
+    dry_run: Option<bool>,
+    bundle: Capped<Vec<u8>>,
+) -> Result<Json<ImportReport>, (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let failed = |(status, message): (Status, String)| (status, Json(vec![message]));
+    if !bundle.is_complete() {
+        return Err((Status::PayloadTooLarge, Json(vec![])));
+    }
+    let strategy = match strategy {
+        Some(name) => ConflictStrategy::from_name(name).ok_or((
+            Status::BadRequest,
+            Json(vec![format!("unknown strategy `{}`", name)]),
+        ))?,
+        None => ConflictStrategy::default(),
+    };
+    let bundle =
+        parse_bundle(content_type, &bundle).map_err(|e| (Status::BadRequest, Json(vec![e])))?;
+    let mut errors = bundle.validate();
+    if errors.is_empty() {
+        let types: HashMap<String, String> = bundle
+            .documents()
+            .into_iter()
+            .filter(|(kind, _, _)| *kind != ContentKind::Course)
+            .filter_map(|(kind, id, _)| {
+                let type_ = if kind == ContentKind::Info {
+                    "info"
+                } else {
+                    "test"
+                };
+                id.map(|id| (id, type_.to_string()))
+            })
+            .collect();
+        let report = bundle.course.validate(&types);
+        if !report.is_valid() {
+            errors.extend(report.to_string().lines().map(|line| line.to_string()));
+        }
+    }
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    let mut existing: HashMap<(ContentKind, String), Document> = HashMap::new();
+    for (kind, id, _) in bundle.documents() {
+        let id = id.unwrap_or_default();
+        if let Some(document) = repos
+            .live_document(kind, &id)
+            .await
+            .map_err(|status| (status, Json(vec![])))?
+        {
+            existing.insert((kind, id), document);
+        }
+    }
+    let items = bundle.plan(&existing.keys().cloned().collect::<HashSet<_>>(), strategy);
+    let mut missing_media = vec![];
+    for media_id in bundle.media_ids() {
+        let media = mdb
+            .get_media(&media_id)
+            .await
+            .map_err(|_| (Status::InternalServerError, Json(vec![])))?;
+        if media.is_none() {
+            missing_media.push(media_id);
+        }
+    }
+    let dry_run = dry_run.unwrap_or(false);
+    let author = author.map(|user| user.id);
+    if !dry_run {
+        for (item, document) in bundle.remap(&items) {
+            match item.outcome {
+                ImportOutcome::Create => repos
+                    .create_document(item.kind, document)
+                    .await
+                    .map_err(failed)?,
+                ImportOutcome::Overwrite => {
+                    let before = &existing[&(item.kind, item.source_id.clone())];
+                    let mut after = document;
+                    after.remove("_id");
+                    repos
+                        .restore_document(item.kind, &item.target_id, after.clone())
+                        .await
+                        .map_err(failed)?;
+                    rdb.record(
+                        item.kind,
+                        &item.target_id,
+                        author.clone(),
+                        Some(before),
+                        &after,
+                    )
+                    .await
+                    .map_err(error)?;
+                }
+                ImportOutcome::Skip => {}
+            }
+        }
+    }
+    let course_id = items
+        .iter()
+        .find(|item| item.kind == ContentKind::Course)
+        .map(|item| item.target_id.clone())
+        .unwrap_or_default();
+    Ok(Json(ImportReport {
+        dry_run,
+        strategy,
+        course_id,
+        items,
+        missing_media,
+    }))
+}
+
+/// It clones a course into a new draft course with new level ids, e.g. so a teacher can adapt a
+/// course for a class without changing the original. The clone records its source in `origin`.
+///
+/// Arguments:
+///
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the courses, infos, choice tests, action tests and
+///   templates
+/// * `mdb`: &State<MediaRepo> - the uploaded media
+/// * `id`: &str - the id of the course to clone
+/// * `deep`: copy the infos, tests and templates of the course too, by default the clone shares
+///   them with the source
+/// * `title`: the title of the clone, by default the title of the source with ` (copy)`
+///
+/// Returns:
+///
+/// The clone, 404 if there is no such course and 422 if a deep clone points at content that does
+/// not exist.
+#[allow(clippy::too_many_arguments)]
+#[post("/admin/courses/<id>/clone?<deep>&<title>")]
+pub async fn clone_cource_admin(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    mdb: &State<MediaRepo>,
+    id: &str,
+    deep: Option<bool>,
+    title: Option<&str>,
+) -> Result<Json<CourseModel>, (Status, String)> {
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    let deep = deep.unwrap_or(false);
+    let mut bundle = if deep {
+        load_bundle(&repos, mdb, id).await?
+    } else {
+        CourseBundle::new(load_course(&repos, id).await?)
+    };
+    bundle.course.title = match title {
+        Some(title) => title.to_string(),
+        None => format!("{} (copy)", bundle.course.title),
+    };
+    let documents = bundle.fork(deep);
+    let clone: CourseModel = documents
+        .last()
+        .map(|(_, document)| from_document(document.clone()))
+        .transpose()
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .ok_or((Status::InternalServerError, String::new()))?;
+    for (item, document) in documents {
+        repos.create_document(item.kind, document).await?;
+    }
+    Ok(Json(clone))
+}
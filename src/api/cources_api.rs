@@ -1,12 +1,27 @@
 use crate::{
-    models::cource_model::*,
+    api::attempts_api::load_question,
+    models::{cource_model::*, media_model::Rendition, publication_model::ContentKind, user_model::{EnrollmentStatus, LearnerProgress}},
     repository::{
+        attempts_repo::AttemptsRepo,
         cource_repo::CourceRepo,
+        infos_repo::InfosRepo,
+        media_repo::MediaRepo,
+        publications_repo::PublicationsRepo,
+        revisions_repo::RevisionsRepo,
+        templates_repo::TemplatesRepo,
+        tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo,
         user_repo::UserRepo,
     },
-    utils::auth::authorize_token,
+    utils::{
+        auth::{authorize_token, AuthUser},
+        locale::{Localize, Locales},
+    },
 };
 
+use std::collections::{HashMap, HashSet};
+
+use mongodb::bson::oid::ObjectId;
 use rocket::{
     http::Status,
     serde::json::Json,
@@ -16,57 +31,305 @@ use rocket::{
 //? User routes
 
 
-/// It returns all the courses in the database.
+/// It returns all the published courses, as they were approved.
 /// 
 /// Arguments:
 /// 
-/// * `db`: &State<CourceRepo> - this is the database connection that we created in the main.rs file.
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the courses.
 /// * `udb`: &State<UserRepo>
 /// * `token`: The token of the user who is requesting the data.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`.
 /// 
 /// Returns:
 /// 
 /// A vector of CourseModel
 #[get("/user/<token>/get/cources/all")]
-pub async fn get_all_cources_user(db: &State<CourceRepo>, udb: &State<UserRepo>, token: &str) -> Result<Json<Vec<CourseModel>>, Status> {
+pub async fn get_all_cources_user(pubdb: &State<PublicationsRepo>, udb: &State<UserRepo>, token: &str, locales: Locales) -> Result<Json<Vec<CourseModel>>, Status> {
     if authorize_token(token.to_string(), udb).await.0 {
-        let cources = db.get_all().await;
+        let cources = pubdb.get_all_published::<CourseModel>(ContentKind::Course).await;
         match cources {
-            Some(cources) => Ok(Json(cources)),
-            None => Err(Status::NotFound),
+            Ok(mut cources) => {
+                for cource in cources.iter_mut() {
+                    cource.localize(&locales);
+                }
+                Ok(Json(cources))
+            },
+            Err(_) => Err(Status::InternalServerError),
         }
     } else {
         Err(Status::Unauthorized)
     }
 }
 
-/// It gets a cource by id.
+/// It gets a published cource by id.
 /// 
 /// Arguments:
 /// 
-/// * `db`: &State<CourceRepo> - this is the database connection.
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the courses.
 /// * `udb`: &State<UserRepo>
 /// * `token`: The token of the user who is requesting the course.
 /// * `id`: &str - The id of the course to get
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`.
 /// 
 /// Returns:
 /// 
 /// A JSON object of the course with the given ID.
 #[get("/user/<token>/get/cource?<id>")]
-pub async fn get_cource_user(db: &State<CourceRepo>, udb: &State<UserRepo>, token: &str, id: &str) -> Result<Json<CourseModel>, Status> {
+pub async fn get_cource_user(pubdb: &State<PublicationsRepo>, udb: &State<UserRepo>, token: &str, id: &str, locales: Locales) -> Result<Json<CourseModel>, Status> {
     if authorize_token(token.to_string(), udb).await.0 {
-        let cource = db.get(id).await;
+        let cource = pubdb.get_published::<CourseModel>(ContentKind::Course, id).await;
         match cource {
-            Some(cource) => Ok(Json(cource)),
-            None => Err(Status::NotFound),
+            Ok(Some(mut cource)) => {
+                cource.localize(&locales);
+                Ok(Json(cource))
+            },
+            Ok(None) => Err(Status::NotFound),
+            Err(_) => Err(Status::InternalServerError),
         }
     } else {
         Err(Status::Unauthorized)
     }
 }
 
+/// It collects what the server knows about the progress of a user in a course. The levels the
+/// placement tests of the user unlock are the test levels whose tests are all at most as hard as
+/// the placed level of their theme.
+///
+/// Arguments:
+///
+/// * `user_id`: &str - the id of the user
+/// * `cource`: &CourseModel - the published course
+/// * `udb`: &UserRepo - the users
+/// * `adb`: &AttemptsRepo - the graded attempts
+/// * `pubdb`: &PublicationsRepo - the published tests, for the placement tests
+///
+/// Returns:
+///
+/// A LearnerProgress, or 404 if the user does not exist.
+pub async fn learner_progress(
+    user_id: &str,
+    cource: &CourseModel,
+    udb: &UserRepo,
+    adb: &AttemptsRepo,
+    pubdb: &PublicationsRepo,
+) -> Result<LearnerProgress, Status> {
+    let error = |_| Status::InternalServerError;
+    let user = udb
+        .get_user_by_id(&user_id.to_string())
+        .await
+        .map_err(error)?
+        .ok_or(Status::NotFound)?;
+    let attempts = adb
+        .get_user_attempts(user_id)
+        .await
+        .map_err(error)?;
+    let cource_id = cource.id.map(|id| id.to_hex()).unwrap_or_default();
+    let mut progress = LearnerProgress::new(&user.progress, &attempts, &cource_id);
+    if !user.progress.placements.is_empty() {
+        let placed = |theme: &str, theme_id: &Option<String>, level: i32| {
+            user.progress
+                .placed_level(theme, theme_id)
+                .is_some_and(|placed| level <= placed)
+        };
+        let mut placed_out: HashSet<String> = HashSet::new();
+        for id in cource.content_ids() {
+            if let Some(question) = load_question(pubdb, &id, 0).await {
+                if placed(&question.theme, &question.theme_id, question.level) {
+                    placed_out.insert(id);
+                }
+            }
+        }
+        progress.unlocked = cource.placement_unlocked(&placed_out);
+    }
+    Ok(progress)
+}
+
+/// It returns the layers of a course with every level marked as locked, available, in progress
+/// or completed for the authenticated user.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the courses.
+/// * `udb`: &State<UserRepo>
+/// * `adb`: &State<AttemptsRepo> - the graded attempts of the user.
+/// * `user`: The user from the `Authorization` header.
+/// * `id`: &str - The id of the course.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`.
+///
+/// Returns:
+///
+/// A CourseMap
+#[get("/user/courses/<id>/map")]
+pub async fn get_cource_map_user(pubdb: &State<PublicationsRepo>, udb: &State<UserRepo>, adb: &State<AttemptsRepo>, user: AuthUser, id: &str, locales: Locales) -> Result<Json<CourseMap>, Status> {
+    let mut cource = pubdb
+        .get_published::<CourseModel>(ContentKind::Course, id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    cource.localize(&locales);
+    let progress = learner_progress(&user.id, &cource, udb, adb, pubdb).await?;
+    Ok(Json(cource.map(&progress.completed, &progress.started, &progress.unlocked)))
+}
+
+/// It reports how far the authenticated user is through each course they joined. Courses whose
+/// levels are all passed are marked as completed. Courses that are no longer published are left out.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the courses.
+/// * `udb`: &State<UserRepo>
+/// * `adb`: &State<AttemptsRepo> - the graded attempts of the user.
+/// * `user`: The user from the `Authorization` header.
+///
+/// Returns:
+///
+/// A vector of CourseProgress, in the order the courses were joined.
+#[get("/user/courses/progress")]
+pub async fn get_cources_progress_user(pubdb: &State<PublicationsRepo>, udb: &State<UserRepo>, adb: &State<AttemptsRepo>, user: AuthUser) -> Result<Json<Vec<CourseProgress>>, Status> {
+    let progress = udb
+        .get_user_by_id(&user.id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?
+        .progress;
+    let attempts = adb
+        .get_user_attempts(&user.id)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let mut report: Vec<CourseProgress> = vec![];
+    for enrollment in &progress.enrollments {
+        let cource = pubdb
+            .get_published::<CourseModel>(ContentKind::Course, &enrollment.course_id)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if let Some(cource) = cource {
+            let learner = LearnerProgress::new(&progress, &attempts, &enrollment.course_id);
+            let mut cource_progress = cource.progress(&learner);
+            cource_progress.enrolled_at = Some(enrollment.enrolled_at);
+            cource_progress.completed_at = enrollment.completed_at;
+            if cource_progress.completed && enrollment.status != EnrollmentStatus::Completed {
+                udb.complete_cource_for_user(user.id.clone(), enrollment.course_id.clone()).await;
+                cource_progress.completed_at = Some(chrono::Utc::now().timestamp());
+            }
+            report.push(cource_progress);
+        }
+    }
+    Ok(Json(report))
+}
+
 //? Admin routes
 
+/// It generates the mini images of a level from its uploaded image, if it has one. Both
+/// renditions are generated right away, so a broken image is reported when the course is saved.
+///
+/// Arguments:
+///
+/// * `level`: &mut Level - the level to update
+/// * `mdb`: &MediaRepo - the media storage
+///
+/// Returns:
+///
+/// Nothing, or 422 with the reason if the media does not exist or is not an image.
+async fn resolve_level_media(level: &mut Level, mdb: &MediaRepo) -> Result<(), (Status, String)> {
+    let media_id = match &level.media_id {
+        Some(media_id) => media_id,
+        None => return Ok(()),
+    };
+    let unprocessable = |reason: &str| {
+        (Status::UnprocessableEntity, format!("level `{}`: {}", level.title, reason))
+    };
+    let media = mdb
+        .get_media(media_id)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .ok_or_else(|| unprocessable("unknown media"))?;
+    for rendition in [Rendition::Thumbnail, Rendition::Success] {
+        mdb.get_rendition(&media, rendition)
+            .await
+            .map_err(|e| unprocessable(&e.to_string()))?
+            .ok_or_else(|| unprocessable("the media has no content"))?;
+    }
+    level.mini_image = media.rendition_url(Rendition::Thumbnail);
+    level.mini_image_success = media.rendition_url(Rendition::Success);
+    Ok(())
+}
+
+/// It generates the mini images of all the levels of a course that have an uploaded image.
+async fn resolve_cource_media(cource: &mut CourseModel, mdb: &MediaRepo) -> Result<(), (Status, String)> {
+    for layer in cource.levels.values_mut() {
+        for level in layer.iter_mut() {
+            resolve_level_media(level, mdb).await?;
+        }
+    }
+    Ok(())
+}
+
+/// It looks up the content the levels of a course point at. Test levels can point at choice
+/// tests, action tests and question templates.
+///
+/// Arguments:
+///
+/// * `cource`: &CourseModel - the course to check
+/// * `idb`: &InfosRepo - the infos
+/// * `tdb`: &TestsRepo - the choice tests
+/// * `adb`: &TActionRepo - the action tests
+/// * `pdb`: &TemplatesRepo - the question templates
+///
+/// Returns:
+///
+/// The type (`info` or `test`) of every content id that exists.
+async fn content_types(
+    cource: &CourseModel,
+    idb: &InfosRepo,
+    tdb: &TestsRepo,
+    adb: &TActionRepo,
+    pdb: &TemplatesRepo,
+) -> Result<HashMap<String, String>, Status> {
+    let mut types = HashMap::new();
+    for id in cource.content_ids() {
+        // the repositories panic on ids that are not object ids, such ids are dangling anyway
+        if ObjectId::parse_str(&id).is_err() {
+            continue;
+        }
+        let error = |_| Status::InternalServerError;
+        let type_ = if idb.get_info(&id).await.map_err(error)?.is_some() {
+            "info"
+        } else if tdb.get_test_by_id(&id).await.map_err(error)?.is_some()
+            || adb.get_test_by_id(&id).await.map_err(error)?.is_some()
+            || pdb.get_template_by_id(&id).await.map_err(error)?.is_some()
+        {
+            "test"
+        } else {
+            continue;
+        };
+        types.insert(id, type_.to_string());
+    }
+    Ok(types)
+}
+
+/// It validates the level graph of a course before it is saved.
+///
+/// Returns:
+///
+/// Nothing, or 422 with the issues, one per line.
+async fn check_cource(
+    cource: &CourseModel,
+    idb: &InfosRepo,
+    tdb: &TestsRepo,
+    adb: &TActionRepo,
+    pdb: &TemplatesRepo,
+) -> Result<(), (Status, String)> {
+    let types = content_types(cource, idb, tdb, adb, pdb)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let report = cource.validate(&types);
+    if report.is_valid() {
+        Ok(())
+    } else {
+        Err((Status::UnprocessableEntity, report.to_string()))
+    }
+}
+
 /// It's a `GET` request that takes a `CourceRepo` from the `State` and returns a `Json` of a `Vec` of
 /// `CourseModel`s
 /// 
@@ -111,32 +374,75 @@ pub async fn get_cource_admin(db: &State<CourceRepo>, id: &str) -> Result<Json<C
 /// Arguments:
 /// 
 /// * `db`: &State<CourceRepo> - This is the database connection.
+/// * `mdb`: &State<MediaRepo> - The media storage, levels with a `media_id` get their mini images from it.
+/// * `idb`, `tdb`, `adb`, `pdb`: The infos, choice tests, action tests and question templates the levels point at.
 /// * `cource`: Json<CourseModel>
 /// 
 /// Returns:
 /// 
-/// A status code of 200
+/// A status code of 200, or 422 if the levels are not valid or the image of a level can not be used.
 #[post("/admin/add/cource", data = "<cource>")]
-pub async fn add_cource_admin(db: &State<CourceRepo>, cource: Json<CourseModel>) -> Result<Status, Status> {
-    db.create(cource.into_inner()).await;
+pub async fn add_cource_admin(db: &State<CourceRepo>, mdb: &State<MediaRepo>, idb: &State<InfosRepo>, tdb: &State<TestsRepo>, adb: &State<TActionRepo>, pdb: &State<TemplatesRepo>, cource: Json<CourseModel>) -> Result<Status, (Status, String)> {
+    let mut cource = cource.into_inner();
+    check_cource(&cource, idb, tdb, adb, pdb).await?;
+    resolve_cource_media(&mut cource, mdb).await?;
+    db.create(cource).await;
     Ok(Status::Ok)
 }
 
 /// Update a cource by id
 /// Arguments:
 /// * `db`: &State<CourceRepo> - This is the database connection.
+/// * `mdb`: &State<MediaRepo> - The media storage, levels with a `media_id` get their mini images from it.
+/// * `idb`, `tdb`, `adb`, `pdb`: The infos, choice tests, action tests and question templates the levels point at.
+/// * `rdb`: &State<RevisionsRepo> - The history, every update is recorded as a revision.
+/// * `author`: The user from the `Authorization` header, if any.
 /// * `id`: &str - The id of the course to update.
 /// * `cource`: Json<CourseModel>
 /// 
 /// Returns: 
-/// A status code of 200
+/// A status code of 200, or 422 if the levels are not valid or the image of a level can not be used.
+#[allow(clippy::too_many_arguments)]
 #[put("/admin/update/cource?<id>", data = "<cource>")]
-pub async fn update_cource_admin(db: &State<CourceRepo>, id: &str, cource: Json<CourseModel>) -> Result<Status, Status> {
-    let cource = db.update(id, cource.into_inner()).await;
-    match cource {
-        Some(_cource) => Ok(Status::Ok),
-        None => Err(Status::NotFound),
+pub async fn update_cource_admin(db: &State<CourceRepo>, mdb: &State<MediaRepo>, idb: &State<InfosRepo>, tdb: &State<TestsRepo>, adb: &State<TActionRepo>, pdb: &State<TemplatesRepo>, rdb: &State<RevisionsRepo>, author: Option<AuthUser>, id: &str, cource: Json<CourseModel>) -> Result<Status, (Status, String)> {
+    let mut cource = cource.into_inner();
+    check_cource(&cource, idb, tdb, adb, pdb).await?;
+    resolve_cource_media(&mut cource, mdb).await?;
+    // the origin of a clone is kept by the server
+    cource.origin = db.get(id).await.and_then(|saved| saved.origin);
+    let before = db.update(id, cource.clone()).await;
+    match before {
+        Some(before) => {
+            rdb.record(ContentKind::Course, id, author.map(|user| user.id), Some(&before), &cource)
+                .await
+                .map_err(|_| (Status::InternalServerError, String::new()))?;
+            Ok(Status::Ok)
+        },
+        None => Err((Status::NotFound, String::new())),
+    }
+}
+
+/// It checks the level graph of a saved course: content ids that point at nothing, levels of the
+/// wrong type, `n_of_tests` that does not match the ids, empty layers, gaps in the layer numbers
+/// and level ids that are used twice.
+///
+/// Arguments:
+///
+/// * `db`: &State<CourceRepo> - This is the database connection.
+/// * `idb`, `tdb`, `adb`, `pdb`: The infos, choice tests, action tests and question templates the levels point at.
+/// * `id`: &str - The id of the course to check.
+///
+/// Returns:
+///
+/// A CourseReport, empty if the course is valid.
+#[get("/admin/courses/<id>/validate")]
+pub async fn validate_cource_admin(db: &State<CourceRepo>, idb: &State<InfosRepo>, tdb: &State<TestsRepo>, adb: &State<TActionRepo>, pdb: &State<TemplatesRepo>, id: &str) -> Result<Json<CourseReport>, Status> {
+    if ObjectId::parse_str(id).is_err() {
+        return Err(Status::NotFound);
     }
+    let cource = db.get(id).await.ok_or(Status::NotFound)?;
+    let types = content_types(&cource, idb, tdb, adb, pdb).await?;
+    Ok(Json(cource.validate(&types)))
 }
 
 /// Delete a cource by id
@@ -144,19 +450,26 @@ pub async fn update_cource_admin(db: &State<CourceRepo>, id: &str, cource: Json<
 /// Arguments:
 /// 
 /// * `db`: &State<CourceRepo> - This is the database connection.
+/// * `pubdb`: &State<PublicationsRepo> - The publication of the course is removed with it.
 /// * `id`: &str - The id of the course to delete.
 /// 
 /// Returns:
 /// 
 /// A status code
 #[delete("/admin/delete/cource?<id>")]
-pub async fn delete_cource_admin(db: &State<CourceRepo>, id: &str) -> Result<Status, Status> {
+pub async fn delete_cource_admin(db: &State<CourceRepo>, pubdb: &State<PublicationsRepo>, id: &str) -> Result<Status, Status> {
+    pubdb
+        .delete_publication(ContentKind::Course, id)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
     db.delete(id).await;
     Ok(Status::Ok)
 }
 
 #[post("/admin/add/cource/<cource_id>/level?<layer_number>", data="<level>")]
-pub async fn add_level_admin(db: &State<CourceRepo>, cource_id: &str, level: Json<Level>, layer_number: i32) -> Result<Status, Status> {
-    db.add_level(cource_id, level.into_inner(), layer_number).await;
+pub async fn add_level_admin(db: &State<CourceRepo>, mdb: &State<MediaRepo>, cource_id: &str, level: Json<Level>, layer_number: i32) -> Result<Status, (Status, String)> {
+    let mut level = level.into_inner();
+    resolve_level_media(&mut level, mdb).await?;
+    db.add_level(cource_id, level, layer_number).await;
     Ok(Status::Ok)
 }
\ No newline at end of file
@@ -1,33 +1,91 @@
 use crate::{
-    models::info_model::*,
-    repository::{infos_repo::InfosRepo, user_repo::UserRepo}, utils::auth::authorize_token,
+    api::{skills_api::check_skills, themes_api::resolve_theme},
+    models::{info_model::*, publication_model::ContentKind},
+    repository::{
+        infos_repo::{ContentMigrationReport, InfosRepo},
+        publications_repo::PublicationsRepo,
+        revisions_repo::RevisionsRepo,
+        skills_repo::SkillsRepo,
+        themes_repo::ThemesRepo,
+        user_repo::UserRepo,
+    },
+    utils::{
+        auth::{authorize_token, AuthUser},
+        locale::{Localize, Locales},
+        render::{render_info, RenderCache, RenderFormat, Rendered, RenderedInfo},
+    },
 };
 use rocket::{
     http::Status, serde::json::Json, State
 };
 
+/// It validates an info and checks that the info and all its translations can be rendered.
+///
+/// Arguments:
+///
+/// * `info`: &InfoModel - the info to check
+///
+/// Returns:
+///
+/// A list of problems, empty if the info can be saved.
+fn check_info(info: &InfoModel) -> Vec<String> {
+    let errors = info.validate();
+    if !errors.is_empty() {
+        return errors;
+    }
+    if let Err(errors) = render_info(info) {
+        return errors;
+    }
+    let mut errors = vec![];
+    let mut locales: Vec<&String> = info.translations.keys().collect();
+    locales.sort();
+    for locale in locales {
+        let mut translated = info.clone();
+        translated.localize(&Locales(vec![locale.to_lowercase()]));
+        if let Err(e) = render_info(&translated) {
+            errors.extend(e.into_iter().map(|e| format!("translation `{}`, {}", locale, e)));
+        }
+    }
+    errors
+}
+
 // User routes
-/// It takes a token and an id, checks if the token is valid, and if it is, it returns the info with the
-/// given id
+/// It takes a token and an id, checks if the token is valid, and if it is, it returns the published
+/// info with the given id
 /// 
 /// Arguments:
 /// 
-/// * `db`: &State<InfosRepo> - This is the database connection that we created in the main.rs file.
+/// * `pubdb`: &State<PublicationsRepo> - The published snapshots, learners never see drafts.
 /// * `user_db`: &State<UserRepo>
+/// * `cache`: &State<RenderCache<RenderedInfo>> - rendered content of the infos
 /// * `token`: The token of the user who is requesting the info.
 /// * `id`: The id of the info you want to get
+/// * `render`: If `html`, the content blocks are also returned rendered into HTML with MathML.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`.
 /// 
 /// Returns:
 /// 
 /// A JSON object containing the info with the given id.
-#[get("/user/<token>/get/info?<id>")]
-pub async fn get_info_user(db: &State<InfosRepo>, user_db: &State<UserRepo>, token: &str, id: &str) -> Result<Json<InfoModel>, Status> {
+#[allow(clippy::too_many_arguments)]
+#[get("/user/<token>/get/info?<id>&<render>")]
+pub async fn get_info_user(pubdb: &State<PublicationsRepo>, user_db: &State<UserRepo>, cache: &State<RenderCache<RenderedInfo>>, token: &str, id: &str, render: Option<RenderFormat>, locales: Locales) -> Result<Json<Rendered<InfoModel, RenderedInfo>>, Status> {
     if authorize_token(token.to_string(), user_db).await.0 {
-        let info = db.get_info(&id.to_string()).await;
+        let info = pubdb.get_published::<InfoModel>(ContentKind::Info, id).await;
         match info {
             Ok(info) => {
                 match info {
-                    Some(info) => Ok(Json(info)),
+                    Some(mut info) => {
+                        let locale = info.localize(&locales);
+                        let rendered = match render {
+                            Some(RenderFormat::Html) => Some(
+                                cache
+                                    .get_or_render(&format!("{}@{}", id, locale), &info, render_info)
+                                    .map_err(|_| Status::InternalServerError)?,
+                            ),
+                            None => None,
+                        };
+                        Ok(Json(Rendered { doc: info, rendered }))
+                    },
                     None => Err(Status::NotFound)
                 }
             },
@@ -93,18 +151,26 @@ pub async fn get_all_infos(db: &State<InfosRepo>) -> Result<Json<Vec<InfoModel>>
 /// 
 /// * `db`: &State<InfosRepo> - this is the database connection. It's a reference to the database
 /// connection that we created in the main function.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
 /// * `info`: Json<InfoModel>
 /// 
 /// Returns:
 /// 
-/// A Status
+/// A Status, or 422 with the list of problems if the content is invalid, can not be rendered or has an unknown theme or skill
 #[post("/admin/create/info", data="<info>")]
-pub async fn create_info(db: &State<InfosRepo>, info: Json<InfoModel>) -> Status {
-    let info = info.into_inner();
+pub async fn create_info(db: &State<InfosRepo>, thdb: &State<ThemesRepo>, skdb: &State<SkillsRepo>, info: Json<InfoModel>) -> Result<Status, (Status, Json<Vec<String>>)> {
+    let mut info = info.into_inner();
+    let errors = check_info(&info);
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+    resolve_theme(thdb, &mut info.theme, &info.theme_id).await?;
+    check_skills(skdb, &info.skills).await?;
     let create_result = db.create_info(info).await;
     match create_result {
-        Ok(_) => Status::Ok,
-        Err(_) => Status::InternalServerError
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, Json(vec![])))
     }
 }
 
@@ -113,14 +179,18 @@ pub async fn create_info(db: &State<InfosRepo>, info: Json<InfoModel>) -> Status
 /// Arguments:
 /// 
 /// * `db`: &State<InfosRepo> - this is the database connection that we created in the main.rs file.
+/// * `pubdb`: &State<PublicationsRepo> - the publication of the info is removed with it.
 /// * `id`: &str - The id of the info to delete.
 /// 
 /// Returns:
 /// 
 /// A Status
 #[delete("/admin/del/info?<id>")]
-pub async fn delete_info(db: &State<InfosRepo>, id: &str) -> Status {
+pub async fn delete_info(db: &State<InfosRepo>, pubdb: &State<PublicationsRepo>, id: &str) -> Status {
     let delete_result = db.delete_info(&id.to_string()).await;
+    if pubdb.delete_publication(ContentKind::Info, id).await.is_err() {
+        return Status::InternalServerError;
+    }
     match delete_result {
         Ok(_) => Status::Ok,
         Err(_) => Status::InternalServerError
@@ -132,18 +202,56 @@ pub async fn delete_info(db: &State<InfosRepo>, id: &str) -> Status {
 /// Arguments:
 /// 
 /// * `db`: &State<InfosRepo> - this is the database connection that we created in the main.rs file.
+/// * `rdb`: &State<RevisionsRepo> - the history, every update is recorded as a revision.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
+/// * `author`: The user from the `Authorization` header, if any.
 /// * `id`: The id of the info to update
 /// * `info`: Json<InfoModel> - This is the model that we're going to be updating.
 /// 
 /// Returns:
 /// 
-/// A Status
+/// A Status, 404 if there is no such info, or 422 with the list of problems if the content is invalid, can not be rendered or has an unknown theme or skill
 #[put("/admin/update/info?<id>", data="<info>")]
-pub async fn update_info(db: &State<InfosRepo>, id: &str, info: Json<InfoModel>) -> Status {
-    let info = info.into_inner();
-    let update_result = db.update_info(&id.to_string(), info).await;
-    match update_result {
-        Ok(_) => Status::Ok,
-        Err(_) => Status::InternalServerError
+pub async fn update_info(db: &State<InfosRepo>, rdb: &State<RevisionsRepo>, thdb: &State<ThemesRepo>, skdb: &State<SkillsRepo>, author: Option<AuthUser>, id: &str, info: Json<InfoModel>) -> Result<Status, (Status, Json<Vec<String>>)> {
+    let mut info = info.into_inner();
+    let errors = check_info(&info);
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+    resolve_theme(thdb, &mut info.theme, &info.theme_id).await?;
+    check_skills(skdb, &info.skills).await?;
+    let before = match db.get_info(&id.to_string()).await {
+        Ok(Some(before)) => before,
+        Ok(None) => return Err((Status::NotFound, Json(vec![]))),
+        Err(_) => return Err((Status::InternalServerError, Json(vec![]))),
+    };
+    let update_result = db.update_info(&id.to_string(), info.clone()).await;
+    if update_result.is_err() {
+        return Err((Status::InternalServerError, Json(vec![])));
+    }
+    match rdb.record(ContentKind::Info, id, author.map(|user| user.id), Some(&before), &info).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, Json(vec![])))
+    }
+}
+
+/// It converts the content blocks of all infos stored in the old free-form shape into typed blocks.
+/// The migration also runs when the server starts, this route is for infos restored from an old
+/// backup.
+/// 
+/// Arguments:
+/// 
+/// * `db`: &State<InfosRepo> - this is the database connection that we created in the main.rs file.
+/// 
+/// Returns:
+/// 
+/// A report with the migrated infos, the content types that were not recognized and the blocks
+/// that must be fixed before their info can be saved again
+#[post("/admin/migrate/infos/content")]
+pub async fn migrate_infos_content(db: &State<InfosRepo>) -> Result<Json<ContentMigrationReport>, Status> {
+    match db.migrate_content_levels().await {
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(Status::InternalServerError)
     }
 }
\ No newline at end of file
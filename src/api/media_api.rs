@@ -0,0 +1,257 @@
+use rocket::{
+    data::Capped,
+    form::Form,
+    fs::TempFile,
+    http::{ContentType, Header, Status},
+    request::FromParam,
+    response::{self, Responder},
+    serde::json::Json,
+    tokio::fs,
+    Request, Response, State,
+};
+use serde::Serialize;
+use std::io::Cursor;
+
+use crate::{
+    models::media_model::{validate_upload, MediaModel, Rendition},
+    repository::media_repo::MediaRepo,
+};
+
+/// This is a trait that allows us to convert a string into a Rendition enum.
+impl<'r> FromParam<'r> for Rendition {
+    type Error = &'r str;
+
+    fn from_param(param: &'r str) -> Result<Self, Self::Error> {
+        match param {
+            "thumbnail" => Ok(Rendition::Thumbnail),
+            "success" => Ok(Rendition::Success),
+            _ => Err("Invalid rendition"),
+        }
+    }
+}
+
+/// `MediaUpload` is the multipart form of an upload, the image is sent in the `file` field.
+#[derive(FromForm)]
+pub struct MediaUpload<'r> {
+    pub file: Capped<TempFile<'r>>,
+}
+
+/// `UploadedMedia` is the response to an upload.
+///
+/// Properties:
+///
+/// * `id`: The id of the media.
+/// * `url`: The url the media is served from, to be used in image content blocks.
+/// * `content_type`: The detected content type.
+/// * `size`: The size in bytes.
+#[derive(Debug, Serialize)]
+pub struct UploadedMedia {
+    pub id: String,
+    pub url: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+impl From<MediaModel> for UploadedMedia {
+    fn from(media: MediaModel) -> Self {
+        UploadedMedia {
+            id: media.id.map(|id| id.to_hex()).unwrap_or_default(),
+            url: media.url(),
+            content_type: media.content_type,
+            size: media.size,
+        }
+    }
+}
+
+// * Admin API routes
+
+/// It uploads an image. The type is detected from the content of the file and only images are
+/// accepted, the size is limited by `MEDIA_MAX_BYTES` and by the `file` limit of Rocket.
+///
+/// Arguments:
+///
+/// * `db`: &State<MediaRepo> - the media storage
+/// * `upload`: Form<MediaUpload<'_>> - the multipart form with the file
+///
+/// Returns:
+///
+/// The id and the url of the media, 413 if the file is too big and 422 if it is not a supported
+/// image.
+#[post("/admin/upload/media", data = "<upload>")]
+pub async fn upload_media(
+    db: &State<MediaRepo>,
+    upload: Form<MediaUpload<'_>>,
+) -> Result<Json<UploadedMedia>, (Status, String)> {
+    let file = &upload.file;
+    if !file.is_complete() || file.len() > db.max_bytes as u64 {
+        return Err((
+            Status::PayloadTooLarge,
+            format!(
+                "the file is too big, at most {} bytes are allowed",
+                db.max_bytes
+            ),
+        ));
+    }
+    let path = file
+        .path()
+        .ok_or((Status::UnprocessableEntity, "expected a file".to_string()))?;
+    let data = fs::read(path)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?;
+    let content_type =
+        validate_upload(&data, db.max_bytes).map_err(|e| (Status::UnprocessableEntity, e))?;
+
+    let media = db
+        .create_media(
+            &data,
+            content_type,
+            file.name().map(|name| name.to_string()),
+        )
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?;
+    Ok(Json(media.into()))
+}
+
+/// It gets the metadata of all the uploaded media.
+///
+/// Arguments:
+///
+/// * `db`: &State<MediaRepo> - the media storage
+///
+/// Returns:
+///
+/// A JSON array of all media.
+#[get("/admin/get/media/all")]
+pub async fn get_all_media(db: &State<MediaRepo>) -> Result<Json<Vec<MediaModel>>, Status> {
+    match db.get_all_media().await {
+        Ok(media) => Ok(Json(media)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It deletes a media and its content.
+///
+/// Arguments:
+///
+/// * `db`: &State<MediaRepo> - the media storage
+/// * `id`: &str - the id of the media
+///
+/// Returns:
+///
+/// Status
+#[delete("/admin/delete/media?<id>")]
+pub async fn delete_media(db: &State<MediaRepo>, id: &str) -> Result<Status, Status> {
+    match db.delete_media(id).await {
+        Ok(true) => Ok(Status::Ok),
+        Ok(false) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// * Public routes
+
+/// `MediaFile` is the content of a media. The content of a media never changes, so it can be
+/// cached forever; the ETag is the hash of the content and a matching `If-None-Match` gets 304.
+pub struct MediaFile {
+    pub content_type: String,
+    pub etag: String,
+    pub data: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for MediaFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let etag = format!("\"{}\"", self.etag);
+        let not_modified = request
+            .headers()
+            .get("If-None-Match")
+            .flat_map(|value| value.split(','))
+            .any(|tag| tag.trim() == etag || tag.trim() == "*");
+
+        let mut response = Response::build();
+        response
+            .header(Header::new("ETag", etag))
+            .header(Header::new(
+                "Cache-Control",
+                "public, max-age=31536000, immutable",
+            ));
+        if not_modified {
+            response.status(Status::NotModified);
+        } else {
+            response
+                .header(
+                    ContentType::parse_flexible(&self.content_type).unwrap_or(ContentType::Binary),
+                )
+                .sized_body(self.data.len(), Cursor::new(self.data));
+        }
+        response.ok()
+    }
+}
+
+/// It serves the content of a media. The route is public so that the url can be used directly in
+/// `<img>` tags.
+///
+/// Arguments:
+///
+/// * `db`: &State<MediaRepo> - the media storage
+/// * `id`: &str - the id of the media
+///
+/// Returns:
+///
+/// The content with its content type and caching headers.
+#[get("/media/<id>")]
+pub async fn get_media(db: &State<MediaRepo>, id: &str) -> Result<MediaFile, Status> {
+    let media = db
+        .get_media(id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    let data = db
+        .read_media(&media)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    Ok(MediaFile {
+        content_type: media.content_type,
+        etag: media.storage_key,
+        data,
+    })
+}
+
+/// It serves a rendition of a media, e.g. the thumbnail of a level image. The rendition is
+/// generated on the first request and stored next to the media.
+///
+/// Arguments:
+///
+/// * `db`: &State<MediaRepo> - the media storage
+/// * `id`: &str - the id of the media
+/// * `rendition`: Rendition - `thumbnail` or `success`
+///
+/// Returns:
+///
+/// The PNG of the rendition with caching headers, 422 if the media is not an image that can be
+/// decoded.
+#[get("/media/<id>/<rendition>")]
+pub async fn get_media_rendition(
+    db: &State<MediaRepo>,
+    id: &str,
+    rendition: Rendition,
+) -> Result<MediaFile, Status> {
+    let media = db
+        .get_media(id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    let data = db
+        .get_rendition(&media, rendition)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => Status::UnprocessableEntity,
+            _ => Status::InternalServerError,
+        })?
+        .ok_or(Status::NotFound)?;
+    Ok(MediaFile {
+        content_type: "image/png".to_string(),
+        etag: media.rendition_key(rendition),
+        data,
+    })
+}
@@ -2,4 +2,19 @@ pub mod user_api;
 pub mod auth_api;
 pub mod tests_api;
 pub mod infos_api;
-pub mod cources_api;
\ No newline at end of file
+pub mod cources_api;
+pub mod templates_api;
+pub mod attempts_api;
+pub mod media_api;
+pub mod translations_api;
+pub mod publications_api;
+pub mod revisions_api;
+pub mod bundles_api;
+pub mod themes_api;
+pub mod skills_api;
+pub mod question_import_api;
+pub mod qti_api;
+pub mod scorm_api;
+pub mod reviews_api;
+pub mod placement_api;
+pub mod quizzes_api;
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::{
+    api::{
+        attempts_api::{load_question, AttemptAnswer},
+        tests_api::{Candidate, TestRes},
+        themes_api::{theme_filter, ThemeFilter},
+    },
+    models::{
+        attempt_model::QuestionKind,
+        placement_model::{PlacementModel, PlacementStep, PLACEMENT_QUESTIONS},
+        publication_model::ContentKind,
+        rating_model::{pick_adaptive, question_rating, QuestionRatingModel},
+        template_model::QuestionTemplate,
+        tests_model::{TestModel, TestModelWithActions},
+        theme_model::slugify,
+    },
+    repository::{
+        placements_repo::PlacementsRepo, publications_repo::PublicationsRepo,
+        ratings_repo::RatingsRepo, themes_repo::ThemesRepo, user_repo::UserRepo,
+    },
+    utils::{
+        auth::AuthUser,
+        locale::{Locales, Localize},
+    },
+};
+
+/// `PlacementQuestion` is the next question of a placement test.
+///
+/// Properties:
+///
+/// * `placement_id`: The id of the placement test.
+/// * `number`: The number of the question, from 1.
+/// * `total`: How many questions the placement test asks at most.
+/// * `test`: The test, without hints and explanation.
+#[derive(Debug, Serialize)]
+pub struct PlacementQuestion {
+    pub placement_id: String,
+    pub number: usize,
+    pub total: usize,
+    pub test: TestRes<TestModel, TestModelWithActions>,
+}
+
+/// `PlacementResult` is the graded answer to a question of a placement test.
+///
+/// Properties:
+///
+/// * `correct`: Whether the answer is correct.
+/// * `level`: The estimated level, once the placement test is finished.
+/// * `next`: The next question, None once the placement test is finished.
+#[derive(Debug, Serialize)]
+pub struct PlacementResult {
+    pub correct: bool,
+    pub level: Option<i32>,
+    pub next: Option<PlacementQuestion>,
+}
+
+/// It collects the published questions of a theme that the placement test has not asked yet,
+/// with their ratings.
+///
+/// Arguments:
+///
+/// * `pubdb`: &PublicationsRepo - the published tests and templates
+/// * `rdb`: &RatingsRepo - the ratings of the questions
+/// * `themes`: &ThemeFilter - the theme of the placement test
+/// * `asked`: &HashSet<String> - the ids of the questions already asked
+///
+/// Returns:
+///
+/// The questions that can be asked next.
+async fn placement_candidates(
+    pubdb: &PublicationsRepo,
+    rdb: &RatingsRepo,
+    themes: &ThemeFilter,
+    asked: &HashSet<String>,
+) -> Result<Vec<(f64, Candidate)>, Status> {
+    let error = |_| Status::InternalServerError;
+    let ratings: HashMap<String, QuestionRatingModel> = rdb
+        .get_question_ratings()
+        .await
+        .map_err(error)?
+        .into_iter()
+        .map(|rating| (rating.question_id.clone(), rating))
+        .collect();
+    let is_new = |id: &str| !asked.contains(id);
+    let mut candidates = vec![];
+    for test in pubdb
+        .get_all_published::<TestModel>(ContentKind::Choice)
+        .await
+        .map_err(error)?
+    {
+        let id = test.id.unwrap().to_hex();
+        if is_new(&id) && themes.matches(&test.theme, &test.theme_id) {
+            candidates.push((
+                question_rating(&ratings, &id, test.level),
+                Candidate::Choice(test),
+            ));
+        }
+    }
+    for test in pubdb
+        .get_all_published::<TestModelWithActions>(ContentKind::Action)
+        .await
+        .map_err(error)?
+    {
+        let id = test.id.unwrap().to_hex();
+        if is_new(&id) && themes.matches(&test.theme, &test.theme_id) {
+            candidates.push((
+                question_rating(&ratings, &id, test.level),
+                Candidate::Action(test),
+            ));
+        }
+    }
+    for template in pubdb
+        .get_all_published::<QuestionTemplate>(ContentKind::Template)
+        .await
+        .map_err(error)?
+    {
+        let id = template.id.unwrap().to_hex();
+        if is_new(&id) && themes.matches(&template.theme, &template.theme_id) {
+            let rating = question_rating(&ratings, &id, template.level);
+            candidates.push((rating, Candidate::Template(template)));
+        }
+    }
+    Ok(candidates)
+}
+
+/// It picks the question whose rating is the nearest to the current estimate and adds it to the
+/// placement test.
+///
+/// Returns:
+///
+/// The question as the user sees it, or None if there is nothing left to ask.
+fn ask_next(
+    placement: &mut PlacementModel,
+    candidates: Vec<(f64, Candidate)>,
+    locales: &Locales,
+) -> Option<TestRes<TestModel, TestModelWithActions>> {
+    let ratings: HashMap<String, f64> = candidates
+        .iter()
+        .map(|(rating, candidate)| {
+            let id = match candidate {
+                Candidate::Choice(test) => test.id,
+                Candidate::Action(test) => test.id,
+                Candidate::Template(template) => template.id,
+            };
+            (id.unwrap().to_hex(), *rating)
+        })
+        .collect();
+    let candidate = pick_adaptive(candidates, placement.rating, 1).pop()?;
+    let (question_id, kind, seed, test) = match candidate {
+        Candidate::Choice(test) => {
+            let id = test.id.unwrap().to_hex();
+            let mut test = test.for_learner();
+            test.localize(locales);
+            (id, QuestionKind::Choice, None, TestRes::ChoiceTest(test))
+        }
+        Candidate::Action(test) => {
+            let id = test.id.unwrap().to_hex();
+            let mut test = test.for_learner();
+            test.localize(locales);
+            (id, QuestionKind::Action, None, TestRes::ActionTest(test))
+        }
+        Candidate::Template(template) => {
+            let seed = QuestionTemplate::random_seed();
+            let mut instance = template.instantiate(seed).ok()?;
+            instance.test = instance.test.for_learner();
+            instance.test.localize(locales);
+            let id = template.id.unwrap().to_hex();
+            let test = TestRes::TemplateTest(Box::new(instance));
+            (id, QuestionKind::Template, Some(seed), test)
+        }
+    };
+    placement.steps.push(PlacementStep {
+        difficulty: ratings[&question_id],
+        question_id,
+        kind,
+        seed,
+        correct: None,
+    });
+    Some(test)
+}
+
+// * User API routes
+
+/// It starts a placement test of the authenticated user for a theme. The placement test asks up to
+/// six questions of the theme across levels, every question near the current estimate of the
+/// level of the user, see `PlacementModel`. Questions are picked by their ratings, see
+/// `question_rating`.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `rdb`: &State<RatingsRepo> - the ratings of the questions
+/// * `pldb`: &State<PlacementsRepo> - the placement tests
+/// * `user`: The user from the `Authorization` header.
+/// * `theme`: The id or the slug of the theme, questions of the themes below it are included.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The first question, or 404 if the theme has no published questions.
+#[post("/user/placement?<theme>")]
+pub async fn start_placement(
+    pubdb: &State<PublicationsRepo>,
+    thdb: &State<ThemesRepo>,
+    rdb: &State<RatingsRepo>,
+    pldb: &State<PlacementsRepo>,
+    user: AuthUser,
+    theme: &str,
+    locales: Locales,
+) -> Result<Json<PlacementQuestion>, Status> {
+    let (found, themes) = theme_filter(thdb, theme).await?;
+    let (slug, theme_id) = match found {
+        Some(found) => (found.slug, found.id.map(|id| id.to_hex())),
+        None => (slugify(theme), None),
+    };
+    let now = chrono::Utc::now().timestamp();
+    let mut placement = PlacementModel::new(&user.id, &slug, theme_id, now);
+    let candidates = placement_candidates(pubdb, rdb, &themes, &HashSet::new()).await?;
+    let test = ask_next(&mut placement, candidates, &locales).ok_or(Status::NotFound)?;
+    let result = pldb
+        .create_placement(&placement)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(PlacementQuestion {
+        placement_id: result.inserted_id.as_object_id().unwrap().to_hex(),
+        number: 1,
+        total: PLACEMENT_QUESTIONS,
+        test,
+    }))
+}
+
+/// It grades the answer to the current question of a placement test and moves the estimate. The
+/// answer can be given in any locale of the question. After the last question, or when the theme
+/// has no more questions, the estimated level is stored on the profile of the user. It is the
+/// level `get_random_test_by_level_user` starts from, and course levels whose tests are all at or
+/// below it are unlocked. Answers to a placement test do not change the progress, the ratings or
+/// the reviews of the user.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `rdb`: &State<RatingsRepo> - the ratings of the questions
+/// * `pldb`: &State<PlacementsRepo> - the placement tests
+/// * `udb`: &State<UserRepo> - the users
+/// * `user`: The user from the `Authorization` header.
+/// * `id`: The id of the placement test.
+/// * `answer`: Json<AttemptAnswer> - the answer of the user
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The graded answer with the next question or the estimated level. 403 if the placement test is
+/// of another user, 404 if it does not exist and 409 if it is finished.
+#[put("/user/placement/<id>/answer", data = "<answer>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn answer_placement(
+    pubdb: &State<PublicationsRepo>,
+    thdb: &State<ThemesRepo>,
+    rdb: &State<RatingsRepo>,
+    pldb: &State<PlacementsRepo>,
+    udb: &State<UserRepo>,
+    user: AuthUser,
+    id: &str,
+    answer: Json<AttemptAnswer>,
+    locales: Locales,
+) -> Result<Json<PlacementResult>, Status> {
+    let error = |_| Status::InternalServerError;
+    let mut placement = pldb
+        .get_placement(id)
+        .await
+        .map_err(error)?
+        .ok_or(Status::NotFound)?;
+    if placement.user_id != user.id {
+        return Err(Status::Forbidden);
+    }
+    let step = placement.current().cloned().ok_or(Status::Conflict)?;
+    let question = load_question(pubdb, &step.question_id, step.seed.unwrap_or(0))
+        .await
+        .ok_or(Status::NotFound)?;
+    let answer = question.canonical_answer(&answer.into_inner().answer);
+    let correct = answer.trim() == question.answer.trim();
+    placement.answer(correct);
+
+    let mut next = None;
+    if placement.answered() < PLACEMENT_QUESTIONS {
+        let key = placement
+            .theme_id
+            .clone()
+            .unwrap_or(placement.theme.clone());
+        let (_, themes) = theme_filter(thdb, &key).await?;
+        let asked: HashSet<String> = placement
+            .steps
+            .iter()
+            .map(|step| step.question_id.clone())
+            .collect();
+        let candidates = placement_candidates(pubdb, rdb, &themes, &asked).await?;
+        next = ask_next(&mut placement, candidates, &locales).map(|test| PlacementQuestion {
+            placement_id: id.to_string(),
+            number: placement.steps.len(),
+            total: PLACEMENT_QUESTIONS,
+            test,
+        });
+    }
+    if next.is_none() {
+        let placed = placement.finish(chrono::Utc::now().timestamp());
+        udb.set_placement(user.id.clone(), placed).await;
+    }
+    pldb.update_placement(&placement).await.map_err(error)?;
+    Ok(Json(PlacementResult {
+        correct,
+        level: placement.level,
+        next,
+    }))
+}
@@ -0,0 +1,632 @@
+use mongodb::bson::{from_document, oid::ObjectId, to_document, Document};
+use rocket::{http::Status, request::FromParam, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{
+        cource_model::CourseModel,
+        publication_model::{ContentKind, PublicationModel, PublicationStatus},
+    },
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, publications_repo::PublicationsRepo,
+        templates_repo::TemplatesRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+};
+
+/// This is a trait that allows us to convert a string into a ContentKind enum.
+impl<'r> FromParam<'r> for ContentKind {
+    type Error = &'r str;
+
+    /// It parses `course`, `info`, `choice`, `action` and `template`.
+    ///
+    /// Arguments:
+    ///
+    /// * `param`: The parameter to be converted.
+    ///
+    /// Returns:
+    ///
+    /// A Result<Self, Self::Error>
+    fn from_param(param: &'r str) -> Result<Self, Self::Error> {
+        ContentKind::from_name(param).ok_or("Invalid content kind")
+    }
+}
+
+/// `ContentRepos` are the collections of the working copies, one per kind of content.
+pub struct ContentRepos<'a> {
+    pub cdb: &'a CourceRepo,
+    pub idb: &'a InfosRepo,
+    pub tdb: &'a TestsRepo,
+    pub adb: &'a TActionRepo,
+    pub pdb: &'a TemplatesRepo,
+}
+
+impl ContentRepos<'_> {
+    /// It loads the working copy of a document as it is stored right now.
+    ///
+    /// Arguments:
+    ///
+    /// * `kind`: ContentKind - The kind of the document
+    /// * `id`: &str - The id of the document
+    ///
+    /// Returns:
+    ///
+    /// The document, or None if there is no such document.
+    pub async fn live_document(
+        &self,
+        kind: ContentKind,
+        id: &str,
+    ) -> Result<Option<Document>, Status> {
+        // the repositories panic on ids that are not object ids
+        if ObjectId::parse_str(id).is_err() {
+            return Ok(None);
+        }
+        let error = |_| Status::InternalServerError;
+        let document = match kind {
+            ContentKind::Course => self.cdb.get(id).await.map(|c| to_document(&c)),
+            ContentKind::Info => self
+                .idb
+                .get_info(&id.to_string())
+                .await
+                .map_err(error)?
+                .map(|info| to_document(&info)),
+            ContentKind::Choice => self
+                .tdb
+                .get_test_by_id(&id.to_string())
+                .await
+                .map_err(error)?
+                .map(|test| to_document(&test)),
+            ContentKind::Action => self
+                .adb
+                .get_test_by_id(id)
+                .await
+                .map_err(error)?
+                .map(|test| to_document(&test)),
+            ContentKind::Template => self
+                .pdb
+                .get_template_by_id(id)
+                .await
+                .map_err(error)?
+                .map(|template| to_document(&template)),
+        };
+        document
+            .transpose()
+            .map_err(|_| Status::InternalServerError)
+    }
+
+    /// It overwrites the working copy of a document with a snapshot, e.g. of an older revision.
+    ///
+    /// Arguments:
+    ///
+    /// * `kind`: ContentKind - The kind of the document
+    /// * `id`: &str - The id of the document
+    /// * `snapshot`: Document - The document to write, without its `_id`
+    ///
+    /// Returns:
+    ///
+    /// Whether the document exists, or 422 if the snapshot no longer fits the model.
+    pub async fn restore_document(
+        &self,
+        kind: ContentKind,
+        id: &str,
+        snapshot: Document,
+    ) -> Result<bool, (Status, String)> {
+        if ObjectId::parse_str(id).is_err() {
+            return Ok(false);
+        }
+        let invalid = |e: mongodb::bson::de::Error| (Status::UnprocessableEntity, e.to_string());
+        let error = |_| (Status::InternalServerError, String::new());
+        let found = match kind {
+            ContentKind::Course => self
+                .cdb
+                .update(id, from_document(snapshot).map_err(invalid)?)
+                .await
+                .is_some(),
+            ContentKind::Info => {
+                self.idb
+                    .update_info(&id.to_string(), from_document(snapshot).map_err(invalid)?)
+                    .await
+                    .map_err(error)?
+                    .matched_count
+                    > 0
+            }
+            ContentKind::Choice => self
+                .tdb
+                .update_test_by_id(&id.to_string(), from_document(snapshot).map_err(invalid)?)
+                .await
+                .map_err(error)?
+                .is_some(),
+            ContentKind::Action => self
+                .adb
+                .update_test(id, from_document(snapshot).map_err(invalid)?)
+                .await
+                .map_err(error)?
+                .is_some(),
+            ContentKind::Template => self
+                .pdb
+                .update_template(id, from_document(snapshot).map_err(invalid)?)
+                .await
+                .map_err(error)?
+                .is_some(),
+        };
+        Ok(found)
+    }
+
+    /// It creates a document from a snapshot that carries its `_id`.
+    ///
+    /// Arguments:
+    ///
+    /// * `kind`: ContentKind - The kind of the document
+    /// * `document`: Document - The document to create
+    ///
+    /// Returns:
+    ///
+    /// Nothing, or 422 if the document does not fit the model.
+    pub async fn create_document(
+        &self,
+        kind: ContentKind,
+        document: Document,
+    ) -> Result<(), (Status, String)> {
+        let invalid = |e: mongodb::bson::de::Error| (Status::UnprocessableEntity, e.to_string());
+        let error = |_| (Status::InternalServerError, String::new());
+        match kind {
+            ContentKind::Course => {
+                self.cdb
+                    .create(from_document(document).map_err(invalid)?)
+                    .await;
+            }
+            ContentKind::Info => {
+                self.idb
+                    .create_info(from_document(document).map_err(invalid)?)
+                    .await
+                    .map_err(error)?;
+            }
+            ContentKind::Choice => {
+                self.tdb
+                    .create_test(from_document(document).map_err(invalid)?)
+                    .await
+                    .map_err(error)?;
+            }
+            ContentKind::Action => {
+                self.adb
+                    .create_test(from_document(document).map_err(invalid)?)
+                    .await
+                    .map_err(error)?;
+            }
+            ContentKind::Template => {
+                self.pdb
+                    .create_template(from_document(document).map_err(invalid)?)
+                    .await
+                    .map_err(error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// It lists the content of a course that learners can not see, a course is only approved once
+/// every info and test of its levels is published.
+///
+/// Arguments:
+///
+/// * `cource`: &CourseModel - The course to approve
+/// * `pubdb`: &PublicationsRepo - The publications
+///
+/// Returns:
+///
+/// The ids of the unpublished content.
+async fn unpublished_content(cource: &CourseModel, pubdb: &PublicationsRepo) -> Vec<String> {
+    let mut unpublished = vec![];
+    for id in cource.content_ids() {
+        let mut published = false;
+        for kind in [
+            ContentKind::Info,
+            ContentKind::Choice,
+            ContentKind::Action,
+            ContentKind::Template,
+        ] {
+            if pubdb.is_published(kind, &id).await {
+                published = true;
+                break;
+            }
+        }
+        if !published {
+            unpublished.push(id);
+        }
+    }
+    unpublished
+}
+
+/// It loads the publication of a document, a document that was never submitted is a draft.
+async fn load_publication(
+    pubdb: &PublicationsRepo,
+    kind: ContentKind,
+    id: &str,
+) -> Result<PublicationModel, (Status, String)> {
+    Ok(pubdb
+        .get_publication(kind, id)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .unwrap_or_else(|| PublicationModel::new(kind, id)))
+}
+
+/// It moves a document to the next status and stores its publication.
+async fn move_publication(
+    pubdb: &PublicationsRepo,
+    mut publication: PublicationModel,
+    next: PublicationStatus,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    publication
+        .transition(next, chrono::Utc::now().timestamp())
+        .map_err(|e| (Status::Conflict, e))?;
+    pubdb
+        .save_publication(&publication)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?;
+    Ok(Json(publication))
+}
+
+/// `PublicationMigrationReport` is the result of `migrate_publications`.
+///
+/// Properties:
+///
+/// * `published`: The published documents, as `kind id`.
+/// * `unpublished_courses`: The courses that are left as drafts, with the content they use that
+///   learners can not see.
+#[derive(Debug, Default, Serialize)]
+pub struct PublicationMigrationReport {
+    pub published: Vec<String>,
+    pub unpublished_courses: Vec<String>,
+}
+
+/// It publishes a document that has no publication yet with its working copy as the snapshot.
+///
+/// Returns:
+///
+/// Whether the document was published, false if it already has a publication.
+async fn grandfather(
+    pubdb: &PublicationsRepo,
+    kind: ContentKind,
+    id: &str,
+    snapshot: Document,
+) -> Result<bool, Status> {
+    let error = |_| Status::InternalServerError;
+    if pubdb
+        .get_publication(kind, id)
+        .await
+        .map_err(error)?
+        .is_some()
+    {
+        return Ok(false);
+    }
+    let now = chrono::Utc::now().timestamp();
+    let mut publication = PublicationModel::new(kind, id);
+    publication.review_comment = Some("published when publications were introduced".to_string());
+    publication
+        .transition(PublicationStatus::InReview, now)
+        .and_then(|_| publication.publish(snapshot, now))
+        .map_err(|_| Status::InternalServerError)?;
+    pubdb.save_publication(&publication).await.map_err(error)?;
+    Ok(true)
+}
+
+// * Admin API routes
+
+/// It gets the publication state of a document.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+///
+/// Returns:
+///
+/// The publication, a draft if the document was never submitted.
+#[get("/admin/publications/<kind>/<id>")]
+pub async fn get_publication(
+    pubdb: &State<PublicationsRepo>,
+    kind: ContentKind,
+    id: &str,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    load_publication(pubdb, kind, id).await.map(Json)
+}
+
+/// It gets the documents that wait for a reviewer.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+///
+/// Returns:
+///
+/// A vector of publications in review.
+#[get("/admin/publications/review")]
+pub async fn get_review_queue(
+    pubdb: &State<PublicationsRepo>,
+) -> Result<Json<Vec<PublicationModel>>, Status> {
+    match pubdb.get_by_status(PublicationStatus::InReview).await {
+        Ok(publications) => Ok(Json(publications)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It submits a document for review.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the working copies of the courses, infos, choice tests,
+///   action tests and templates
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+///
+/// Returns:
+///
+/// The publication, 404 if there is no such document and 409 if it can not be submitted.
+#[allow(clippy::too_many_arguments)]
+#[post("/admin/publications/<kind>/<id>/submit")]
+pub async fn submit_publication(
+    pubdb: &State<PublicationsRepo>,
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    kind: ContentKind,
+    id: &str,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    if repos
+        .live_document(kind, id)
+        .await
+        .map_err(|status| (status, String::new()))?
+        .is_none()
+    {
+        return Err((
+            Status::NotFound,
+            format!("unknown {} `{}`", kind.name(), id),
+        ));
+    }
+    let publication = load_publication(pubdb, kind, id).await?;
+    move_publication(pubdb, publication, PublicationStatus::InReview).await
+}
+
+/// `ReviewDecision` is the answer of a reviewer to a submitted document.
+///
+/// Properties:
+///
+/// * `reviewer`: Who reviewed the document.
+/// * `comment`: Why the document was approved or rejected.
+#[derive(Debug, Deserialize)]
+pub struct ReviewDecision {
+    pub reviewer: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// It approves a document in review. The working copy is snapshotted and the snapshot is what
+/// learners see until the next approval.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the working copies of the courses, infos, choice tests,
+///   action tests and templates
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+/// * `decision`: Json<ReviewDecision> - the reviewer and their comment
+///
+/// Returns:
+///
+/// The publication, 404 if there is no such document, 409 if it is not in review and 422 with the
+/// unpublished content of a course.
+#[allow(clippy::too_many_arguments)]
+#[post("/admin/publications/<kind>/<id>/approve", data = "<decision>")]
+pub async fn approve_publication(
+    pubdb: &State<PublicationsRepo>,
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    kind: ContentKind,
+    id: &str,
+    decision: Json<ReviewDecision>,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    let snapshot = repos
+        .live_document(kind, id)
+        .await
+        .map_err(|status| (status, String::new()))?
+        .ok_or((
+            Status::NotFound,
+            format!("unknown {} `{}`", kind.name(), id),
+        ))?;
+    if kind == ContentKind::Course {
+        let cource: CourseModel = mongodb::bson::from_document(snapshot.clone())
+            .map_err(|_| (Status::InternalServerError, String::new()))?;
+        let unpublished = unpublished_content(&cource, pubdb).await;
+        if !unpublished.is_empty() {
+            return Err((
+                Status::UnprocessableEntity,
+                format!("unpublished content: {}", unpublished.join(", ")),
+            ));
+        }
+    }
+    let decision = decision.into_inner();
+    let mut publication = load_publication(pubdb, kind, id).await?;
+    publication
+        .publish(snapshot, chrono::Utc::now().timestamp())
+        .map_err(|e| (Status::Conflict, e))?;
+    publication.reviewed_by = Some(decision.reviewer);
+    publication.review_comment = decision.comment;
+    pubdb
+        .save_publication(&publication)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?;
+    Ok(Json(publication))
+}
+
+/// It sends a document in review back to draft. The last published snapshot, if any, stays
+/// visible to learners.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+/// * `decision`: Json<ReviewDecision> - the reviewer and the reason of the rejection
+///
+/// Returns:
+///
+/// The publication, or 409 if it is not in review.
+#[post("/admin/publications/<kind>/<id>/reject", data = "<decision>")]
+pub async fn reject_publication(
+    pubdb: &State<PublicationsRepo>,
+    kind: ContentKind,
+    id: &str,
+    decision: Json<ReviewDecision>,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    let decision = decision.into_inner();
+    let mut publication = load_publication(pubdb, kind, id).await?;
+    if publication.status != PublicationStatus::InReview {
+        return Err((
+            Status::Conflict,
+            "the document is not in review".to_string(),
+        ));
+    }
+    publication.reviewed_by = Some(decision.reviewer);
+    publication.review_comment = decision.comment;
+    move_publication(pubdb, publication, PublicationStatus::Draft).await
+}
+
+/// It withdraws a document from learners.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+///
+/// Returns:
+///
+/// The publication, or 409 if it is in review or already archived.
+#[post("/admin/publications/<kind>/<id>/archive")]
+pub async fn archive_publication(
+    pubdb: &State<PublicationsRepo>,
+    kind: ContentKind,
+    id: &str,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    let publication = load_publication(pubdb, kind, id).await?;
+    move_publication(pubdb, publication, PublicationStatus::Archived).await
+}
+
+/// It restores an archived document as a draft, it has to be approved again to be visible.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+///
+/// Returns:
+///
+/// The publication, or 409 if it is not archived.
+#[post("/admin/publications/<kind>/<id>/restore")]
+pub async fn restore_publication(
+    pubdb: &State<PublicationsRepo>,
+    kind: ContentKind,
+    id: &str,
+) -> Result<Json<PublicationModel>, (Status, String)> {
+    let publication = load_publication(pubdb, kind, id).await?;
+    move_publication(pubdb, publication, PublicationStatus::Draft).await
+}
+
+/// It publishes the content that was created before publications existed, so learners keep
+/// seeing it. Every course, info, test and template without a publication is published with its
+/// working copy as the snapshot; documents that already have a publication are left as they are,
+/// so the migration can be run more than once. The infos, tests and templates are published
+/// first, a course whose content learners still can not see stays a draft.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the publications
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the working copies of the courses, infos, choice tests,
+///   action tests and templates
+///
+/// Returns:
+///
+/// A report with the published documents and the courses left as drafts.
+#[post("/admin/migrate/publications")]
+pub async fn migrate_publications(
+    pubdb: &State<PublicationsRepo>,
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+) -> Result<Json<PublicationMigrationReport>, Status> {
+    let error = |_| Status::InternalServerError;
+    let invalid = |_| Status::InternalServerError;
+    let mut documents = vec![];
+    for info in idb.get_all_infos().await.map_err(error)? {
+        let document = to_document(&info).map_err(invalid)?;
+        documents.push((ContentKind::Info, info.id, document));
+    }
+    for test in tdb.get_all_tests().await.map_err(error)? {
+        let document = to_document(&test).map_err(invalid)?;
+        documents.push((ContentKind::Choice, test.id, document));
+    }
+    for test in adb.get_all_tests().await.map_err(error)? {
+        let document = to_document(&test).map_err(invalid)?;
+        documents.push((ContentKind::Action, test.id, document));
+    }
+    for template in pdb.get_all_templates().await.map_err(error)? {
+        let document = to_document(&template).map_err(invalid)?;
+        documents.push((ContentKind::Template, template.id, document));
+    }
+
+    let mut report = PublicationMigrationReport::default();
+    for (kind, id, document) in documents {
+        let id = id.unwrap().to_hex();
+        if grandfather(pubdb, kind, &id, document).await? {
+            report.published.push(format!("{} {}", kind.name(), id));
+        }
+    }
+    for cource in cdb.get_all().await.unwrap_or_default() {
+        let id = cource.id.unwrap().to_hex();
+        if pubdb
+            .get_publication(ContentKind::Course, &id)
+            .await
+            .map_err(error)?
+            .is_some()
+        {
+            continue;
+        }
+        let unpublished = unpublished_content(&cource, pubdb).await;
+        if !unpublished.is_empty() {
+            report
+                .unpublished_courses
+                .push(format!("{}: {}", id, unpublished.join(", ")));
+            continue;
+        }
+        let document = to_document(&cource).map_err(invalid)?;
+        if grandfather(pubdb, ContentKind::Course, &id, document).await? {
+            report.published.push(format!("course {}", id));
+        }
+    }
+    Ok(Json(report))
+}
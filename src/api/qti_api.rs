@@ -0,0 +1,132 @@
+use mongodb::bson::from_document;
+use rocket::{
+    http::{Header, Status},
+    serde::json::Json,
+    State,
+};
+
+use crate::{
+    api::{bundles_api::load_course, publications_api::ContentRepos},
+    models::{
+        publication_model::ContentKind,
+        qti_model::{QtiExportReport, QtiPackage},
+    },
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, templates_repo::TemplatesRepo,
+        tests_repo::TestsRepo, tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+};
+
+/// `QtiExport` is an exported question bank, a QTI content package or, for a dry run, the report
+/// of what the package would contain.
+#[derive(Responder)]
+pub enum QtiExport {
+    Report(Json<QtiExportReport>),
+    Package(QtiArchive),
+}
+
+/// `QtiArchive` is a question bank packed by `QtiPackage::to_zip`, served as a download.
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct QtiArchive {
+    data: Vec<u8>,
+    disposition: Header<'static>,
+}
+
+/// It loads the working copies of the choice tests, action tests and templates of a course.
+async fn load_course_package(
+    repos: &ContentRepos<'_>,
+    id: &str,
+) -> Result<QtiPackage, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    let course = load_course(repos, id).await?;
+    let (mut choice_tests, mut action_tests, mut templates) = (vec![], vec![], vec![]);
+    for content_id in course.content_ids() {
+        for kind in [
+            ContentKind::Choice,
+            ContentKind::Action,
+            ContentKind::Template,
+        ] {
+            let document = repos
+                .live_document(kind, &content_id)
+                .await
+                .map_err(|status| (status, String::new()))?;
+            if let Some(document) = document {
+                match kind {
+                    ContentKind::Choice => {
+                        choice_tests.push(from_document(document).map_err(error)?)
+                    }
+                    ContentKind::Action => {
+                        action_tests.push(from_document(document).map_err(error)?)
+                    }
+                    _ => templates.push(from_document(document).map_err(error)?),
+                }
+                break;
+            }
+        }
+    }
+    Ok(QtiPackage::new(&choice_tests, &action_tests, &templates))
+}
+
+// * Admin API routes
+
+/// It exports the question bank, or the questions of one course, as a QTI 2.1 content package.
+/// Choice tests become `choiceInteraction` items and action tests `textEntryInteraction` items.
+/// Templates and tests that can not be represented are listed in `export-report.json` of the
+/// package. The package is imported back with `POST /admin/tests/import?format=qti`.
+///
+/// Arguments:
+///
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the courses, infos, choice tests, action tests and
+///   templates
+/// * `course`: the id of the course, the whole bank if empty
+/// * `dry_run`: return the report instead of the package
+///
+/// Returns:
+///
+/// The package or the report, 404 if there is no such course.
+#[get("/admin/tests/export/qti?<course>&<dry_run>")]
+pub async fn export_tests_qti(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    course: Option<&str>,
+    dry_run: Option<bool>,
+) -> Result<QtiExport, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    let package = match course {
+        Some(id) => {
+            let repos = ContentRepos {
+                cdb,
+                idb,
+                tdb,
+                adb,
+                pdb,
+            };
+            load_course_package(&repos, id).await?
+        }
+        None => QtiPackage::new(
+            &tdb.get_all_tests().await.map_err(error)?,
+            &adb.get_all_tests().await.map_err(error)?,
+            &pdb.get_all_templates().await.map_err(error)?,
+        ),
+    };
+    if dry_run.unwrap_or(false) {
+        return Ok(QtiExport::Report(Json(package.report)));
+    }
+    let name = match course {
+        Some(id) => format!("course-{}-qti.zip", id),
+        None => "tests-qti.zip".to_string(),
+    };
+    Ok(QtiExport::Package(QtiArchive {
+        data: package
+            .to_zip()
+            .map_err(|e| (Status::InternalServerError, e))?,
+        disposition: Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", name),
+        ),
+    }))
+}
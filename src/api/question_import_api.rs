@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use rocket::{
+    data::Capped,
+    http::{ContentType, Status},
+    serde::json::Json,
+    State,
+};
+
+use crate::{
+    models::{
+        publication_model::ContentKind,
+        qti_model::parse_qti,
+        question_import_model::{
+            parse_csv, parse_gift, ImportDefaults, ImportIssue, ImportedQuestion, QuestionFormat,
+            QuestionImportReport,
+        },
+        theme_model::{slugify, ThemeModel},
+    },
+    repository::{
+        skills_repo::SkillsRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo, themes_repo::ThemesRepo,
+    },
+    utils::render::render_question,
+};
+
+/// It finds the theme a question bank names, by id, by slug or by name.
+async fn find_theme(thdb: &ThemesRepo, key: &str) -> Result<Option<ThemeModel>, Status> {
+    let error = |_| Status::InternalServerError;
+    match thdb.find_theme(key).await.map_err(error)? {
+        Some(theme) => Ok(Some(theme)),
+        None => thdb.get_theme_by_slug(&slugify(key)).await.map_err(error),
+    }
+}
+
+/// It checks an imported question and points it at its theme and its skills. A theme that is not
+/// in the taxonomy is kept as free text, like the themes `POST /admin/migrate/themes` migrates.
+///
+/// Returns:
+///
+/// The problems of the question, empty if it can be imported.
+async fn prepare_question(
+    thdb: &ThemesRepo,
+    skdb: &SkillsRepo,
+    themes: &mut HashMap<String, Option<ThemeModel>>,
+    question: &mut ImportedQuestion,
+) -> Result<Vec<String>, Status> {
+    let error = |_| Status::InternalServerError;
+    let mut errors = question.validate();
+    if let Err(e) = render_question(&question.test.question, &question.test.answers) {
+        errors.extend(e);
+    }
+    let test = &mut question.test;
+    if !test.theme.is_empty() {
+        if !themes.contains_key(&test.theme) {
+            let theme = find_theme(thdb, &test.theme).await?;
+            themes.insert(test.theme.clone(), theme);
+        }
+        if let Some(theme) = &themes[&test.theme] {
+            test.theme_id = theme.id.map(|id| id.to_hex());
+            test.theme = theme.slug.clone();
+        }
+    }
+    for key in &question.skills {
+        let skill = match skdb.get_skill(key).await.map_err(error)? {
+            Some(skill) => Some(skill),
+            None => skdb.get_skill_by_slug(&slugify(key)).await.map_err(error)?,
+        };
+        match skill.and_then(|skill| skill.id) {
+            Some(id) => test.skills.push(id.to_hex()),
+            None => errors.push(format!("unknown skill `{}`", key)),
+        }
+    }
+    Ok(errors)
+}
+
+// * Admin API routes
+
+/// It imports a question bank written in CSV, in the GIFT format of Moodle or as QTI 2.1 items as
+/// choice and action tests, see `parse_csv` for the columns, `parse_gift` for the supported
+/// questions and `parse_qti_item` for the supported interactions. Every
+/// question is checked on its own: invalid questions and questions whose text is already in the
+/// bank or in the database are reported with their line and skipped, the others are imported.
+/// Imported tests are drafts until they are approved.
+///
+/// Arguments:
+///
+/// * `tdb`: &State<TestsRepo> - the choice tests
+/// * `adb`: &State<TActionRepo> - the action tests
+/// * `thdb`: &State<ThemesRepo> - the themes the questions are pointed at
+/// * `skdb`: &State<SkillsRepo> - the skills the questions are tagged with
+/// * `content_type`: `text/csv` reads the bank as CSV and `application/zip` or `text/xml` as QTI
+///   when there is no `format`
+/// * `format`: `csv`, `gift` or `qti`, a QTI bank is a content package or a single item
+/// * `dry_run`: report what would be imported without writing anything
+/// * `theme`: the theme of questions without one
+/// * `level`: the difficulty of questions without one, 1 by default
+/// * `bank`: the question bank, limited by the `bytes` limit of Rocket
+///
+/// Returns:
+///
+/// What was (or would be) imported, 400 if the format is unknown or the bank is not UTF-8 and 413
+/// if the bank is too big.
+#[allow(clippy::too_many_arguments)]
+#[post(
+    "/admin/tests/import?<format>&<dry_run>&<theme>&<level>",
+    data = "<bank>"
+)]
+pub async fn import_tests(
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    thdb: &State<ThemesRepo>,
+    skdb: &State<SkillsRepo>,
+    content_type: &ContentType,
+    format: Option<&str>,
+    dry_run: Option<bool>,
+    theme: Option<&str>,
+    level: Option<i32>,
+    bank: Capped<Vec<u8>>,
+) -> Result<Json<QuestionImportReport>, (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    if !bank.is_complete() {
+        return Err((Status::PayloadTooLarge, Json(vec![])));
+    }
+    let format = match format {
+        Some(name) => QuestionFormat::from_name(name),
+        None if *content_type == ContentType::CSV => Some(QuestionFormat::Csv),
+        None if [ContentType::ZIP, ContentType::XML].contains(content_type) => {
+            Some(QuestionFormat::Qti)
+        }
+        None => None,
+    }
+    .ok_or((
+        Status::BadRequest,
+        Json(vec![
+            "unknown format, use `format=csv`, `format=gift` or `format=qti`".to_string(),
+        ]),
+    ))?;
+    let defaults = ImportDefaults {
+        theme: theme.unwrap_or_default().to_string(),
+        level: level.unwrap_or(1),
+    };
+    let (questions, errors) = match format {
+        QuestionFormat::Csv => parse_csv(&bank, &defaults),
+        QuestionFormat::Gift => {
+            let text = std::str::from_utf8(&bank).map_err(|e| {
+                (
+                    Status::BadRequest,
+                    Json(vec![format!("the bank is not UTF-8: {}", e)]),
+                )
+            })?;
+            parse_gift(text, &defaults)
+        }
+        QuestionFormat::Qti => parse_qti(&bank, &defaults),
+    };
+    let dry_run = dry_run.unwrap_or(false);
+    let mut report = QuestionImportReport {
+        format,
+        dry_run,
+        parsed: questions.len() + errors.len(),
+        choice_tests: 0,
+        action_tests: 0,
+        imported: vec![],
+        duplicates: vec![],
+        errors,
+    };
+    let mut themes = HashMap::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for mut question in questions {
+        let issue = |question: &ImportedQuestion, message: String| ImportIssue {
+            line: question.line,
+            file: question.file.clone(),
+            question: question.test.question.clone(),
+            message,
+        };
+        let problems = prepare_question(thdb, skdb, &mut themes, &mut question)
+            .await
+            .map_err(|status| (status, Json(vec![])))?;
+        if !problems.is_empty() {
+            report.errors.push(issue(&question, problems.join("; ")));
+            continue;
+        }
+        let text = question.test.question.clone();
+        if let Some(line) = seen.get(&text) {
+            let message = format!("the question is already on line {}", line);
+            report.duplicates.push(issue(&question, message));
+            continue;
+        }
+        seen.insert(text.clone(), question.line);
+        let existing = match tdb.get_test_by_question(&text).await.map_err(error)? {
+            Some(test) => test.id,
+            None => adb
+                .get_test_by_ex(text)
+                .await
+                .map_err(error)?
+                .and_then(|test| test.id),
+        };
+        if let Some(id) = existing {
+            let message = format!("the question is already test `{}`", id.to_hex());
+            report.duplicates.push(issue(&question, message));
+            continue;
+        }
+        match question.kind {
+            ContentKind::Action => report.action_tests += 1,
+            _ => report.choice_tests += 1,
+        }
+        if dry_run {
+            continue;
+        }
+        let result = match question.kind {
+            ContentKind::Action => adb.create_test(question.action_test()).await,
+            _ => tdb.create_test(question.test).await,
+        }
+        .map_err(error)?;
+        if let Some(id) = result.inserted_id.as_object_id() {
+            report.imported.push(id.to_hex());
+        }
+    }
+    report.errors.sort_by_key(|issue| issue.line);
+    Ok(Json(report))
+}
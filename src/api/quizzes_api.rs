@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, thread_rng};
+use rocket::{http::Status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{
+        attempts_api::{load_question, record_answer, AttemptAnswer},
+        cources_api::learner_progress,
+        tests_api::TestRes,
+        themes_api::theme_filter,
+    },
+    models::{
+        attempt_model::{AttemptModel, QuestionKind},
+        cource_model::{CourseModel, NodeState},
+        publication_model::ContentKind,
+        quiz_model::{
+            QuizModel, QuizQuestion, QuizResult, QuizSource, DEFAULT_QUIZ_QUESTIONS,
+            MAX_QUIZ_QUESTIONS, MAX_QUIZ_TIME_LIMIT,
+        },
+        rating_model::{calibrated_level, QuestionRatingModel},
+        template_model::QuestionTemplate,
+        tests_model::{TestModel, TestModelWithActions},
+    },
+    repository::{
+        attempts_repo::AttemptsRepo, publications_repo::PublicationsRepo,
+        quizzes_repo::QuizzesRepo, ratings_repo::RatingsRepo, reviews_repo::ReviewsRepo,
+        skills_repo::SkillsRepo, themes_repo::ThemesRepo, user_repo::UserRepo,
+    },
+    utils::{
+        auth::AuthUser,
+        locale::{Locales, Localize},
+    },
+};
+
+/// `QuizRequest` is the request to start a quiz.
+///
+/// Properties:
+///
+/// * `source`: Where the questions come from, `level`, `theme` or `template`, with its fields.
+/// * `number_of_questions`: How many questions a quiz of a theme or of a template has, 10 by
+///   default. A quiz of a course level has all the tests of the level.
+/// * `time_limit`: The time limit in seconds, the quiz is untimed without it.
+#[derive(Debug, Deserialize)]
+pub struct QuizRequest {
+    #[serde(flatten)]
+    pub source: QuizSource,
+    #[serde(default)]
+    pub number_of_questions: Option<usize>,
+    #[serde(default)]
+    pub time_limit: Option<i64>,
+}
+
+/// `QuizItem` is a question of a quiz as the user sees it.
+///
+/// Properties:
+///
+/// * `number`: The number of the question, from 1.
+/// * `test`: The test, without hints and explanation. None if it is no longer published.
+/// * `answered`: Whether the question is answered.
+/// * `answer`: The answer of the user, once the quiz is finished.
+/// * `correct`: Whether the answer was correct, once the quiz is finished.
+#[derive(Debug, Serialize)]
+pub struct QuizItem {
+    pub number: usize,
+    pub test: Option<TestRes<TestModel, TestModelWithActions>>,
+    pub answered: bool,
+    pub answer: Option<String>,
+    pub correct: Option<bool>,
+}
+
+/// `QuizView` is a quiz as the user sees it.
+///
+/// Properties:
+///
+/// * `id`: The id of the quiz.
+/// * `source`: Where the questions come from.
+/// * `time_limit`: The time limit in seconds.
+/// * `started_at`: Unix timestamp of the start.
+/// * `expires_at`: Unix timestamp after which answers are refused.
+/// * `finished_at`: Unix timestamp of the end.
+/// * `questions`: The questions, in order.
+/// * `result`: The score breakdown, once the quiz is finished.
+#[derive(Debug, Serialize)]
+pub struct QuizView {
+    pub id: String,
+    pub source: QuizSource,
+    pub time_limit: Option<i64>,
+    pub started_at: i64,
+    pub expires_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub questions: Vec<QuizItem>,
+    pub result: Option<QuizResult>,
+}
+
+/// `QuizProgress` is how far the user is through a quiz after an answer. Whether the answer is
+/// correct is only told when the quiz is finished.
+#[derive(Debug, Serialize)]
+pub struct QuizProgress {
+    pub answered: usize,
+    pub total: usize,
+    pub expires_at: Option<i64>,
+}
+
+/// It renders a question of a quiz for the user.
+///
+/// Returns:
+///
+/// The test without hints and explanation, or None if it is no longer published.
+async fn quiz_test(
+    pubdb: &PublicationsRepo,
+    question: &QuizQuestion,
+    locales: &Locales,
+) -> Result<Option<TestRes<TestModel, TestModelWithActions>>, Status> {
+    let error = |_| Status::InternalServerError;
+    let id = &question.question_id;
+    let test = match question.kind {
+        QuestionKind::Choice => pubdb
+            .get_published::<TestModel>(ContentKind::Choice, id)
+            .await
+            .map_err(error)?
+            .map(|test| {
+                let mut test = test.for_learner();
+                test.localize(locales);
+                TestRes::ChoiceTest(test)
+            }),
+        QuestionKind::Action => pubdb
+            .get_published::<TestModelWithActions>(ContentKind::Action, id)
+            .await
+            .map_err(error)?
+            .map(|test| {
+                let mut test = test.for_learner();
+                test.localize(locales);
+                TestRes::ActionTest(test)
+            }),
+        QuestionKind::Template => pubdb
+            .get_published::<QuestionTemplate>(ContentKind::Template, id)
+            .await
+            .map_err(error)?
+            .and_then(|template| template.instantiate(question.seed.unwrap_or(0)).ok())
+            .map(|mut instance| {
+                instance.test = instance.test.for_learner();
+                instance.test.localize(locales);
+                TestRes::TemplateTest(Box::new(instance))
+            }),
+    };
+    Ok(test)
+}
+
+/// It renders a quiz for the user. Answers are shown once the quiz is finished.
+async fn quiz_view(
+    pubdb: &PublicationsRepo,
+    quiz: QuizModel,
+    locales: &Locales,
+) -> Result<QuizView, Status> {
+    let finished = quiz.finished_at.is_some();
+    let mut questions = vec![];
+    for (index, question) in quiz.questions.iter().enumerate() {
+        questions.push(QuizItem {
+            number: index + 1,
+            test: quiz_test(pubdb, question, locales).await?,
+            answered: question.answer.is_some(),
+            answer: question.answer.clone().filter(|_| finished),
+            correct: question.correct.filter(|_| finished),
+        });
+    }
+    Ok(QuizView {
+        id: quiz.id.map(|id| id.to_hex()).unwrap_or_default(),
+        source: quiz.source,
+        time_limit: quiz.time_limit,
+        started_at: quiz.started_at,
+        expires_at: quiz.expires_at,
+        finished_at: quiz.finished_at,
+        questions,
+        result: quiz.result,
+    })
+}
+
+/// It gets a quiz of the authenticated user.
+///
+/// Returns:
+///
+/// The quiz, 404 if it does not exist or 403 if it is of another user.
+async fn user_quiz(qzdb: &QuizzesRepo, user: &AuthUser, id: &str) -> Result<QuizModel, Status> {
+    let quiz = qzdb
+        .get_quiz(id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    if quiz.user_id != user.id {
+        return Err(Status::Forbidden);
+    }
+    Ok(quiz)
+}
+
+// * User API routes
+
+/// It starts a quiz of the authenticated user with a frozen set of questions:
+///
+/// * `{"source": "level", "course_id": ..., "node_id": ...}`: the tests of a test level of a
+///   published course, the level must not be locked for the user.
+/// * `{"source": "theme", "theme": ..., "level": ...}`: random published questions of a theme at a
+///   calibrated level, see `get_random_test_by_level_user`. Without `level`, the level from the
+///   placement test of the user for the theme, or 1.
+/// * `{"source": "template", "template_id": ...}`: questions generated from a published template.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published courses, tests and templates
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `udb`: &State<UserRepo> - the users
+/// * `adb`: &State<AttemptsRepo> - the graded attempts, to find the locked levels
+/// * `rdb`: &State<RatingsRepo> - the ratings of the questions
+/// * `qzdb`: &State<QuizzesRepo> - the quizzes
+/// * `user`: The user from the `Authorization` header.
+/// * `req`: Json<QuizRequest> - where the questions come from, how many and the time limit
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The quiz. 422 with the reason for a bad number of questions, time limit or level, 403 if the
+/// level is locked and 404 if the course, the level, the template or any question is missing.
+#[post("/user/quizzes", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_quiz(
+    pubdb: &State<PublicationsRepo>,
+    thdb: &State<ThemesRepo>,
+    udb: &State<UserRepo>,
+    adb: &State<AttemptsRepo>,
+    rdb: &State<RatingsRepo>,
+    qzdb: &State<QuizzesRepo>,
+    user: AuthUser,
+    req: Json<QuizRequest>,
+    locales: Locales,
+) -> Result<Json<QuizView>, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    let status = |status: Status| (status, String::new());
+    let req = req.into_inner();
+    let count = req.number_of_questions.unwrap_or(DEFAULT_QUIZ_QUESTIONS);
+    if count == 0 || count > MAX_QUIZ_QUESTIONS {
+        return Err((
+            Status::UnprocessableEntity,
+            format!("a quiz has from 1 to {} questions", MAX_QUIZ_QUESTIONS),
+        ));
+    }
+    if req
+        .time_limit
+        .is_some_and(|limit| limit <= 0 || limit > MAX_QUIZ_TIME_LIMIT)
+    {
+        return Err((
+            Status::UnprocessableEntity,
+            format!(
+                "the time limit is from 1 to {} seconds",
+                MAX_QUIZ_TIME_LIMIT
+            ),
+        ));
+    }
+    let ratings: HashMap<String, QuestionRatingModel> = rdb
+        .get_question_ratings()
+        .await
+        .map_err(error)?
+        .into_iter()
+        .map(|rating| (rating.question_id.clone(), rating))
+        .collect();
+
+    let picked: Vec<String> = match &req.source {
+        QuizSource::Level { course_id, node_id } => {
+            let cource = pubdb
+                .get_published::<CourseModel>(ContentKind::Course, course_id)
+                .await
+                .map_err(error)?
+                .ok_or((Status::NotFound, format!("unknown course `{}`", course_id)))?;
+            let (_, level) = cource
+                .find_level(node_id)
+                .ok_or((Status::NotFound, format!("unknown level `{}`", node_id)))?;
+            if level.type_ != "test" {
+                return Err((
+                    Status::UnprocessableEntity,
+                    format!("level `{}` is not a test level", node_id),
+                ));
+            }
+            let progress = learner_progress(&user.id, &cource, udb, adb, pubdb)
+                .await
+                .map_err(status)?;
+            let states =
+                cource.node_states(&progress.completed, &progress.started, &progress.unlocked);
+            if states.get(node_id) == Some(&NodeState::Locked) {
+                return Err((Status::Forbidden, format!("level `{}` is locked", node_id)));
+            }
+            level.ids.clone()
+        }
+        QuizSource::Theme { theme, level } => {
+            let (found, themes) = theme_filter(thdb, theme).await.map_err(status)?;
+            let level = match level {
+                Some(level) => *level,
+                None => {
+                    let progress = udb
+                        .get_user_by_id(&user.id)
+                        .await
+                        .map_err(error)?
+                        .ok_or(status(Status::NotFound))?
+                        .progress;
+                    let placed = match &found {
+                        Some(found) => {
+                            progress.placed_level(&found.slug, &found.id.map(|id| id.to_hex()))
+                        }
+                        None => progress.placed_level(theme, &None),
+                    };
+                    placed.unwrap_or(1)
+                }
+            };
+            let at_level =
+                |id: &str, hand_set: i32| calibrated_level(&ratings, id, hand_set) == level;
+            let mut ids = vec![];
+            for test in pubdb
+                .get_all_published::<TestModel>(ContentKind::Choice)
+                .await
+                .map_err(error)?
+            {
+                let id = test.id.unwrap().to_hex();
+                if themes.matches(&test.theme, &test.theme_id) && at_level(&id, test.level) {
+                    ids.push(id);
+                }
+            }
+            for test in pubdb
+                .get_all_published::<TestModelWithActions>(ContentKind::Action)
+                .await
+                .map_err(error)?
+            {
+                let id = test.id.unwrap().to_hex();
+                if themes.matches(&test.theme, &test.theme_id) && at_level(&id, test.level) {
+                    ids.push(id);
+                }
+            }
+            for template in pubdb
+                .get_all_published::<QuestionTemplate>(ContentKind::Template)
+                .await
+                .map_err(error)?
+            {
+                let id = template.id.unwrap().to_hex();
+                if themes.matches(&template.theme, &template.theme_id)
+                    && at_level(&id, template.level)
+                {
+                    ids.push(id);
+                }
+            }
+            ids.shuffle(&mut thread_rng());
+            ids.truncate(count);
+            ids
+        }
+        QuizSource::Template { template_id } => {
+            pubdb
+                .get_published::<QuestionTemplate>(ContentKind::Template, template_id)
+                .await
+                .map_err(error)?
+                .ok_or((
+                    Status::NotFound,
+                    format!("unknown template `{}`", template_id),
+                ))?;
+            vec![template_id.clone(); count]
+        }
+    };
+    if picked.is_empty() {
+        return Err((Status::NotFound, "no published questions".to_string()));
+    }
+
+    let mut questions = vec![];
+    for id in picked {
+        let seed = QuestionTemplate::random_seed();
+        let question = load_question(pubdb, &id, seed)
+            .await
+            .ok_or((Status::NotFound, format!("unknown question `{}`", id)))?;
+        let seed = (question.kind == QuestionKind::Template).then_some(seed);
+        let level = calibrated_level(&ratings, &id, question.level);
+        questions.push(QuizQuestion::new(
+            &id,
+            question.kind,
+            seed,
+            &question.theme,
+            level,
+        ));
+    }
+    let now = chrono::Utc::now().timestamp();
+    let mut quiz = QuizModel::new(&user.id, req.source, questions, req.time_limit, now);
+    let result = qzdb.create_quiz(&quiz).await.map_err(error)?;
+    quiz.id = result.inserted_id.as_object_id();
+    Ok(Json(
+        quiz_view(pubdb, quiz, &locales).await.map_err(status)?,
+    ))
+}
+
+/// It returns the quizzes of the authenticated user, the latest first, with the score breakdown
+/// of the finished ones. Like `get_quiz_user`, the answers and whether they were correct are shown
+/// once a quiz is finished.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `qzdb`: &State<QuizzesRepo> - the quizzes
+/// * `user`: The user from the `Authorization` header.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// A vector of quizzes
+#[get("/user/quizzes")]
+pub async fn get_quizzes_user(
+    pubdb: &State<PublicationsRepo>,
+    qzdb: &State<QuizzesRepo>,
+    user: AuthUser,
+    locales: Locales,
+) -> Result<Json<Vec<QuizView>>, Status> {
+    let quizzes = qzdb
+        .get_user_quizzes(&user.id)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let mut views = vec![];
+    for quiz in quizzes {
+        views.push(quiz_view(pubdb, quiz, &locales).await?);
+    }
+    Ok(Json(views))
+}
+
+/// It returns a quiz of the authenticated user with its questions. The answers and whether they
+/// were correct are shown once the quiz is finished.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `qzdb`: &State<QuizzesRepo> - the quizzes
+/// * `user`: The user from the `Authorization` header.
+/// * `id`: The id of the quiz.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The quiz, 404 if it does not exist or 403 if it is of another user.
+#[get("/user/quizzes/<id>")]
+pub async fn get_quiz_user(
+    pubdb: &State<PublicationsRepo>,
+    qzdb: &State<QuizzesRepo>,
+    user: AuthUser,
+    id: &str,
+    locales: Locales,
+) -> Result<Json<QuizView>, Status> {
+    let quiz = user_quiz(qzdb, &user, id).await?;
+    Ok(Json(quiz_view(pubdb, quiz, &locales).await?))
+}
+
+/// It grades the answer to a question of a quiz. Every question can be answered once and no answer
+/// is taken after the time limit. The answer can be given in any locale of the question. The
+/// answer is stored as an attempt and counts like one, see `answer_attempt`: a correct answer
+/// marks the test as passed, and the score updates the mastery, the ratings and the reviews of
+/// the user.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests and templates
+/// * `qzdb`: &State<QuizzesRepo> - the quizzes
+/// * `attempts_db`: &State<AttemptsRepo> - the attempts
+/// * `skdb`: &State<SkillsRepo> - the mastery of the users
+/// * `rdb`: &State<RatingsRepo> - the ratings of the users and of the questions
+/// * `rvdb`: &State<ReviewsRepo> - the review schedule of the users
+/// * `udb`: &State<UserRepo> - the users
+/// * `user`: The user from the `Authorization` header.
+/// * `id`: The id of the quiz.
+/// * `number`: The number of the question, from 1.
+/// * `answer`: Json<AttemptAnswer> - the answer of the user
+///
+/// Returns:
+///
+/// How many questions are answered. 404 if the quiz or the question does not exist, 403 if the
+/// quiz is of another user and 409 with the reason if the quiz is finished, the time limit is over
+/// or the question is already answered.
+#[put("/user/quizzes/<id>/answers/<number>", data = "<answer>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn answer_quiz(
+    pubdb: &State<PublicationsRepo>,
+    qzdb: &State<QuizzesRepo>,
+    attempts_db: &State<AttemptsRepo>,
+    skdb: &State<SkillsRepo>,
+    rdb: &State<RatingsRepo>,
+    rvdb: &State<ReviewsRepo>,
+    udb: &State<UserRepo>,
+    user: AuthUser,
+    id: &str,
+    number: usize,
+    answer: Json<AttemptAnswer>,
+) -> Result<Json<QuizProgress>, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    let mut quiz = user_quiz(qzdb, &user, id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let index = number.wrapping_sub(1);
+    let question = quiz
+        .questions
+        .get(index)
+        .cloned()
+        .ok_or((Status::NotFound, format!("unknown question {}", number)))?;
+    let graded = load_question(pubdb, &question.question_id, question.seed.unwrap_or(0))
+        .await
+        .ok_or((
+            Status::NotFound,
+            format!("question {} is no longer published", number),
+        ))?;
+    let answer = graded.canonical_answer(&answer.into_inner().answer);
+    let mut attempt = AttemptModel::new(
+        user.id.clone(),
+        question.question_id,
+        question.kind,
+        question.seed,
+    );
+    attempt.grade(&answer, &graded.answer, graded.hint_penalty);
+    let now = attempt.answered_at.unwrap_or(attempt.started_at);
+    quiz.answer(index, &answer, attempt.correct == Some(true), now)
+        .map_err(|reason| (Status::Conflict, reason))?;
+    // another answer may have been taken since the quiz was loaded
+    if !qzdb
+        .answer_question(&quiz, index, now)
+        .await
+        .map_err(error)?
+    {
+        return Err((
+            Status::Conflict,
+            format!(
+                "question {} is already answered or the quiz is over",
+                number
+            ),
+        ));
+    }
+    record_answer(&attempt, &graded, udb, skdb, rdb, rvdb)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    attempts_db.create_attempt(attempt).await.map_err(error)?;
+    Ok(Json(QuizProgress {
+        answered: quiz
+            .questions
+            .iter()
+            .filter(|question| question.answer.is_some())
+            .count(),
+        total: quiz.questions.len(),
+        expires_at: quiz.expires_at,
+    }))
+}
+
+/// It finishes a quiz of the authenticated user and stores its score breakdown, see `QuizResult`.
+/// Unanswered questions count as wrong. A quiz can be finished after its time limit.
+///
+/// Arguments:
+///
+/// * `qzdb`: &State<QuizzesRepo> - the quizzes
+/// * `user`: The user from the `Authorization` header.
+/// * `id`: The id of the quiz.
+///
+/// Returns:
+///
+/// The score breakdown. 404 if the quiz does not exist, 403 if it is of another user and 409 if
+/// it is already finished.
+#[post("/user/quizzes/<id>/finish")]
+pub async fn finish_quiz(
+    qzdb: &State<QuizzesRepo>,
+    user: AuthUser,
+    id: &str,
+) -> Result<Json<QuizResult>, (Status, String)> {
+    // an answer taken while the quiz is finished changes the score, the quiz is loaded again
+    loop {
+        let mut quiz = user_quiz(qzdb, &user, id)
+            .await
+            .map_err(|status| (status, String::new()))?;
+        let result = quiz
+            .finish(chrono::Utc::now().timestamp())
+            .map_err(|reason| (Status::Conflict, reason))?;
+        if qzdb
+            .finish_quiz(&quiz)
+            .await
+            .map_err(|_| (Status::InternalServerError, String::new()))?
+        {
+            return Ok(Json(result));
+        }
+    }
+}
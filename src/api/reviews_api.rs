@@ -0,0 +1,100 @@
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::{
+    api::tests_api::TestRes,
+    models::{
+        attempt_model::QuestionKind,
+        publication_model::ContentKind,
+        review_model::{end_of_day, DEFAULT_REVIEW_LIMIT},
+        tests_model::{TestModel, TestModelWithActions},
+    },
+    repository::{publications_repo::PublicationsRepo, reviews_repo::ReviewsRepo},
+    utils::{
+        auth::AuthUser,
+        locale::{Locales, Localize},
+    },
+};
+
+/// `ReviewItem` is a question of the review set.
+///
+/// Properties:
+///
+/// * `due_at`: Unix timestamp the review was due.
+/// * `interval`: The number of days since the question was seen.
+/// * `repetitions`: How many reviews in a row were remembered.
+/// * `test`: The test, without hints and explanation.
+#[derive(Debug, Serialize)]
+pub struct ReviewItem {
+    pub due_at: i64,
+    pub interval: i32,
+    pub repetitions: i32,
+    pub test: TestRes<TestModel, TestModelWithActions>,
+}
+
+// * User API routes
+
+/// It returns the review set of the day of the authenticated user: the choice and action tests
+/// whose next review is due before the end of the day (UTC), the most overdue first. Every graded
+/// attempt schedules the next review of its test with SM-2, see `ReviewModel::record`, also the
+/// attempts of tests the user has already passed. Tests that are no longer published are left out.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published tests
+/// * `rvdb`: &State<ReviewsRepo> - the review schedule
+/// * `user`: The user from the `Authorization` header.
+/// * `limit`: The size of the review set, 20 by default.
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
+///
+/// Returns:
+///
+/// The questions to review.
+#[get("/user/review/due?<limit>")]
+pub async fn get_due_reviews(
+    pubdb: &State<PublicationsRepo>,
+    rvdb: &State<ReviewsRepo>,
+    user: AuthUser,
+    limit: Option<usize>,
+    locales: Locales,
+) -> Result<Json<Vec<ReviewItem>>, Status> {
+    let error = |_| Status::InternalServerError;
+    let limit = limit.unwrap_or(DEFAULT_REVIEW_LIMIT);
+    let until = end_of_day(chrono::Utc::now().timestamp());
+    let mut items = vec![];
+    for review in rvdb.get_due_reviews(&user.id, until).await.map_err(error)? {
+        if items.len() >= limit {
+            break;
+        }
+        let test = match review.kind {
+            QuestionKind::Choice => pubdb
+                .get_published::<TestModel>(ContentKind::Choice, &review.question_id)
+                .await
+                .map_err(error)?
+                .map(|test| {
+                    let mut test = test.for_learner();
+                    test.localize(&locales);
+                    TestRes::ChoiceTest(test)
+                }),
+            QuestionKind::Action => pubdb
+                .get_published::<TestModelWithActions>(ContentKind::Action, &review.question_id)
+                .await
+                .map_err(error)?
+                .map(|test| {
+                    let mut test = test.for_learner();
+                    test.localize(&locales);
+                    TestRes::ActionTest(test)
+                }),
+            QuestionKind::Template => None,
+        };
+        if let Some(test) = test {
+            items.push(ReviewItem {
+                due_at: review.due_at,
+                interval: review.interval,
+                repetitions: review.repetitions,
+                test,
+            });
+        }
+    }
+    Ok(Json(items))
+}
@@ -0,0 +1,159 @@
+use rocket::{http::Status, serde::json::Json, State};
+
+use crate::{
+    api::publications_api::ContentRepos,
+    models::{
+        publication_model::ContentKind,
+        revision_model::{diff, FieldChange, RevisionModel, RevisionSummary},
+    },
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, revisions_repo::RevisionsRepo,
+        templates_repo::TemplatesRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+    utils::auth::AuthUser,
+};
+
+/// It loads a revision, 404 if there is no such revision.
+async fn load_revision(
+    rdb: &RevisionsRepo,
+    kind: ContentKind,
+    id: &str,
+    number: i32,
+) -> Result<RevisionModel, (Status, String)> {
+    rdb.get_revision(kind, id, number)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .ok_or((
+            Status::NotFound,
+            format!("{} `{}` has no revision {}", kind.name(), id, number),
+        ))
+}
+
+// * Admin API routes
+
+/// It lists the revisions of a document.
+///
+/// Arguments:
+///
+/// * `rdb`: &State<RevisionsRepo> - the history
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+///
+/// Returns:
+///
+/// The revisions without their snapshots, oldest first.
+#[get("/admin/revisions/<kind>/<id>")]
+pub async fn get_revisions(
+    rdb: &State<RevisionsRepo>,
+    kind: ContentKind,
+    id: &str,
+) -> Result<Json<Vec<RevisionSummary>>, Status> {
+    match rdb.get_revisions(kind, id).await {
+        Ok(revisions) => Ok(Json(revisions.iter().map(RevisionSummary::from).collect())),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It gets one revision of a document with its snapshot.
+///
+/// Arguments:
+///
+/// * `rdb`: &State<RevisionsRepo> - the history
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+/// * `number`: i32 - the number of the revision
+///
+/// Returns:
+///
+/// The revision, or 404 if there is no such revision.
+#[get("/admin/revisions/<kind>/<id>/<number>")]
+pub async fn get_revision(
+    rdb: &State<RevisionsRepo>,
+    kind: ContentKind,
+    id: &str,
+    number: i32,
+) -> Result<Json<RevisionModel>, (Status, String)> {
+    load_revision(rdb, kind, id, number).await.map(Json)
+}
+
+/// It compares two revisions of a document field by field.
+///
+/// Arguments:
+///
+/// * `rdb`: &State<RevisionsRepo> - the history
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+/// * `from`: i32 - the number of the old revision
+/// * `to`: i32 - the number of the new revision
+///
+/// Returns:
+///
+/// The changed fields by dotted path, or 404 if one of the revisions does not exist.
+#[get("/admin/revisions/<kind>/<id>/diff/<from>/<to>")]
+pub async fn diff_revisions(
+    rdb: &State<RevisionsRepo>,
+    kind: ContentKind,
+    id: &str,
+    from: i32,
+    to: i32,
+) -> Result<Json<Vec<FieldChange>>, (Status, String)> {
+    let before = load_revision(rdb, kind, id, from).await?;
+    let after = load_revision(rdb, kind, id, to).await?;
+    Ok(Json(diff(&before.snapshot, &after.snapshot)))
+}
+
+/// It rolls a document back to one of its revisions. The rollback is recorded as a new revision,
+/// so it can be rolled back as well. A published document keeps its published snapshot until the
+/// rolled back version is approved.
+///
+/// Arguments:
+///
+/// * `rdb`: &State<RevisionsRepo> - the history
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the working copies of the courses, infos, choice tests,
+///   action tests and templates
+/// * `author`: The user from the `Authorization` header, if any.
+/// * `kind`: ContentKind - the kind of the document
+/// * `id`: &str - the id of the document
+/// * `number`: i32 - the number of the revision to restore
+///
+/// Returns:
+///
+/// The new revision, 404 if the revision or the document does not exist and 422 if the revision
+/// no longer fits the model of the document.
+#[allow(clippy::too_many_arguments)]
+#[post("/admin/revisions/<kind>/<id>/<number>/rollback")]
+pub async fn rollback_revision(
+    rdb: &State<RevisionsRepo>,
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    author: Option<AuthUser>,
+    kind: ContentKind,
+    id: &str,
+    number: i32,
+) -> Result<Json<RevisionModel>, (Status, String)> {
+    let revision = load_revision(rdb, kind, id, number).await?;
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    if !repos
+        .restore_document(kind, id, revision.snapshot.clone())
+        .await?
+    {
+        return Err((
+            Status::NotFound,
+            format!("unknown {} `{}`", kind.name(), id),
+        ));
+    }
+    rdb.record_rollback(&revision, author.map(|user| user.id))
+        .await
+        .map(Json)
+        .map_err(|_| (Status::InternalServerError, String::new()))
+}
@@ -0,0 +1,101 @@
+use rocket::{
+    http::{Header, Status},
+    State,
+};
+
+use crate::{
+    api::{bundles_api::load_bundle, publications_api::ContentRepos},
+    models::{
+        scorm_model::{ScormPackage, DEFAULT_MASTERY_SCORE},
+        template_model::QuestionTemplate,
+    },
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, media_repo::MediaRepo,
+        templates_repo::TemplatesRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+};
+
+/// `ScormArchive` is a course packed by `ScormPackage::to_zip`, served as a download.
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct ScormArchive {
+    data: Vec<u8>,
+    disposition: Header<'static>,
+}
+
+// * Admin API routes
+
+/// It exports a course as a SCORM 1.2 package that runs in any LMS: one page with the infos and
+/// the questions of the levels, the uploaded media it shows and a runtime that reports the
+/// completion and the score through the SCORM API. The questions of templates are generated once
+/// for the whole package. The answers are checked in the browser, so they are in the page.
+///
+/// Arguments:
+///
+/// * `cdb`, `idb`, `tdb`, `adb`, `pdb` - the courses, infos, choice tests, action tests and
+///   templates
+/// * `mdb`: &State<MediaRepo> - the uploaded media
+/// * `id`: &str - the id of the course
+/// * `mastery`: the score in percent needed to pass, 80 by default
+///
+/// Returns:
+///
+/// The package, 404 if there is no such course and 422 if the mastery score is not between 0 and
+/// 100 or some content can not be rendered.
+#[allow(clippy::too_many_arguments)]
+#[get("/admin/courses/<id>/export/scorm?<mastery>")]
+pub async fn export_cource_scorm(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    tdb: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    pdb: &State<TemplatesRepo>,
+    mdb: &State<MediaRepo>,
+    id: &str,
+    mastery: Option<i32>,
+) -> Result<ScormArchive, (Status, String)> {
+    let mastery = mastery.unwrap_or(DEFAULT_MASTERY_SCORE);
+    if !(0..=100).contains(&mastery) {
+        return Err((
+            Status::UnprocessableEntity,
+            "the mastery score must be between 0 and 100".to_string(),
+        ));
+    }
+    let repos = ContentRepos {
+        cdb,
+        idb,
+        tdb,
+        adb,
+        pdb,
+    };
+    let bundle = load_bundle(&repos, mdb, id).await?;
+    let mut media = vec![];
+    for model in &bundle.media {
+        let data = mdb
+            .read_media(model)
+            .await
+            .map_err(|_| (Status::InternalServerError, String::new()))?
+            .ok_or((
+                Status::UnprocessableEntity,
+                format!("media `{}` is missing from the storage", model.url()),
+            ))?;
+        media.push((model.clone(), data));
+    }
+    let package = ScormPackage::new(&bundle, &media, mastery, QuestionTemplate::random_seed())
+        .map_err(|errors| {
+            (
+                Status::UnprocessableEntity,
+                format!("content that can not be rendered: {}", errors.join("; ")),
+            )
+        })?;
+    Ok(ScormArchive {
+        data: package
+            .to_zip()
+            .map_err(|e| (Status::InternalServerError, e))?,
+        disposition: Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"course-{}-scorm.zip\"", id),
+        ),
+    })
+}
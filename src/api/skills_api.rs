@@ -0,0 +1,379 @@
+use std::collections::{HashMap, HashSet};
+
+use mongodb::results::InsertOneResult;
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::{
+    models::{
+        cource_model::CourseModel,
+        info_model::InfoModel,
+        publication_model::ContentKind,
+        skill_model::{class_skills, knowledge_map, ClassSkill, SkillMastery, SkillModel},
+        template_model::QuestionTemplate,
+        tests_model::{TestModel, TestModelWithActions},
+        user_model::UserRole,
+    },
+    repository::{
+        publications_repo::PublicationsRepo, skills_repo::SkillsRepo, themes_repo::ThemesRepo,
+        user_repo::UserRepo,
+    },
+    utils::auth::AuthUser,
+};
+
+/// It checks that the skills an info, a test or a template is tagged with are in the catalog.
+///
+/// Arguments:
+///
+/// * `skdb`: &SkillsRepo - the skills
+/// * `skills`: &[String] - the ids of the skills
+///
+/// Returns:
+///
+/// Nothing, or 422 with the ids that are not skills.
+pub async fn check_skills(
+    skdb: &SkillsRepo,
+    skills: &[String],
+) -> Result<(), (Status, Json<Vec<String>>)> {
+    let mut errors = vec![];
+    for id in skills {
+        match skdb.get_skill(id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => errors.push(format!("unknown skill `{}`", id)),
+            Err(_) => return Err((Status::InternalServerError, Json(vec![]))),
+        }
+    }
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+    Ok(())
+}
+
+/// It checks a skill before it is saved.
+///
+/// Arguments:
+///
+/// * `skdb`: &SkillsRepo - the skills
+/// * `thdb`: &ThemesRepo - the themes, `theme_id` must point at one of them
+/// * `skill`: &mut SkillModel - the skill, an empty slug is made from the name
+/// * `id`: Option<&str> - the id of the skill when it is updated
+///
+/// Returns:
+///
+/// Nothing, 422 with the problems of the skill and 409 if the slug is taken.
+async fn check_skill(
+    skdb: &SkillsRepo,
+    thdb: &ThemesRepo,
+    skill: &mut SkillModel,
+    id: Option<&str>,
+) -> Result<(), (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let mut errors = skill.validate();
+    if let Some(theme_id) = &skill.theme_id {
+        if thdb.get_theme(theme_id).await.map_err(error)?.is_none() {
+            errors.push(format!("unknown theme `{}`", theme_id));
+        }
+    }
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+    if let Some(taken) = skdb.get_skill_by_slug(&skill.slug).await.map_err(error)? {
+        if taken.id.map(|oid| oid.to_hex()).as_deref() != id {
+            return Err((
+                Status::Conflict,
+                Json(vec![format!("slug `{}` is taken", skill.slug)]),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// * Admin API routes
+
+/// It gets the skills catalog.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills
+///
+/// Returns:
+///
+/// A JSON array of all skills.
+#[get("/admin/skills")]
+pub async fn get_all_skills(skdb: &State<SkillsRepo>) -> Result<Json<Vec<SkillModel>>, Status> {
+    match skdb.get_all_skills().await {
+        Ok(skills) => Ok(Json(skills)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It gets a skill by id.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills
+/// * `id`: &str - the id of the skill
+///
+/// Returns:
+///
+/// The skill, or 404 if there is no such skill.
+#[get("/admin/skills/<id>")]
+pub async fn get_skill_admin(
+    skdb: &State<SkillsRepo>,
+    id: &str,
+) -> Result<Json<SkillModel>, Status> {
+    match skdb.get_skill(id).await {
+        Ok(Some(skill)) => Ok(Json(skill)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It adds a skill to the catalog.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `skill`: Json<SkillModel> - the skill, the slug is made from the name if it is empty
+///
+/// Returns:
+///
+/// The result of the insert, 422 with the problems of the skill and 409 if the slug is taken.
+#[post("/admin/skills", data = "<skill>")]
+pub async fn create_skill(
+    skdb: &State<SkillsRepo>,
+    thdb: &State<ThemesRepo>,
+    skill: Json<SkillModel>,
+) -> Result<Json<InsertOneResult>, (Status, Json<Vec<String>>)> {
+    let mut skill = skill.into_inner();
+    skill.id = None;
+    check_skill(skdb, thdb, &mut skill, None).await?;
+    match skdb.create_skill(skill).await {
+        Ok(result) => Ok(Json(result)),
+        Err(_) => Err((Status::InternalServerError, Json(vec![]))),
+    }
+}
+
+/// It updates a skill.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `id`: &str - the id of the skill
+/// * `skill`: Json<SkillModel> - the new skill
+///
+/// Returns:
+///
+/// Status, 404 if there is no such skill, 422 with the problems of the skill and 409 if the slug
+/// is taken.
+#[put("/admin/skills/<id>", data = "<skill>")]
+pub async fn update_skill(
+    skdb: &State<SkillsRepo>,
+    thdb: &State<ThemesRepo>,
+    id: &str,
+    skill: Json<SkillModel>,
+) -> Result<Status, (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let mut skill = skill.into_inner();
+    let before = skdb
+        .get_skill(id)
+        .await
+        .map_err(error)?
+        .ok_or((Status::NotFound, Json(vec![])))?;
+    skill.id = before.id;
+    check_skill(skdb, thdb, &mut skill, Some(id)).await?;
+    match skdb.update_skill(id, skill).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, Json(vec![]))),
+    }
+}
+
+/// It deletes a skill that no content is tagged with, with the mastery of all users.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills
+/// * `id`: &str - the id of the skill
+///
+/// Returns:
+///
+/// Status, 404 if there is no such skill and 409 if content is still tagged with it.
+#[delete("/admin/skills/<id>")]
+pub async fn delete_skill(skdb: &State<SkillsRepo>, id: &str) -> Result<Status, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    if skdb.get_skill(id).await.map_err(error)?.is_none() {
+        return Err((Status::NotFound, String::new()));
+    }
+    let references = skdb.count_references(id).await.map_err(error)?;
+    if references > 0 {
+        return Err((
+            Status::Conflict,
+            format!(
+                "{} infos, tests or templates are tagged with the skill",
+                references
+            ),
+        ));
+    }
+    match skdb.delete_skill(id).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, String::new())),
+    }
+}
+
+// * User API routes
+
+/// It builds the knowledge map of the authenticated user: every skill with how well the user
+/// masters it, from the graded attempts of the user, and the infos that teach it.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills and the mastery
+/// * `pubdb`: &State<PublicationsRepo> - the published infos
+/// * `user`: The user from the `Authorization` header.
+///
+/// Returns:
+///
+/// The skills sorted by name.
+#[get("/user/skills/map")]
+pub async fn get_knowledge_map_user(
+    skdb: &State<SkillsRepo>,
+    pubdb: &State<PublicationsRepo>,
+    user: AuthUser,
+) -> Result<Json<Vec<SkillMastery>>, Status> {
+    let error = |_| Status::InternalServerError;
+    let skills = skdb.get_all_skills().await.map_err(error)?;
+    let mastery = skdb.get_user_mastery(&user.id).await.map_err(error)?;
+    let mut infos: HashMap<String, Vec<String>> = HashMap::new();
+    for info in pubdb
+        .get_all_published::<InfoModel>(ContentKind::Info)
+        .await
+        .map_err(error)?
+    {
+        for skill in &info.skills {
+            infos
+                .entry(skill.clone())
+                .or_default()
+                .push(info.id.map(|id| id.to_hex()).unwrap_or_default());
+        }
+    }
+    Ok(Json(knowledge_map(&skills, &mastery, &infos)))
+}
+
+// * Teacher API routes
+
+/// `ClassReport` is how well the learners of a course master the skills of the course.
+///
+/// Properties:
+///
+/// * `course_id`: The id of the course.
+/// * `learners`: How many users are enrolled in the course.
+/// * `skills`: The skills the content of the course is tagged with, the least mastered first.
+#[derive(Debug, Serialize)]
+pub struct ClassReport {
+    pub course_id: String,
+    pub learners: usize,
+    pub skills: Vec<ClassSkill>,
+}
+
+/// It collects the skills the published content of a course is tagged with.
+async fn course_skills(
+    pubdb: &PublicationsRepo,
+    course: &CourseModel,
+) -> Result<HashSet<String>, Status> {
+    let error = |_| Status::InternalServerError;
+    let mut skills = HashSet::new();
+    for id in course.content_ids() {
+        if let Some(info) = pubdb
+            .get_published::<InfoModel>(ContentKind::Info, &id)
+            .await
+            .map_err(error)?
+        {
+            skills.extend(info.skills);
+        } else if let Some(test) = pubdb
+            .get_published::<TestModel>(ContentKind::Choice, &id)
+            .await
+            .map_err(error)?
+        {
+            skills.extend(test.skills);
+        } else if let Some(test) = pubdb
+            .get_published::<TestModelWithActions>(ContentKind::Action, &id)
+            .await
+            .map_err(error)?
+        {
+            skills.extend(test.skills);
+        } else if let Some(template) = pubdb
+            .get_published::<QuestionTemplate>(ContentKind::Template, &id)
+            .await
+            .map_err(error)?
+        {
+            skills.extend(template.skills);
+        }
+    }
+    Ok(skills)
+}
+
+/// It shows a teacher how well the class of a course, the users enrolled in it, masters each
+/// skill the course exercises.
+///
+/// Arguments:
+///
+/// * `skdb`: &State<SkillsRepo> - the skills and the mastery
+/// * `pubdb`: &State<PublicationsRepo> - the published course and its content
+/// * `udb`: &State<UserRepo> - the users
+/// * `user`: The user from the `Authorization` header, must be a teacher.
+/// * `id`: &str - the id of the course
+///
+/// Returns:
+///
+/// The report, 403 if the user is not a teacher and 404 if the course is not published.
+#[get("/teacher/courses/<id>/skills")]
+pub async fn get_class_skills(
+    skdb: &State<SkillsRepo>,
+    pubdb: &State<PublicationsRepo>,
+    udb: &State<UserRepo>,
+    user: AuthUser,
+    id: &str,
+) -> Result<Json<ClassReport>, Status> {
+    if user.role != UserRole::Teacher {
+        return Err(Status::Forbidden);
+    }
+    let error = |_| Status::InternalServerError;
+    let course = pubdb
+        .get_published::<CourseModel>(ContentKind::Course, id)
+        .await
+        .map_err(error)?
+        .ok_or(Status::NotFound)?;
+    let skill_ids = course_skills(pubdb, &course).await?;
+    let skills: Vec<SkillModel> = skdb
+        .get_all_skills()
+        .await
+        .map_err(error)?
+        .into_iter()
+        .filter(|skill| {
+            skill
+                .id
+                .is_some_and(|oid| skill_ids.contains(&oid.to_hex()))
+        })
+        .collect();
+    let learners: Vec<(String, String)> = udb
+        .get_enrolled_users(id)
+        .await
+        .map_err(error)?
+        .into_iter()
+        .map(|learner| {
+            (
+                learner.id.map(|oid| oid.to_hex()).unwrap_or_default(),
+                learner.username,
+            )
+        })
+        .collect();
+    let user_ids: Vec<String> = learners.iter().map(|(id, _)| id.clone()).collect();
+    let mastery = skdb.get_users_mastery(&user_ids).await.map_err(error)?;
+    Ok(Json(ClassReport {
+        course_id: id.to_string(),
+        learners: learners.len(),
+        skills: class_skills(&skills, &learners, &mastery),
+    }))
+}
@@ -0,0 +1,314 @@
+use mongodb::results::InsertOneResult;
+use rocket::{http::Status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{skills_api::check_skills, themes_api::resolve_theme},
+    models::{
+        publication_model::ContentKind,
+        template_model::{QuestionTemplate, TemplateInstance},
+    },
+    repository::{
+        publications_repo::PublicationsRepo, revisions_repo::RevisionsRepo,
+        skills_repo::SkillsRepo, templates_repo::TemplatesRepo, themes_repo::ThemesRepo,
+        user_repo::UserRepo,
+    },
+    utils::auth::{authorize_token, AuthUser},
+};
+
+// * Admin API routes
+
+/// It gets all the question templates from the database.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+///
+/// Returns:
+///
+/// A JSON array of all templates.
+#[get("/admin/get/templates/all")]
+pub async fn get_all_templates(
+    db: &State<TemplatesRepo>,
+) -> Result<Json<Vec<QuestionTemplate>>, Status> {
+    match db.get_all_templates().await {
+        Ok(templates) => Ok(Json(templates)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It gets a question template by id.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+/// * `id`: &str - the id of the template
+///
+/// Returns:
+///
+/// The template with the given id.
+#[get("/admin/get/template?<id>")]
+pub async fn get_template_admin(
+    db: &State<TemplatesRepo>,
+    id: &str,
+) -> Result<Json<QuestionTemplate>, Status> {
+    match db.get_template_by_id(id).await {
+        Ok(Some(template)) => Ok(Json(template)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It generates a question from a template, so authors can check how the template looks for
+/// learners.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+/// * `id`: &str - the id of the template
+/// * `seed`: the seed to generate the question with, random if not given
+///
+/// Returns:
+///
+/// The generated question, or 422 with the reason if the template is broken.
+#[get("/admin/get/template/preview?<id>&<seed>")]
+pub async fn preview_template(
+    db: &State<TemplatesRepo>,
+    id: &str,
+    seed: Option<i64>,
+) -> Result<Json<TemplateInstance>, (Status, String)> {
+    let template = match db.get_template_by_id(id).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return Err((Status::NotFound, "Template not found".to_string())),
+        Err(_) => return Err((Status::InternalServerError, String::new())),
+    };
+    template
+        .instantiate(seed.unwrap_or_else(QuestionTemplate::random_seed))
+        .map(Json)
+        .map_err(|e| (Status::UnprocessableEntity, e))
+}
+
+/// It creates a new question template. The template is instantiated once before saving, so a
+/// broken formula is reported to the author right away.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
+/// * `template`: Json<QuestionTemplate> - the template to create
+///
+/// Returns:
+///
+/// A JSON object with the result of the insert operation, or 422 if the template is broken or
+/// its theme or a skill is unknown.
+#[post("/admin/create/template", data = "<template>")]
+pub async fn create_template(
+    db: &State<TemplatesRepo>,
+    thdb: &State<ThemesRepo>,
+    skdb: &State<SkillsRepo>,
+    template: Json<QuestionTemplate>,
+) -> Result<Json<InsertOneResult>, (Status, String)> {
+    let mut template = template.into_inner();
+    if let Err(e) = template.instantiate(0) {
+        return Err((Status::UnprocessableEntity, e));
+    }
+    resolve_theme(thdb, &mut template.theme, &template.theme_id)
+        .await
+        .map_err(|(status, errors)| (status, errors.join(", ")))?;
+    check_skills(skdb, &template.skills)
+        .await
+        .map_err(|(status, errors)| (status, errors.join(", ")))?;
+    match db.create_template(template).await {
+        Ok(result) => Ok(Json(result)),
+        Err(_) => Err((Status::InternalServerError, String::new())),
+    }
+}
+
+/// It updates a question template.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+/// * `rdb`: &State<RevisionsRepo> - the history, every update is recorded as a revision.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
+/// * `author`: The user from the `Authorization` header, if any.
+/// * `id`: &str - the id of the template to update
+/// * `template`: Json<QuestionTemplate> - the new template
+///
+/// Returns:
+///
+/// Status, or 422 if the template is broken or its theme or a skill is unknown.
+#[put("/admin/update/template?<id>", data = "<template>")]
+pub async fn update_template(
+    db: &State<TemplatesRepo>,
+    rdb: &State<RevisionsRepo>,
+    thdb: &State<ThemesRepo>,
+    skdb: &State<SkillsRepo>,
+    author: Option<AuthUser>,
+    id: &str,
+    template: Json<QuestionTemplate>,
+) -> Result<Status, (Status, String)> {
+    let mut template = template.into_inner();
+    if let Err(e) = template.instantiate(0) {
+        return Err((Status::UnprocessableEntity, e));
+    }
+    resolve_theme(thdb, &mut template.theme, &template.theme_id)
+        .await
+        .map_err(|(status, errors)| (status, errors.join(", ")))?;
+    check_skills(skdb, &template.skills)
+        .await
+        .map_err(|(status, errors)| (status, errors.join(", ")))?;
+    match db.update_template(id, template.clone()).await {
+        Ok(Some(before)) => rdb
+            .record(
+                ContentKind::Template,
+                id,
+                author.map(|user| user.id),
+                Some(&before),
+                &template,
+            )
+            .await
+            .map(|_| Status::Ok)
+            .map_err(|_| (Status::InternalServerError, String::new())),
+        Ok(None) => Err((Status::NotFound, "Template not found".to_string())),
+        Err(_) => Err((Status::InternalServerError, String::new())),
+    }
+}
+
+/// It deletes a question template.
+///
+/// Arguments:
+///
+/// * `db`: &State<TemplatesRepo> - the database connection to the templates collection.
+/// * `pubdb`: &State<PublicationsRepo> - the publication of the template is removed with it.
+/// * `id`: &str - the id of the template to delete
+///
+/// Returns:
+///
+/// Status
+#[delete("/admin/delete/template?<id>")]
+pub async fn delete_template(
+    db: &State<TemplatesRepo>,
+    pubdb: &State<PublicationsRepo>,
+    id: &str,
+) -> Status {
+    if pubdb
+        .delete_publication(ContentKind::Template, id)
+        .await
+        .is_err()
+    {
+        return Status::InternalServerError;
+    }
+    match db.delete_template(id).await {
+        Ok(_) => Status::Ok,
+        Err(_) => Status::InternalServerError,
+    }
+}
+
+// * User API routes
+
+/// It generates a question from a published template for the user.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the templates.
+/// * `user_db`: &State<UserRepo> - the user database, used to check the token.
+/// * `token`: the token of the user
+/// * `id`: the id of the template
+/// * `seed`: the seed of an already served question, a new question is generated if not given
+///
+/// Returns:
+///
+/// The generated question together with its seed.
+#[get("/user/<token>/get/template?<id>&<seed>")]
+pub async fn get_template_user(
+    pubdb: &State<PublicationsRepo>,
+    user_db: &State<UserRepo>,
+    token: &str,
+    id: &str,
+    seed: Option<i64>,
+) -> Result<Json<TemplateInstance>, Status> {
+    if !authorize_token(token.to_string(), user_db).await.0 {
+        return Err(Status::Unauthorized);
+    }
+    match pubdb
+        .get_published::<QuestionTemplate>(ContentKind::Template, id)
+        .await
+    {
+        Ok(Some(template)) => template
+            .instantiate(seed.unwrap_or_else(QuestionTemplate::random_seed))
+            .map(Json)
+            .map_err(|_| Status::InternalServerError),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// `TemplateAnswer` is an answer of a user to a question generated from a template.
+///
+/// Properties:
+///
+/// * `template_id`: The id of the template.
+/// * `seed`: The seed the question was generated with.
+/// * `answer`: The answer of the user.
+#[derive(Debug, Deserialize)]
+pub struct TemplateAnswer {
+    pub template_id: String,
+    pub seed: i64,
+    pub answer: String,
+}
+
+/// `TemplateAnswerResult` is the result of checking a `TemplateAnswer`.
+///
+/// Properties:
+///
+/// * `correct`: Whether the answer is correct.
+/// * `answer`: The correct answer.
+#[derive(Debug, Serialize)]
+pub struct TemplateAnswerResult {
+    pub correct: bool,
+    pub answer: String,
+}
+
+/// It regenerates the question from the published template and the seed and checks the answer of
+/// the user.
+///
+/// Arguments:
+///
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the templates.
+/// * `user_db`: &State<UserRepo> - the user database, used to check the token.
+/// * `token`: the token of the user
+/// * `answer`: Json<TemplateAnswer> - the answer of the user
+///
+/// Returns:
+///
+/// Whether the answer is correct and the correct answer.
+#[post("/user/<token>/check/template", data = "<answer>")]
+pub async fn check_template_answer(
+    pubdb: &State<PublicationsRepo>,
+    user_db: &State<UserRepo>,
+    token: &str,
+    answer: Json<TemplateAnswer>,
+) -> Result<Json<TemplateAnswerResult>, Status> {
+    if !authorize_token(token.to_string(), user_db).await.0 {
+        return Err(Status::Unauthorized);
+    }
+    let answer = answer.into_inner();
+    let template = match pubdb
+        .get_published::<QuestionTemplate>(ContentKind::Template, &answer.template_id)
+        .await
+    {
+        Ok(Some(template)) => template,
+        Ok(None) => return Err(Status::NotFound),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+    let instance = template
+        .instantiate(answer.seed)
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(TemplateAnswerResult {
+        correct: instance.test.answer == answer.answer.trim(),
+        answer: instance.test.answer,
+    }))
+}
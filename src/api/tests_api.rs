@@ -1,7 +1,9 @@
 #![allow(dead_code, unused_imports)]
 
 use std::any;
+use std::collections::{HashMap, HashSet};
 
+use mongodb::bson::oid::ObjectId;
 use mongodb::results::InsertOneResult;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
@@ -15,11 +17,29 @@ use rocket::{
 use serde::{Deserialize, Serialize};
 
 // use crate::models::tests_model::{TestModelWithActionsResponse, TestModelResponse};
-use crate::utils::auth::authorize_token;
+use crate::utils::auth::{authorize_token, AuthUser};
+use crate::utils::locale::{Localize, Locales};
+use crate::utils::render::{
+    render_question, RenderCache, RenderFormat, Rendered, RenderedQuestion,
+};
 use crate::{
-    models::tests_model::{TestModel, TestModelWithActions},
+    api::{
+        skills_api::check_skills,
+        themes_api::{resolve_theme, theme_filter},
+    },
+    models::{
+        publication_model::ContentKind,
+        rating_model::{
+            calibrated_level, combined_rating, pick_adaptive, question_rating, QuestionRatingModel,
+        },
+        template_model::{QuestionTemplate, TemplateInstance},
+        tests_model::{validate_translations, TestModel, TestModelWithActions, TestTranslation},
+    },
     repository::{
-        tests_repo::TestsRepo, tests_with_actions_repo::TestsRepo as TActionRepo,
+        attempts_repo::AttemptsRepo, publications_repo::PublicationsRepo,
+        ratings_repo::RatingsRepo, revisions_repo::RevisionsRepo,
+        skills_repo::SkillsRepo, templates_repo::TemplatesRepo, tests_repo::TestsRepo,
+        tests_with_actions_repo::TestsRepo as TActionRepo, themes_repo::ThemesRepo,
         user_repo::UserRepo,
     },
 };
@@ -38,10 +58,13 @@ pub fn options() -> Status {
 ///
 /// * `tests`: A list of all tests that have been run.
 /// * `tests_with_actions`: This is a vector of TestModelWithActions.
+/// * `templates`: Questions generated from templates, only filled for users.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AllTests {
     pub tests: Vec<TestModel>,
     pub tests_with_actions: Vec<TestModelWithActions>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<TemplateInstance>,
 }
 
 impl AllTests {
@@ -51,14 +74,20 @@ impl AllTests {
     ///
     /// * `tests`: A vector of `TestModel`s.
     /// * `tests_with_actions`: A vector of `TestModelWithActions`s.
+    /// * `templates`: A vector of questions generated from templates.
     ///
     /// Returns:
     ///
     /// An instance of `AllTests`.
-    pub fn new(tests: Vec<TestModel>, tests_with_actions: Vec<TestModelWithActions>) -> Self {
+    pub fn new(
+        tests: Vec<TestModel>,
+        tests_with_actions: Vec<TestModelWithActions>,
+        templates: Vec<TemplateInstance>,
+    ) -> Self {
         Self {
             tests,
             tests_with_actions,
+            templates,
         }
     }
 }
@@ -95,11 +124,16 @@ impl<'r> FromParam<'r> for TestType {
     }
 }
 
-/// Creating an enum that has two variants: ChoiceTest and ActionTest.
+/// Creating an enum that has three variants: ChoiceTest, ActionTest and TemplateTest. TemplateTest
+/// is a question generated from a template and is only returned to users, a request body with it
+/// is rejected.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TestRes<T, A> {
     ChoiceTest(T),
     ActionTest(A),
+    #[serde(skip_deserializing)]
+    TemplateTest(Box<TemplateInstance>),
 }
 
 /// This is a trait that allows us to convert a string into a TestType enum.
@@ -113,7 +147,7 @@ impl<T, A> TestRes<T, A> {
     pub fn get_test(&self) -> &T {
         match self {
             TestRes::ChoiceTest(test) => test,
-            TestRes::ActionTest(_) => unimplemented!(),
+            TestRes::ActionTest(_) | TestRes::TemplateTest(_) => unimplemented!(),
         }
     }
 
@@ -125,12 +159,50 @@ impl<T, A> TestRes<T, A> {
     /// A reference to the action test.
     pub fn get_test_with_actions(&self) -> &A {
         match self {
-            TestRes::ChoiceTest(_) => unimplemented!(),
+            TestRes::ChoiceTest(_) | TestRes::TemplateTest(_) => unimplemented!(),
             TestRes::ActionTest(test) => test,
         }
     }
 }
 
+/// It checks that the question and the answers of a test and of its translations can be
+/// rendered, so authors see broken formulas when they save the test and not when learners open it.
+///
+/// Arguments:
+///
+/// * `test`: &TestRes<TestModel, TestModelWithActions> - the test to check
+///
+/// Returns:
+///
+/// A Result<(), (Status, Json<Vec<String>>)> with the list of problems
+fn check_rendering(
+    test: &TestRes<TestModel, TestModelWithActions>,
+) -> Result<(), (Status, Json<Vec<String>>)> {
+    let (question, answers, translations) = match test {
+        TestRes::ChoiceTest(test) => (&test.question, &test.answers, &test.translations),
+        TestRes::ActionTest(test) => (&test.question, &test.answers, &test.translations),
+        TestRes::TemplateTest(_) => return Ok(()),
+    };
+    let mut errors = validate_translations(answers, translations);
+    if let Err(e) = render_question(question, answers) {
+        errors.extend(e);
+    }
+    let mut locales: Vec<(&String, &TestTranslation)> = translations.iter().collect();
+    locales.sort_by_key(|(locale, _)| *locale);
+    for (locale, translation) in locales {
+        let question = translation.question.as_ref().unwrap_or(question);
+        let answers = translation.answers.as_ref().unwrap_or(answers);
+        if let Err(e) = render_question(question, answers) {
+            errors.extend(e.into_iter().map(|e| format!("translation `{}`: {}", locale, e)));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err((Status::UnprocessableEntity, Json(errors)))
+    }
+}
+
 /// It gets all the tests from the database and returns them as a JSON object
 ///
 /// Arguments:
@@ -149,10 +221,7 @@ pub async fn get_all_tests(
 ) -> Result<Json<AllTests>, Status> {
     let tests = test_db.get_all_tests().await.unwrap();
     let tests_with_actions = ta_db.get_all_tests().await.unwrap();
-    let all_tests = AllTests {
-        tests,
-        tests_with_actions,
-    };
+    let all_tests = AllTests::new(tests, tests_with_actions, vec![]);
     Ok(Json(all_tests))
 }
 
@@ -190,37 +259,45 @@ pub async fn get_test_by_id(
 ///
 /// * `db`: &State<TestsRepo> - this is the database connection that we created in the main.rs file.
 /// * `adb`: &State<TActionRepo> - this is the connection to the database for the action test.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
 /// * `test`: Json<TestRes<TestModel, TestModelWithActions>>
 /// * `test_type`: TestType - this is the type of test we're creating.
 ///
 /// Returns:
 ///
-/// A JSON object with the result of the insert operation.
+/// A JSON object with the result of the insert operation, or 422 with the list of problems if
+/// the question can not be rendered or the theme or a skill is unknown.
 #[post("/admin/<test_type>/create/test", data = "<test>")]
 pub async fn create_test(
     db: &State<TestsRepo>,
     test_type: &str,
     adb: &State<TActionRepo>,
+    thdb: &State<ThemesRepo>,
+    skdb: &State<SkillsRepo>,
     test: Json<TestRes<TestModel, TestModelWithActions>>,
-) -> Result<Json<InsertOneResult>, Status> {
+) -> Result<Json<InsertOneResult>, (Status, Json<Vec<String>>)> {
     let test_type = TestType::from_param(test_type).unwrap();
+    check_rendering(&test)?;
     match test_type {
         TestType::ChoiceTest => {
-            let test = test.into_inner();
-            let result = db.create_test(test.get_test().to_owned()).await;
+            let mut test = test.into_inner().get_test().to_owned();
+            resolve_theme(thdb, &mut test.theme, &test.theme_id).await?;
+            check_skills(skdb, &test.skills).await?;
+            let result = db.create_test(test).await;
             match result {
                 Ok(result) => Ok(Json(result)),
-                Err(_) => Err(Status::InternalServerError),
+                Err(_) => Err((Status::InternalServerError, Json(vec![]))),
             }
         }
         TestType::ActionTest => {
-            let test = test.into_inner();
-            let result = adb
-                .create_test(test.get_test_with_actions().to_owned())
-                .await;
+            let mut test = test.into_inner().get_test_with_actions().to_owned();
+            resolve_theme(thdb, &mut test.theme, &test.theme_id).await?;
+            check_skills(skdb, &test.skills).await?;
+            let result = adb.create_test(test).await;
             match result {
                 Ok(result) => Ok(Json(result)),
-                Err(_) => Err(Status::InternalServerError),
+                Err(_) => Err((Status::InternalServerError, Json(vec![]))),
             }
         }
     }
@@ -232,6 +309,10 @@ pub async fn create_test(
 ///
 /// * `db`: &State<TestsRepo> - this is the database connection.
 /// * `adb`: &State<TActionRepo> - this is the database connection for the action tests.
+/// * `rdb`: &State<RevisionsRepo> - the history, every update is recorded as a revision.
+/// * `thdb`: &State<ThemesRepo> - the themes, `theme_id` must point at one of them.
+/// * `skdb`: &State<SkillsRepo> - the skills, `skills` must be ids of skills.
+/// * `author`: The user from the `Authorization` header, if any.
 /// * `id`: &str - the id of the test to update
 /// * `test`: Json<TestRes<TestModel, TestModelWithActions>>
 /// * `test_type`: TestType - this is an enum that is defined in the models.rs file. It's used to
@@ -239,38 +320,52 @@ pub async fn create_test(
 ///
 /// Returns:
 ///
-/// Status
+/// Status, 404 if there is no such test, or 422 with the list of problems if the question can not
+/// be rendered or the theme or a skill is unknown.
+#[allow(clippy::too_many_arguments)]
 #[put("/admin/<test_type>/update/test?<id>", data = "<test>")]
 pub async fn update_test(
     db: &State<TestsRepo>,
     adb: &State<TActionRepo>,
+    rdb: &State<RevisionsRepo>,
+    thdb: &State<ThemesRepo>,
+    skdb: &State<SkillsRepo>,
+    author: Option<AuthUser>,
     id: &str,
     test: Json<TestRes<TestModel, TestModelWithActions>>,
     test_type: &str,
-) -> Result<Status, Status> {
+) -> Result<Status, (Status, Json<Vec<String>>)> {
     let test_type = TestType::from_param(test_type).unwrap();
-    match test_type {
+    check_rendering(&test)?;
+    let author = author.map(|user| user.id);
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let recorded = match test_type {
         TestType::ChoiceTest => {
-            let test = test.into_inner();
-            let result = db
-                .update_test_by_id(&id.to_string(), test.get_test().to_owned())
-                .await;
-            match result {
-                Ok(_) => Ok(Status::Ok),
-                Err(_) => Err(Status::InternalServerError),
-            }
+            let mut test = test.into_inner().get_test().to_owned();
+            resolve_theme(thdb, &mut test.theme, &test.theme_id).await?;
+            check_skills(skdb, &test.skills).await?;
+            let before = db
+                .update_test_by_id(&id.to_string(), test.clone())
+                .await
+                .map_err(error)?
+                .ok_or((Status::NotFound, Json(vec![])))?;
+            rdb.record(ContentKind::Choice, id, author, Some(&before), &test)
+                .await
         }
         TestType::ActionTest => {
-            let test = test.into_inner();
-            let result = adb
-                .update_test(id, test.get_test_with_actions().to_owned())
-                .await;
-            match result {
-                Ok(_) => Ok(Status::Ok),
-                Err(_) => Err(Status::InternalServerError),
-            }
+            let mut test = test.into_inner().get_test_with_actions().to_owned();
+            resolve_theme(thdb, &mut test.theme, &test.theme_id).await?;
+            check_skills(skdb, &test.skills).await?;
+            let before = adb
+                .update_test(id, test.clone())
+                .await
+                .map_err(error)?
+                .ok_or((Status::NotFound, Json(vec![])))?;
+            rdb.record(ContentKind::Action, id, author, Some(&before), &test)
+                .await
         }
-    }
+    };
+    recorded.map(|_| Status::Ok).map_err(error)
 }
 
 /// Delete a test from the database
@@ -279,6 +374,7 @@ pub async fn update_test(
 ///
 /// * `db`: &State<TestsRepo> - This is the database connection to the tests table
 /// * `adb`: &State<TActionRepo> - This is the test action repository.
+/// * `pubdb`: &State<PublicationsRepo> - The publication of the test is removed with it.
 /// * `id`: The id of the test to delete
 ///
 /// Returns:
@@ -288,11 +384,20 @@ pub async fn update_test(
 pub async fn delete_test(
     db: &State<TestsRepo>,
     adb: &State<TActionRepo>,
+    pubdb: &State<PublicationsRepo>,
     id: &str,
     test_type: &str,
 ) -> Result<Status, Status> {
     
     let test_type = TestType::from_param(test_type).unwrap();
+    let kind = match test_type {
+        TestType::ChoiceTest => ContentKind::Choice,
+        TestType::ActionTest => ContentKind::Action,
+    };
+    pubdb
+        .delete_publication(kind, id)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
     match test_type {
         TestType::ChoiceTest => {
             let result = db.delete_test(&id.to_string()).await;
@@ -321,111 +426,312 @@ pub async fn delete_test(
 * }
 */
 
-/// It gets a test by id, and returns it as a JSON object
+/// It gets a published test by id, and returns it as a JSON object
 ///
 /// Arguments:
 ///
-/// * `db`: &State<TestsRepo> - this is the database connection to the tests table
-/// * `adb`: &State<TActionRepo> - this is the connection to the database
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the tests and templates, course
+/// levels can point at templates
 /// * `user_db`: &State<UserRepo> - this is the user database, which is used to check if the user is
 /// authorized to access the test.
-/// * `id`: &str - the id of the test
+/// * `cache`: &State<RenderCache<RenderedQuestion>> - rendered questions of the tests
+/// * `id`: &str - the id of the test or of a template
+/// * `seed`: the seed of a question generated from a template, a new one is used if not given
+/// * `render`: if `html`, the question and the answers are also returned rendered into HTML
 /// * `token`: the token of the user who is requesting the test
+/// * `locales`: the locales the user prefers, from `?lang=` and `Accept-Language`
 ///
 /// Returns:
 ///
 /// A test with the given id.
-#[get("/user/<token>/get/test?<id>")]
+#[allow(clippy::too_many_arguments)]
+#[get("/user/<token>/get/test?<id>&<seed>&<render>")]
 pub async fn get_test_by_id_user(
-    db: &State<TestsRepo>,
-    adb: &State<TActionRepo>,
+    pubdb: &State<PublicationsRepo>,
     user_db: &State<UserRepo>,
+    cache: &State<RenderCache<RenderedQuestion>>,
     id: &str,
+    seed: Option<i64>,
+    render: Option<RenderFormat>,
     token: &str,
-) -> Result<Json<TestRes<TestModel, TestModelWithActions>>, Status> {
+    locales: Locales,
+) -> Result<Json<Rendered<TestRes<TestModel, TestModelWithActions>, RenderedQuestion>>, Status> {
     if authorize_token(token.to_string(), user_db).await.0 {
-        let test = db.get_test_by_id(&id.to_string()).await.unwrap();
-        let test_with_actions = adb.get_test_by_id(id).await.unwrap();
-        match (test, test_with_actions) {
-            (Some(test), None) => Ok(Json(TestRes::ChoiceTest(test))),
-            (None, Some(test_with_actions)) => Ok(Json(TestRes::ActionTest(test_with_actions))),
-            // (Some(test), Some(test_with_actions)) =>
-            _ => Err(Status::InternalServerError),
-        }
+        let error = |_| Status::InternalServerError;
+        let mut test = if let Some(template) = pubdb
+            .get_published::<QuestionTemplate>(ContentKind::Template, id)
+            .await
+            .map_err(error)?
+        {
+            template
+                .instantiate(seed.unwrap_or_else(QuestionTemplate::random_seed))
+                .map(|mut instance| {
+                    instance.test = instance.test.for_learner();
+                    TestRes::TemplateTest(Box::new(instance))
+                })
+                .map_err(|_| Status::InternalServerError)?
+        } else if let Some(test) = pubdb
+            .get_published::<TestModel>(ContentKind::Choice, id)
+            .await
+            .map_err(error)?
+        {
+            TestRes::ChoiceTest(test.for_learner())
+        } else if let Some(test_with_actions) = pubdb
+            .get_published::<TestModelWithActions>(ContentKind::Action, id)
+            .await
+            .map_err(error)?
+        {
+            TestRes::ActionTest(test_with_actions.for_learner())
+        } else {
+            return Err(Status::NotFound);
+        };
+        let locale = match &mut test {
+            TestRes::ChoiceTest(t) => t.localize(&locales),
+            TestRes::ActionTest(t) => t.localize(&locales),
+            TestRes::TemplateTest(t) => t.test.localize(&locales),
+        };
+        let key = format!("{}@{}", id, locale);
+        let rendered = match render {
+            Some(RenderFormat::Html) => Some(
+                match &test {
+                    TestRes::ChoiceTest(t) => cache.get_or_render(&key, t, |t| {
+                        render_question(&t.question, &t.answers)
+                    }),
+                    TestRes::ActionTest(t) => cache.get_or_render(&key, t, |t| {
+                        render_question(&t.question, &t.answers)
+                    }),
+                    // every seed gives another question, so they are not cached
+                    TestRes::TemplateTest(t) => render_question(&t.test.question, &t.test.answers),
+                }
+                .map_err(|_| Status::InternalServerError)?,
+            ),
+            None => None,
+        };
+        Ok(Json(Rendered { doc: test, rendered }))
     } else {
         Err(Status::Unauthorized)
     }
 }
 
 
+/// `RandReq` is the request for random tests.
+///
+/// Properties:
+///
+/// * `level`: The level of the tests, ignored by the adaptive selection. Without it, the level
+///   the placement test of the user estimated for the theme is used, or 1.
+/// * `number_of_tests`: How many tests to return.
+/// * `theme`: The theme of the tests.
+/// * `id`: The id of the user.
+/// * `adaptive`: Pick the questions whose ratings are near the rating of the user instead of
+///   the questions of `level`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RandReq<'r> {
-    pub level: i32,
+    #[serde(default)]
+    pub level: Option<i32>,
     pub number_of_tests: i32,
     pub theme: &'r str,
     pub id: &'r str,
+    #[serde(default)]
+    pub adaptive: bool,
+}
+
+/// `Candidate` is a question the adaptive selection or the placement test can pick.
+pub enum Candidate {
+    Choice(TestModel),
+    Action(TestModelWithActions),
+    Template(QuestionTemplate),
 }
 
-/// This function returns a random test for a user based on the level of the test
+/// This function returns a random published test for a user based on the level of the test
 /// 
 /// Arguments:
 /// 
-/// * `db`: &State<TestsRepo> - this is the database connection to the tests table
-/// * `adb`: &State<TActionRepo> - this is the action test database
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the choice tests, the action
+/// tests and the templates, templates fill up the tests that are left after choice and action tests
+/// * `thdb`: &State<ThemesRepo> - the themes
 /// * `user_db`: &State<UserRepo> - this is the user database that we created in the previous step.
-/// * `level`: i32 - the level of the test
+/// * `rdb`: &State<RatingsRepo> - the ratings of the users and of the questions
+/// * `attempts_db`: &State<AttemptsRepo> - the attempts, the adaptive selection skips the questions
+/// the user has answered
+/// * `level`: i32 - the level of the test, the calibrated level of questions that enough users have
+/// answered, see `calibrated_level`. The level from the placement test of the user for the theme
+/// when it is not given, or 1 without a placement test
 /// * `id`: The user's id
 /// * `token`: The token that the user has received when they logged in.
 /// * `number_of_tests`: The number of tests that the user wants to get
-/// * `theme`: The theme of the test
+/// * `theme`: The id or the slug of the theme, tests of the themes below it are included. A theme
+/// that is not in the taxonomy is compared by slug, so `Fractions` also finds `fractions `.
+/// * `adaptive`: Ignore `level` and pick unanswered questions whose ratings are the nearest to the
+/// rating of the user for the theme, see `pick_adaptive`
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`
 /// 
 /// Returns:
 /// 
-/// N number of tests based on the level of the test and what the user has not completed yet.
+/// N number of tests based on the level of the test and what the user has not completed yet. The
+/// `level` of the tests is their calibrated level.
+#[allow(clippy::too_many_arguments)]
 #[get("/user/<token>/get/test/random", data="<req>")]
 pub async fn get_random_test_by_level_user(
-    db: &State<TestsRepo>,
-    adb: &State<TActionRepo>,
+    pubdb: &State<PublicationsRepo>,
+    thdb: &State<ThemesRepo>,
     user_db: &State<UserRepo>,
+    rdb: &State<RatingsRepo>,
+    attempts_db: &State<AttemptsRepo>,
     token: &str,
     req: Json<RandReq<'_>>,
+    locales: Locales,
 ) -> Result<Json<AllTests>, Status> {
     if authorize_token(token.to_string(), user_db).await.0 {
         let req = req.into_inner();
-        let level = req.level;
         let number_of_tests = req.number_of_tests;
-        let theme = req.theme;
+        let (theme, themes) = theme_filter(thdb, req.theme).await?;
         let id = req.id;
         
         let user = user_db.get_user_by_id(&id.to_string()).await.unwrap().unwrap();
+        let level = req.level.unwrap_or_else(|| {
+            let placed = match &theme {
+                Some(theme) => user
+                    .progress
+                    .placed_level(&theme.slug, &theme.id.map(|id| id.to_hex())),
+                None => user.progress.placed_level(req.theme, &None),
+            };
+            placed.unwrap_or(1)
+        });
         let user_level_progress = user.progress.tests;
-        let mut choice_tests = db
-            .get_all_tests()
+        let ratings: HashMap<String, QuestionRatingModel> = rdb
+            .get_question_ratings()
             .await
             .unwrap()
             .into_iter()
-            .filter(|test| test.level == level && !user_level_progress.contains(&test.id.unwrap().to_string()) && test.theme == *theme)
+            .map(|rating| (rating.question_id.clone(), rating))
+            .collect();
+        // The hand-set level is replaced by the calibrated level once enough users answered
+        let level_of = |id: &Option<ObjectId>, level: i32| {
+            calibrated_level(&ratings, &id.unwrap().to_hex(), level)
+        };
+        let mut choice_tests = pubdb
+            .get_all_published::<TestModel>(ContentKind::Choice)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|test| !user_level_progress.contains(&test.id.unwrap().to_string()) && themes.matches(&test.theme, &test.theme_id))
+            .map(|mut test| {
+                test.level = level_of(&test.id, test.level);
+                test
+            })
             .collect::<Vec<TestModel>>();
-        let mut action_tests = adb
-            .get_all_tests()
+        let mut action_tests = pubdb
+            .get_all_published::<TestModelWithActions>(ContentKind::Action)
             .await
             .unwrap()
             .into_iter()
-            .filter(|test| test.level == level && !user_level_progress.contains(&test.id.unwrap().to_string()) && test.theme == *theme)
+            .filter(|test| !user_level_progress.contains(&test.id.unwrap().to_string()) && themes.matches(&test.theme, &test.theme_id))
+            .map(|mut test| {
+                test.level = level_of(&test.id, test.level);
+                test
+            })
             .collect::<Vec<TestModelWithActions>>();
-        // Create all tests with choice test percentage of 70% and action test percentage of 30%
-        choice_tests.shuffle(&mut thread_rng());
-        action_tests.shuffle(&mut thread_rng());
-
-        // Get the number of tests that we need to return to the user
+        let mut templates = pubdb
+            .get_all_published::<QuestionTemplate>(ContentKind::Template)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|template| themes.matches(&template.theme, &template.theme_id))
+            .map(|mut template| {
+                template.level = level_of(&template.id, template.level);
+                template
+            })
+            .collect::<Vec<QuestionTemplate>>();
         let number_of_tests = number_of_tests as usize;
-        choice_tests.truncate(number_of_tests);
-        action_tests.truncate(number_of_tests);
 
-        choice_tests.truncate((choice_tests.len() as f32 * 0.7) as usize);
-        action_tests.truncate((action_tests.len() as f32 * 0.3) as usize);
-        
-        let all_tests = AllTests::new(choice_tests, action_tests);
+        if req.adaptive {
+            // Templates give another question every time, so only tests are skipped once answered
+            let answered = attempts_db
+                .get_user_attempts(id)
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|attempt| attempt.answer.is_some())
+                .map(|attempt| attempt.test_id)
+                .collect::<HashSet<String>>();
+            let user_ratings = rdb.get_user_ratings(id).await.unwrap();
+            let rating = combined_rating(
+                user_ratings
+                    .iter()
+                    .filter(|rating| themes.matches(&rating.theme, &rating.theme_id)),
+            );
+            let rating_of = |id: &Option<ObjectId>, level: i32| {
+                question_rating(&ratings, &id.unwrap().to_hex(), level)
+            };
+            let is_new = |id: &Option<ObjectId>| !answered.contains(&id.unwrap().to_hex());
+            let mut candidates = vec![];
+            for test in choice_tests.drain(..).filter(|test| is_new(&test.id)) {
+                candidates.push((rating_of(&test.id, test.level), Candidate::Choice(test)));
+            }
+            for test in action_tests.drain(..).filter(|test| is_new(&test.id)) {
+                candidates.push((rating_of(&test.id, test.level), Candidate::Action(test)));
+            }
+            for template in templates.drain(..) {
+                let rating = rating_of(&template.id, template.level);
+                candidates.push((rating, Candidate::Template(template)));
+            }
+            for candidate in pick_adaptive(candidates, rating, number_of_tests) {
+                match candidate {
+                    Candidate::Choice(test) => choice_tests.push(test),
+                    Candidate::Action(test) => action_tests.push(test),
+                    Candidate::Template(template) => templates.push(template),
+                }
+            }
+        } else {
+            choice_tests.retain(|test| test.level == level);
+            action_tests.retain(|test| test.level == level);
+            templates.retain(|template| template.level == level);
+
+            // Create all tests with choice test percentage of 70% and action test percentage of 30%
+            choice_tests.shuffle(&mut thread_rng());
+            action_tests.shuffle(&mut thread_rng());
+
+            // Get the number of tests that we need to return to the user
+            choice_tests.truncate(number_of_tests);
+            action_tests.truncate(number_of_tests);
+
+            choice_tests.truncate((choice_tests.len() as f32 * 0.7) as usize);
+            action_tests.truncate((action_tests.len() as f32 * 0.3) as usize);
+
+            // Templates can be instantiated any number of times, so they fill up the rest
+            templates.shuffle(&mut thread_rng());
+            let rest = number_of_tests.saturating_sub(choice_tests.len() + action_tests.len());
+            templates.truncate(rest);
+        }
+
+        let choice_tests = choice_tests
+            .into_iter()
+            .map(|test| {
+                let mut test = test.for_learner();
+                test.localize(&locales);
+                test
+            })
+            .collect::<Vec<TestModel>>();
+        let action_tests = action_tests
+            .into_iter()
+            .map(|test| {
+                let mut test = test.for_learner();
+                test.localize(&locales);
+                test
+            })
+            .collect::<Vec<TestModelWithActions>>();
+        let template_tests = templates
+            .iter()
+            .filter_map(|template| template.instantiate(QuestionTemplate::random_seed()).ok())
+            .map(|mut instance| {
+                instance.test = instance.test.for_learner();
+                instance.test.localize(&locales);
+                instance
+            })
+            .collect::<Vec<TemplateInstance>>();
+
+        let all_tests = AllTests::new(choice_tests, action_tests, template_tests);
 
         Ok(Json(all_tests))
     } else {
@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+
+use mongodb::results::InsertOneResult;
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::{
+    models::{
+        info_model::InfoModel,
+        publication_model::ContentKind,
+        template_model::{QuestionTemplate, TemplateInstance},
+        tests_model::{TestModel, TestModelWithActions},
+        theme_model::{
+            matches_theme, slugify, theme_descendants, theme_tree, ThemeModel, ThemeNode,
+        },
+    },
+    repository::{
+        publications_repo::PublicationsRepo,
+        themes_repo::{ThemeMigrationReport, ThemesRepo},
+    },
+    utils::{
+        auth::AuthUser,
+        locale::{Locales, Localize},
+    },
+};
+
+/// `ThemeFilter` is the set of themes content is filtered by: a theme with everything below it,
+/// or the slug of a free-text theme that is not in the taxonomy.
+pub struct ThemeFilter {
+    pub ids: HashSet<String>,
+    pub slugs: HashSet<String>,
+}
+
+impl ThemeFilter {
+    /// It checks whether content with the theme and theme id passes the filter.
+    pub fn matches(&self, theme: &str, theme_id: &Option<String>) -> bool {
+        matches_theme(theme, theme_id, &self.ids, &self.slugs)
+    }
+}
+
+/// It builds the filter of a theme given by id, by slug, or as free text.
+///
+/// Arguments:
+///
+/// * `thdb`: &ThemesRepo - the themes
+/// * `key`: &str - the id or the slug of the theme, or a free-text theme
+///
+/// Returns:
+///
+/// The theme if it is in the taxonomy, and the filter.
+pub async fn theme_filter(
+    thdb: &ThemesRepo,
+    key: &str,
+) -> Result<(Option<ThemeModel>, ThemeFilter), Status> {
+    let error = |_| Status::InternalServerError;
+    let theme = match thdb.find_theme(key).await.map_err(error)? {
+        Some(theme) => Some(theme),
+        None => thdb.get_theme_by_slug(&slugify(key)).await.map_err(error)?,
+    };
+    let filter = match &theme {
+        Some(theme) => {
+            let themes = thdb.get_all_themes().await.map_err(error)?;
+            let ids = theme_descendants(&themes, &theme.id.unwrap().to_hex());
+            let slugs = themes
+                .into_iter()
+                .filter(|theme| ids.contains(&theme.id.unwrap().to_hex()))
+                .map(|theme| theme.slug)
+                .collect();
+            ThemeFilter { ids, slugs }
+        }
+        None => ThemeFilter {
+            ids: HashSet::new(),
+            slugs: HashSet::from([slugify(key)]),
+        },
+    };
+    Ok((theme, filter))
+}
+
+/// It checks the theme id of an info, a test or a template before it is saved, and stores the
+/// slug of the theme in `theme`.
+///
+/// Arguments:
+///
+/// * `thdb`: &ThemesRepo - the themes
+/// * `theme`: &mut String - the free-text theme of the content
+/// * `theme_id`: &Option<String> - the theme id of the content
+///
+/// Returns:
+///
+/// Nothing, or the problem if the theme id points at nothing.
+pub async fn resolve_theme(
+    thdb: &ThemesRepo,
+    theme: &mut String,
+    theme_id: &Option<String>,
+) -> Result<(), (Status, Json<Vec<String>>)> {
+    if let Some(theme_id) = theme_id {
+        match thdb.get_theme(theme_id).await {
+            Ok(Some(found)) => *theme = found.slug,
+            Ok(None) => {
+                return Err((
+                    Status::UnprocessableEntity,
+                    Json(vec![format!("unknown theme `{}`", theme_id)]),
+                ))
+            }
+            Err(_) => return Err((Status::InternalServerError, Json(vec![]))),
+        }
+    }
+    Ok(())
+}
+
+/// It checks a theme and its place in the hierarchy before it is saved.
+///
+/// Arguments:
+///
+/// * `thdb`: &ThemesRepo - the themes
+/// * `theme`: &mut ThemeModel - the theme, an empty slug is made from the name
+/// * `id`: Option<&str> - the id of the theme when it is updated
+///
+/// Returns:
+///
+/// Nothing, 422 with the problems of the theme and 409 if the slug is taken.
+async fn check_theme(
+    thdb: &ThemesRepo,
+    theme: &mut ThemeModel,
+    id: Option<&str>,
+) -> Result<(), (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let parent = match &theme.parent_id {
+        Some(parent_id) => thdb.get_theme(parent_id).await.map_err(error)?,
+        None => None,
+    };
+    let errors = theme.validate(parent.as_ref());
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
+    if let Some(taken) = thdb.get_theme_by_slug(&theme.slug).await.map_err(error)? {
+        if taken.id.map(|oid| oid.to_hex()).as_deref() != id {
+            return Err((
+                Status::Conflict,
+                Json(vec![format!("slug `{}` is taken", theme.slug)]),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// * Admin API routes
+
+/// It gets all the themes.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+///
+/// Returns:
+///
+/// A JSON array of all themes.
+#[get("/admin/themes")]
+pub async fn get_all_themes(thdb: &State<ThemesRepo>) -> Result<Json<Vec<ThemeModel>>, Status> {
+    match thdb.get_all_themes().await {
+        Ok(themes) => Ok(Json(themes)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It gets a theme by id.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `id`: &str - the id of the theme
+///
+/// Returns:
+///
+/// The theme, or 404 if there is no such theme.
+#[get("/admin/themes/<id>")]
+pub async fn get_theme_admin(
+    thdb: &State<ThemesRepo>,
+    id: &str,
+) -> Result<Json<ThemeModel>, Status> {
+    match thdb.get_theme(id).await {
+        Ok(Some(theme)) => Ok(Json(theme)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It creates a theme. A topic needs a subject as its parent and a subtopic needs a topic.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `theme`: Json<ThemeModel> - the theme, the slug is made from the name if it is empty
+///
+/// Returns:
+///
+/// The result of the insert, 422 with the problems of the theme and 409 if the slug is taken.
+#[post("/admin/themes", data = "<theme>")]
+pub async fn create_theme(
+    thdb: &State<ThemesRepo>,
+    theme: Json<ThemeModel>,
+) -> Result<Json<InsertOneResult>, (Status, Json<Vec<String>>)> {
+    let mut theme = theme.into_inner();
+    theme.id = None;
+    check_theme(thdb, &mut theme, None).await?;
+    match thdb.create_theme(theme).await {
+        Ok(result) => Ok(Json(result)),
+        Err(_) => Err((Status::InternalServerError, Json(vec![]))),
+    }
+}
+
+/// It updates a theme. The level of a theme with children can not be changed.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `id`: &str - the id of the theme
+/// * `theme`: Json<ThemeModel> - the new theme
+///
+/// Returns:
+///
+/// Status, 404 if there is no such theme, 422 with the problems of the theme and 409 if the slug
+/// is taken.
+#[put("/admin/themes/<id>", data = "<theme>")]
+pub async fn update_theme(
+    thdb: &State<ThemesRepo>,
+    id: &str,
+    theme: Json<ThemeModel>,
+) -> Result<Status, (Status, Json<Vec<String>>)> {
+    let error = |_| (Status::InternalServerError, Json(vec![]));
+    let mut theme = theme.into_inner();
+    let before = thdb
+        .get_theme(id)
+        .await
+        .map_err(error)?
+        .ok_or((Status::NotFound, Json(vec![])))?;
+    theme.id = before.id;
+    check_theme(thdb, &mut theme, Some(id)).await?;
+    if theme.level != before.level {
+        let themes = thdb.get_all_themes().await.map_err(error)?;
+        if theme_descendants(&themes, id).len() > 1 {
+            return Err((
+                Status::UnprocessableEntity,
+                Json(vec![format!(
+                    "the {} has themes below it, its level can not change",
+                    before.level.name()
+                )]),
+            ));
+        }
+    }
+    match thdb.update_theme(id, theme).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, Json(vec![]))),
+    }
+}
+
+/// It deletes a theme that has no themes below it and no content pointing at it.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `id`: &str - the id of the theme
+///
+/// Returns:
+///
+/// Status, 404 if there is no such theme and 409 if the theme is still used.
+#[delete("/admin/themes/<id>")]
+pub async fn delete_theme(thdb: &State<ThemesRepo>, id: &str) -> Result<Status, (Status, String)> {
+    let error = |_| (Status::InternalServerError, String::new());
+    if thdb.get_theme(id).await.map_err(error)?.is_none() {
+        return Err((Status::NotFound, String::new()));
+    }
+    let themes = thdb.get_all_themes().await.map_err(error)?;
+    if theme_descendants(&themes, id).len() > 1 {
+        return Err((
+            Status::Conflict,
+            "the theme has themes below it".to_string(),
+        ));
+    }
+    let references = thdb.count_references(id).await.map_err(error)?;
+    if references > 0 {
+        return Err((
+            Status::Conflict,
+            format!("{} infos, tests or templates use the theme", references),
+        ));
+    }
+    match thdb.delete_theme(id).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err((Status::InternalServerError, String::new())),
+    }
+}
+
+/// It points the infos, tests and templates with free-text themes at themes of the taxonomy,
+/// creating a subject for every theme that is not in it yet.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+///
+/// Returns:
+///
+/// A report with the created themes and the number of updated documents.
+#[post("/admin/migrate/themes")]
+pub async fn migrate_themes(
+    thdb: &State<ThemesRepo>,
+) -> Result<Json<ThemeMigrationReport>, Status> {
+    match thdb.migrate_themes().await {
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// * User API routes
+
+/// `ThemeContent` is the published content of a theme and of the themes below it.
+#[derive(Debug, Serialize)]
+pub struct ThemeContent {
+    pub theme: ThemeModel,
+    pub infos: Vec<InfoModel>,
+    pub choice_tests: Vec<TestModel>,
+    pub action_tests: Vec<TestModelWithActions>,
+    pub templates: Vec<TemplateInstance>,
+}
+
+/// It lists the themes as subjects with their topics and subtopics.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `_user`: The user from the `Authorization` header.
+///
+/// Returns:
+///
+/// The subjects, sorted by name.
+#[get("/user/themes")]
+pub async fn get_themes_user(
+    thdb: &State<ThemesRepo>,
+    _user: AuthUser,
+) -> Result<Json<Vec<ThemeNode>>, Status> {
+    match thdb.get_all_themes().await {
+        Ok(themes) => Ok(Json(theme_tree(&themes))),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// It gets the published infos, tests and templates of a theme and of the themes below it.
+///
+/// Arguments:
+///
+/// * `thdb`: &State<ThemesRepo> - the themes
+/// * `pubdb`: &State<PublicationsRepo> - the published snapshots of the content
+/// * `_user`: The user from the `Authorization` header.
+/// * `key`: &str - the id or the slug of the theme
+/// * `level`: only content of this difficulty, if given
+/// * `locales`: The locales the user prefers, from `?lang=` and `Accept-Language`.
+///
+/// Returns:
+///
+/// The content, or 404 if there is no such theme.
+#[get("/user/themes/<key>/content?<level>")]
+pub async fn get_theme_content_user(
+    thdb: &State<ThemesRepo>,
+    pubdb: &State<PublicationsRepo>,
+    _user: AuthUser,
+    key: &str,
+    level: Option<i32>,
+    locales: Locales,
+) -> Result<Json<ThemeContent>, Status> {
+    let error = |_| Status::InternalServerError;
+    let (theme, filter) = theme_filter(thdb, key).await?;
+    let theme = theme.ok_or(Status::NotFound)?;
+    let at_level = |test_level: i32| level.is_none_or(|level| level == test_level);
+
+    let mut infos = pubdb
+        .get_all_published::<InfoModel>(ContentKind::Info)
+        .await
+        .map_err(error)?;
+    infos.retain(|info| filter.matches(&info.theme, &info.theme_id));
+    infos.iter_mut().for_each(|info| {
+        info.localize(&locales);
+    });
+    let choice_tests = pubdb
+        .get_all_published::<TestModel>(ContentKind::Choice)
+        .await
+        .map_err(error)?
+        .into_iter()
+        .filter(|test| filter.matches(&test.theme, &test.theme_id) && at_level(test.level))
+        .map(|test| {
+            let mut test = test.for_learner();
+            test.localize(&locales);
+            test
+        })
+        .collect();
+    let action_tests = pubdb
+        .get_all_published::<TestModelWithActions>(ContentKind::Action)
+        .await
+        .map_err(error)?
+        .into_iter()
+        .filter(|test| filter.matches(&test.theme, &test.theme_id) && at_level(test.level))
+        .map(|test| {
+            let mut test = test.for_learner();
+            test.localize(&locales);
+            test
+        })
+        .collect();
+    let templates = pubdb
+        .get_all_published::<QuestionTemplate>(ContentKind::Template)
+        .await
+        .map_err(error)?
+        .into_iter()
+        .filter(|template| {
+            filter.matches(&template.theme, &template.theme_id) && at_level(template.level)
+        })
+        .filter_map(|template| template.instantiate(QuestionTemplate::random_seed()).ok())
+        .map(|mut instance| {
+            instance.test = instance.test.for_learner();
+            instance.test.localize(&locales);
+            instance
+        })
+        .collect();
+    Ok(Json(ThemeContent {
+        theme,
+        infos,
+        choice_tests,
+        action_tests,
+        templates,
+    }))
+}
@@ -0,0 +1,130 @@
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+
+use crate::{
+    repository::{
+        cource_repo::CourceRepo, infos_repo::InfosRepo, templates_repo::TemplatesRepo,
+        tests_repo::TestsRepo, tests_with_actions_repo::TestsRepo as TActionRepo,
+    },
+    utils::locale::Localize,
+};
+
+/// `MissingTranslation` is a document that is not fully translated to a locale.
+///
+/// Properties:
+///
+/// * `collection`: The kind of the document: `cource`, `info`, `choice`, `action` or `template`.
+/// * `id`: The id of the document.
+/// * `title`: The title of the document, or the question of a test.
+/// * `fields`: The fields that have no translation.
+#[derive(Debug, Serialize)]
+pub struct MissingTranslation {
+    pub collection: String,
+    pub id: String,
+    pub title: String,
+    pub fields: Vec<String>,
+}
+
+impl MissingTranslation {
+    fn new(
+        collection: &str,
+        id: Option<mongodb::bson::oid::ObjectId>,
+        title: &str,
+        fields: Vec<String>,
+    ) -> Option<Self> {
+        if fields.is_empty() {
+            return None;
+        }
+        Some(MissingTranslation {
+            collection: collection.to_string(),
+            id: id.map(|id| id.to_hex()).unwrap_or_default(),
+            title: title.to_string(),
+            fields,
+        })
+    }
+}
+
+// * Admin API routes
+
+/// It lists all the content that is not fully translated to a locale.
+///
+/// Arguments:
+///
+/// * `cdb`: &State<CourceRepo> - the courses
+/// * `idb`: &State<InfosRepo> - the infos
+/// * `db`: &State<TestsRepo> - the choice tests
+/// * `adb`: &State<TActionRepo> - the action tests
+/// * `tdb`: &State<TemplatesRepo> - the question templates
+/// * `locale`: &str - the locale to check, e.g. `de`
+///
+/// Returns:
+///
+/// The documents with the fields that have no translation.
+#[get("/admin/translations/missing?<locale>")]
+pub async fn get_missing_translations(
+    cdb: &State<CourceRepo>,
+    idb: &State<InfosRepo>,
+    db: &State<TestsRepo>,
+    adb: &State<TActionRepo>,
+    tdb: &State<TemplatesRepo>,
+    locale: &str,
+) -> Result<Json<Vec<MissingTranslation>>, Status> {
+    let mut missing = vec![];
+    for cource in cdb.get_all().await.unwrap_or_default() {
+        missing.extend(MissingTranslation::new(
+            "cource",
+            cource.id,
+            &cource.title,
+            cource.missing_translations(locale),
+        ));
+    }
+    let infos = idb
+        .get_all_infos()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    for info in infos {
+        missing.extend(MissingTranslation::new(
+            "info",
+            info.id,
+            &info.title,
+            info.missing_translations(locale),
+        ));
+    }
+    let tests = db
+        .get_all_tests()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    for test in tests {
+        missing.extend(MissingTranslation::new(
+            "choice",
+            test.id,
+            &test.question,
+            test.missing_translations(locale),
+        ));
+    }
+    let tests = adb
+        .get_all_tests()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    for test in tests {
+        missing.extend(MissingTranslation::new(
+            "action",
+            test.id,
+            &test.question,
+            test.missing_translations(locale),
+        ));
+    }
+    let templates = tdb
+        .get_all_templates()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    for template in templates {
+        missing.extend(MissingTranslation::new(
+            "template",
+            template.id,
+            &template.question,
+            template.missing_translations(locale),
+        ));
+    }
+    Ok(Json(missing))
+}
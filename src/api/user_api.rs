@@ -1,10 +1,23 @@
 #![allow(dead_code)]
 
 use crate::{
-    models::user_model::{UserModel, UserProgress},
-    repository::user_repo::UserRepo,
+    api::cources_api::learner_progress,
+    models::{
+        cource_model::{CourseModel, NodeState},
+        publication_model::ContentKind,
+        user_model::{UserModel, UserProgress},
+    },
+    repository::{
+        attempts_repo::AttemptsRepo,
+        cource_repo::CourceRepo,
+        publications_repo::PublicationsRepo,
+        user_repo::{ProgressMigrationReport, UserRepo},
+    },
     utils::auth::{authorize_token, decode_jwt},
 };
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
 use rocket::{
     http::Status,
     serde::json::Json,
@@ -59,12 +72,7 @@ pub async fn register_user(
         email: user.email,
         hashed_password: user.password,
         role: user.role.parse().unwrap(),
-        progress: UserProgress {
-            courses: vec![],
-            tests: vec![],
-            infos: vec![],
-            nodes: vec![],
-        },
+        progress: UserProgress::default(),
     };
     let result = db.create_user(user).await;
     match result {
@@ -327,13 +335,17 @@ pub struct NodePassingData {
     pub user_id: String,
 }
 
-/// This function adds a node to a user's account if the token is authorized.
+/// This function adds a node to a user's account if the token is authorized. The node must be
+/// unlocked, and for a test node every test of the node must have a correct attempt. Once all the
+/// nodes of the course are passed, the course is marked as completed.
 /// 
 /// Arguments:
 /// 
 /// * `db`: `db` is a reference to the `UserRepo` state, which is likely a database or repository that
 /// stores information about users and their nodes. This parameter is used to add a node to a user's
 /// list of nodes in the database.
+/// * `pubdb`: The published courses, to find the node and its prerequisites.
+/// * `adb`: The graded attempts of the user.
 /// * `token`: The token parameter is a string that represents the authentication token of the user
 /// making the request. It is used to verify the identity of the user and ensure that they have the
 /// necessary permissions to perform the requested action.
@@ -342,22 +354,65 @@ pub struct NodePassingData {
 /// 
 /// Returns:
 /// 
-/// This function returns a `Result` with either a `Status::Ok` if the token is authorized and the node
-/// is added to the user, or a `Status::Unauthorized` if the token is not authorized.
+/// `Status::Ok` if the node is added to the user, `Status::Unauthorized` if the token is not
+/// authorized, 403 if the user is another user or the node is locked, 404 if the node is not in a
+/// published course and 409 with the ids of the tests that are not passed yet.
 #[put("/user/<token>/pass/node", data = "<node_passing_data>")]
 pub async fn pass_node(
     db: &State<UserRepo>,
+    pubdb: &State<PublicationsRepo>,
+    adb: &State<AttemptsRepo>,
     token: String,
     node_passing_data: Json<NodePassingData>,
-) -> Result<Status, Status> {
-    if authorize_token(token, db).await.0 {
-        let node_passing_data = node_passing_data.into_inner();
-        db.add_node_to_user(node_passing_data.user_id, node_passing_data.node_id)
-            .await;
-        Ok(Status::Ok)
-    } else {
-        Err(Status::Unauthorized)
+) -> Result<Status, (Status, String)> {
+    let (authorized, user_id) = authorize_token(token, db).await;
+    if !authorized {
+        return Err((Status::Unauthorized, String::new()));
     }
+    let node_passing_data = node_passing_data.into_inner();
+    if node_passing_data.user_id != user_id {
+        return Err((Status::Forbidden, String::new()));
+    }
+    let node_id = node_passing_data.node_id;
+    let cource = pubdb
+        .get_all_published::<CourseModel>(ContentKind::Course)
+        .await
+        .map_err(|_| (Status::InternalServerError, String::new()))?
+        .into_iter()
+        .find(|cource| cource.find_level(&node_id).is_some())
+        .ok_or((Status::NotFound, format!("unknown node `{}`", node_id)))?;
+    let cource_id = cource.id.map(|id| id.to_hex()).unwrap_or_default();
+    let progress = learner_progress(&user_id, &cource, db, adb, pubdb)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let states = cource.node_states(&progress.completed, &progress.started, &progress.unlocked);
+    if states.get(&node_id) == Some(&NodeState::Locked) {
+        return Err((Status::Forbidden, format!("node `{}` is locked", node_id)));
+    }
+    let (_, level) = cource.find_level(&node_id).unwrap();
+    if level.type_ == "test" {
+        let not_passed: Vec<&str> = level
+            .ids
+            .iter()
+            .filter(|id| !progress.passed.contains(*id))
+            .map(|id| id.as_str())
+            .collect();
+        if !not_passed.is_empty() {
+            return Err((
+                Status::Conflict,
+                format!("tests not passed: {}", not_passed.join(", ")),
+            ));
+        }
+    }
+    db.add_node_to_user(user_id.clone(), cource_id.clone(), node_id.clone())
+        .await;
+
+    let mut progress = progress;
+    progress.completed.insert(node_id);
+    if cource.progress(&progress).completed {
+        db.complete_cource_for_user(user_id, cource_id).await;
+    }
+    Ok(Status::Ok)
 }
 
 // * Admin api routes
@@ -450,33 +505,86 @@ pub async fn update_user(
 }
 
 /// `update_user_progress` takes a `UserProgress` struct, and updates the user's progress in the
-/// database
+/// database. Every enrollment must be in an existing course, and its passed levels must be levels
+/// of that course.
 ///
 /// Arguments:
 ///
 /// * `db`: &State<UserRepo> - This is the database connection that we created in the main.rs file.
+/// * `cdb`: &State<CourceRepo> - The courses the enrollments are checked against.
 /// * `id`: The id of the user to update
 /// * `progress`: Json<UserProgress>
 ///
 /// Returns:
 ///
-/// A status code
+/// A status code, or 422 with the problems of the enrollments
 #[put("/admins/update/user/progress?<id>", data = "<progress>")]
 pub async fn update_user_progress(
     db: &State<UserRepo>,
+    cdb: &State<CourceRepo>,
     id: &str,
     progress: Json<UserProgress>,
-) -> Result<Status, Status> {
+) -> Result<Status, (Status, Json<Vec<String>>)> {
+    let progress = progress.into_inner();
+    let mut course_levels = HashMap::new();
+    for enrollment in &progress.enrollments {
+        if ObjectId::parse_str(&enrollment.course_id).is_err() {
+            continue;
+        }
+        if let Some(cource) = cdb.get(&enrollment.course_id).await {
+            let levels = cource
+                .levels
+                .values()
+                .flatten()
+                .map(|level| level.id.clone())
+                .collect();
+            course_levels.insert(enrollment.course_id.clone(), levels);
+        }
+    }
+    let errors = progress.validate(&course_levels);
+    if !errors.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(errors)));
+    }
     let result = db
-        .update_all_progress(id.to_string(), progress.into_inner())
+        .update_all_progress(id.to_string(), progress)
         .await
         .unwrap();
     match result {
         Some(_) => Ok(Status::Ok),
-        None => Err(Status::NotFound),
+        None => Err((Status::NotFound, Json(vec![]))),
     }
 }
 
+/// It converts the progress of all users stored in the old flat shape into enrollments. The
+/// passed levels are assigned to the course they belong to and the passed tests are saved as
+/// correct attempts. The migration also runs when the server starts, this route is for progress
+/// restored from an old backup.
+///
+/// Arguments:
+///
+/// * `db`: &State<UserRepo> - This is the database connection.
+/// * `cdb`: &State<CourceRepo> - The courses, to find the course of every passed level.
+/// * `adb`: &State<AttemptsRepo> - The attempts, where the passed tests are saved.
+///
+/// Returns:
+///
+/// A report with the migrated users and the levels that are in no course
+#[post("/admin/migrate/users/progress")]
+pub async fn migrate_users_progress(
+    db: &State<UserRepo>,
+    cdb: &State<CourceRepo>,
+    adb: &State<AttemptsRepo>,
+) -> Result<Json<ProgressMigrationReport>, Status> {
+    let report = db
+        .migrate_progress(&cdb.get_level_courses().await)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    adb.carry_legacy_passes(&report.legacy_passes)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(report))
+}
+
 // Admins routes for work with courses
 // * Add course to user
 /// It takes a JSON object with a user_id and a course_id, and adds the course to the user's list of
@@ -0,0 +1,118 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// `QuestionKind` tells in which collection the question of an attempt is stored.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum QuestionKind {
+    #[serde(rename = "choice")]
+    Choice,
+    #[serde(rename = "action")]
+    Action,
+    #[serde(rename = "template")]
+    Template,
+}
+
+/// `AttemptModel` is one try of a user to answer a question.
+///
+/// Properties:
+///
+/// * `id`: The id of the attempt.
+/// * `user_id`: The id of the user.
+/// * `test_id`: The id of the test or of the template.
+/// * `kind`: The kind of the question.
+/// * `seed`: The seed of a question generated from a template.
+/// * `hints_used`: How many hints the user has revealed.
+/// * `answer`: The answer of the user, empty until the attempt is answered.
+/// * `correct`: Whether the answer was correct.
+/// * `score`: The score from 0 to 1, hints can discount it.
+/// * `started_at`: Unix timestamp of the start of the attempt.
+/// * `answered_at`: Unix timestamp of the answer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AttemptModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub test_id: String,
+    pub kind: QuestionKind,
+    pub seed: Option<i64>,
+    pub hints_used: i32,
+    pub answer: Option<String>,
+    pub correct: Option<bool>,
+    pub score: Option<f64>,
+    pub started_at: i64,
+    pub answered_at: Option<i64>,
+}
+
+impl AttemptModel {
+    /// It creates a new attempt that is not answered yet.
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: The id of the user.
+    /// * `test_id`: The id of the test or of the template.
+    /// * `kind`: The kind of the question.
+    /// * `seed`: The seed of a question generated from a template.
+    ///
+    /// Returns:
+    ///
+    /// A new AttemptModel
+    pub fn new(user_id: String, test_id: String, kind: QuestionKind, seed: Option<i64>) -> Self {
+        Self {
+            id: None,
+            user_id,
+            test_id,
+            kind,
+            seed,
+            hints_used: 0,
+            answer: None,
+            correct: None,
+            score: None,
+            started_at: chrono::Utc::now().timestamp(),
+            answered_at: None,
+        }
+    }
+
+    /// It creates the graded attempt of a test passed before attempts were graded. The answer of
+    /// the user is not known, it is left empty.
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: The id of the user.
+    /// * `test_id`: The id of the passed test.
+    /// * `passed_at`: Unix timestamp used as the start and the answer of the attempt.
+    ///
+    /// Returns:
+    ///
+    /// A correct AttemptModel
+    pub fn legacy_pass(user_id: String, test_id: String, passed_at: i64) -> Self {
+        Self {
+            answer: Some(String::new()),
+            correct: Some(true),
+            score: Some(1.0),
+            started_at: passed_at,
+            answered_at: Some(passed_at),
+            ..Self::new(user_id, test_id, QuestionKind::Choice, None)
+        }
+    }
+
+    /// It grades the answer of the user. Every revealed hint takes `hint_penalty` percent off the
+    /// score of a correct answer.
+    ///
+    /// Arguments:
+    ///
+    /// * `answer`: The answer of the user.
+    /// * `correct_answer`: The correct answer of the question.
+    /// * `hint_penalty`: Percent taken off for every hint, no discount if None.
+    pub fn grade(&mut self, answer: &str, correct_answer: &str, hint_penalty: Option<i32>) {
+        let correct = answer.trim() == correct_answer.trim();
+        let discount = hint_penalty.unwrap_or(0) as f64 * self.hints_used as f64 / 100.0;
+        self.score = Some(if correct {
+            (1.0 - discount).clamp(0.0, 1.0)
+        } else {
+            0.0
+        });
+        self.correct = Some(correct);
+        self.answer = Some(answer.to_string());
+        self.answered_at = Some(chrono::Utc::now().timestamp());
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+
+use mongodb::bson::{oid::ObjectId, to_document, Bson, Document};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::models::{
+    cource_model::{CourseModel, CourseOrigin},
+    info_model::InfoModel,
+    media_model::MediaModel,
+    publication_model::ContentKind,
+    template_model::QuestionTemplate,
+    tests_model::{TestModel, TestModelWithActions},
+};
+
+/// The version of the bundle format written by this server.
+pub const BUNDLE_FORMAT_VERSION: i32 = 1;
+
+/// `CourseBundle` is a course with everything its levels point at, so it can be moved to another
+/// database in one piece.
+///
+/// Properties:
+///
+/// * `format_version`: The version of the bundle format.
+/// * `exported_at`: Unix timestamp of the export.
+/// * `course`: The course.
+/// * `infos`: The infos of the levels.
+/// * `choice_tests`: The choice tests of the levels.
+/// * `action_tests`: The action tests of the levels.
+/// * `templates`: The question templates of the levels.
+/// * `media`: The uploaded media the course and its content use. Only the metadata is exported,
+///   the media has to be uploaded to the other server with the same id.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CourseBundle {
+    pub format_version: i32,
+    pub exported_at: i64,
+    pub course: CourseModel,
+    #[serde(default)]
+    pub infos: Vec<InfoModel>,
+    #[serde(default)]
+    pub choice_tests: Vec<TestModel>,
+    #[serde(default)]
+    pub action_tests: Vec<TestModelWithActions>,
+    #[serde(default)]
+    pub templates: Vec<QuestionTemplate>,
+    #[serde(default)]
+    pub media: Vec<MediaModel>,
+}
+
+/// `BundleManifest` is the `manifest.json` of a zipped bundle.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    format_version: i32,
+    exported_at: i64,
+}
+
+/// `ConflictStrategy` is what an import does with a document whose id already exists.
+///
+/// Variants:
+///
+/// * `Skip`: The existing document is kept, the course points at it.
+/// * `Overwrite`: The existing document is replaced by the one of the bundle.
+/// * `Duplicate`: The document of the bundle is created with a new id.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    #[default]
+    Skip,
+    Overwrite,
+    Duplicate,
+}
+
+impl ConflictStrategy {
+    /// It parses `skip`, `overwrite` and `duplicate`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(ConflictStrategy::Skip),
+            "overwrite" => Some(ConflictStrategy::Overwrite),
+            "duplicate" => Some(ConflictStrategy::Duplicate),
+            _ => None,
+        }
+    }
+}
+
+/// `ImportOutcome` is what happens to one document of a bundle.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Create,
+    Overwrite,
+    Skip,
+}
+
+/// `ImportItem` is one document of a bundle and what the import does with it.
+///
+/// Properties:
+///
+/// * `kind`: The kind of the document.
+/// * `source_id`: The id of the document in the bundle.
+/// * `target_id`: The id of the document after the import.
+/// * `outcome`: Whether the document is created, overwritten or skipped.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ImportItem {
+    pub kind: ContentKind,
+    pub source_id: String,
+    pub target_id: String,
+    pub outcome: ImportOutcome,
+}
+
+/// `ImportReport` is the result of an import, or what an import would do for a dry run.
+///
+/// Properties:
+///
+/// * `dry_run`: Whether nothing was written.
+/// * `strategy`: How conflicts were resolved.
+/// * `course_id`: The id of the imported course.
+/// * `items`: Every document of the bundle, the course last.
+/// * `missing_media`: Ids of media the bundle uses that are not uploaded to this server.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub strategy: ConflictStrategy,
+    pub course_id: String,
+    pub items: Vec<ImportItem>,
+    pub missing_media: Vec<String>,
+}
+
+fn id_of(id: &Option<ObjectId>) -> Option<String> {
+    id.map(|id| id.to_hex())
+}
+
+/// It collects the ids of uploaded media from the urls in a document, e.g. `/media/<id>` and
+/// `/media/<id>/thumbnail`.
+fn collect_media(value: &Bson, media: &mut BTreeSet<String>) {
+    match value {
+        Bson::String(url) => {
+            if let Some(id) = url
+                .strip_prefix("/media/")
+                .and_then(|rest| rest.split('/').next())
+            {
+                if ObjectId::parse_str(id).is_ok() {
+                    media.insert(id.to_string());
+                }
+            }
+        }
+        Bson::Array(items) => items.iter().for_each(|item| collect_media(item, media)),
+        Bson::Document(document) => document
+            .values()
+            .for_each(|item| collect_media(item, media)),
+        _ => {}
+    }
+}
+
+impl CourseBundle {
+    /// It creates a bundle exported now.
+    pub fn new(course: CourseModel) -> Self {
+        Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            exported_at: chrono::Utc::now().timestamp(),
+            course,
+            infos: vec![],
+            choice_tests: vec![],
+            action_tests: vec![],
+            templates: vec![],
+            media: vec![],
+        }
+    }
+
+    /// It returns every document of the bundle with its kind and id, the content first and the
+    /// course last, so the course is only written once the content it points at exists.
+    pub fn documents(&self) -> Vec<(ContentKind, Option<String>, Document)> {
+        let mut documents = vec![];
+        let mut push = |kind: ContentKind, id: Option<String>, document| {
+            if let Ok(document) = document {
+                documents.push((kind, id, document));
+            }
+        };
+        for info in &self.infos {
+            push(ContentKind::Info, id_of(&info.id), to_document(info));
+        }
+        for test in &self.choice_tests {
+            push(ContentKind::Choice, id_of(&test.id), to_document(test));
+        }
+        for test in &self.action_tests {
+            push(ContentKind::Action, id_of(&test.id), to_document(test));
+        }
+        for template in &self.templates {
+            push(
+                ContentKind::Template,
+                id_of(&template.id),
+                to_document(template),
+            );
+        }
+        push(
+            ContentKind::Course,
+            id_of(&self.course.id),
+            to_document(&self.course),
+        );
+        documents
+    }
+
+    /// It collects the ids of the uploaded media the course and its content use.
+    pub fn media_ids(&self) -> BTreeSet<String> {
+        let mut media = BTreeSet::new();
+        for (_, _, document) in self.documents() {
+            collect_media(&Bson::Document(document), &mut media);
+        }
+        for level in self.course.levels.values().flatten() {
+            if let Some(media_id) = &level.media_id {
+                media.insert(media_id.clone());
+            }
+        }
+        media
+    }
+
+    /// It checks that the bundle can be imported: every document has an id, no id is used
+    /// twice and every content id of the levels is in the bundle.
+    ///
+    /// Returns:
+    ///
+    /// The problems, empty if the bundle is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.format_version > BUNDLE_FORMAT_VERSION {
+            errors.push(format!(
+                "bundle format {} is newer than the supported format {}",
+                self.format_version, BUNDLE_FORMAT_VERSION
+            ));
+        }
+        let mut ids = HashSet::new();
+        for (kind, id, _) in self.documents() {
+            match id {
+                Some(id) => {
+                    if !ids.insert(id.clone()) {
+                        errors.push(format!("id `{}` is used more than once", id));
+                    }
+                }
+                None => errors.push(format!("a {} has no id", kind.name())),
+            }
+        }
+        for id in self.course.content_ids() {
+            if !ids.contains(&id) {
+                errors.push(format!(
+                    "content `{}` of the course is not in the bundle",
+                    id
+                ));
+            }
+        }
+        errors
+    }
+
+    /// It decides what happens to every document of the bundle.
+    ///
+    /// Arguments:
+    ///
+    /// * `existing`: The documents of the bundle that already exist, by kind and id.
+    /// * `strategy`: What to do with the existing documents.
+    ///
+    /// Returns:
+    ///
+    /// One item per document, in the order of `documents`.
+    pub fn plan(
+        &self,
+        existing: &HashSet<(ContentKind, String)>,
+        strategy: ConflictStrategy,
+    ) -> Vec<ImportItem> {
+        self.documents()
+            .into_iter()
+            .filter_map(|(kind, id, _)| id.map(|id| (kind, id)))
+            .map(|(kind, source_id)| {
+                let (target_id, outcome) = if !existing.contains(&(kind, source_id.clone())) {
+                    (source_id.clone(), ImportOutcome::Create)
+                } else {
+                    match strategy {
+                        ConflictStrategy::Skip => (source_id.clone(), ImportOutcome::Skip),
+                        ConflictStrategy::Overwrite => {
+                            (source_id.clone(), ImportOutcome::Overwrite)
+                        }
+                        ConflictStrategy::Duplicate => {
+                            (ObjectId::new().to_hex(), ImportOutcome::Create)
+                        }
+                    }
+                };
+                ImportItem {
+                    kind,
+                    source_id,
+                    target_id,
+                    outcome,
+                }
+            })
+            .collect()
+    }
+
+    /// It rewrites the bundle to the ids chosen by `plan`: every document gets its target id and
+    /// the levels point at the target ids of their content. A duplicated course also gets new
+    /// level ids, so its levels are not mixed up with the levels of the original course.
+    ///
+    /// Arguments:
+    ///
+    /// * `items`: The plan of the import.
+    ///
+    /// Returns:
+    ///
+    /// The documents to write with their items.
+    pub fn remap(&self, items: &[ImportItem]) -> Vec<(ImportItem, Document)> {
+        let (course, _) = self.remap_course(items);
+        CourseBundle {
+            course,
+            ..self.clone()
+        }
+        .place(items)
+    }
+
+    /// It copies the course into a new course with new level ids. A deep copy also copies the
+    /// content of the bundle to new documents and points the levels at them, otherwise the copy
+    /// shares its content with the source. The copy records its source in `origin`.
+    ///
+    /// Arguments:
+    ///
+    /// * `deep`: Whether to copy the content too.
+    ///
+    /// Returns:
+    ///
+    /// The documents to create with their items, the course last.
+    pub fn fork(&self, deep: bool) -> Vec<(ImportItem, Document)> {
+        let source = if deep {
+            self.clone()
+        } else {
+            CourseBundle {
+                infos: vec![],
+                choice_tests: vec![],
+                action_tests: vec![],
+                templates: vec![],
+                ..self.clone()
+            }
+        };
+        let existing = source
+            .documents()
+            .into_iter()
+            .filter_map(|(kind, id, _)| id.map(|id| (kind, id)))
+            .collect();
+        let items = source.plan(&existing, ConflictStrategy::Duplicate);
+        let (mut course, level_ids) = source.remap_course(&items);
+        course.origin = Some(CourseOrigin {
+            course_id: id_of(&self.course.id).unwrap_or_default(),
+            cloned_at: chrono::Utc::now().timestamp(),
+            deep,
+            levels: level_ids
+                .into_iter()
+                .map(|(source, target)| (target, source))
+                .collect(),
+            content: items
+                .iter()
+                .filter(|item| item.kind != ContentKind::Course)
+                .map(|item| (item.target_id.clone(), item.source_id.clone()))
+                .collect(),
+        });
+        CourseBundle { course, ..source }.place(&items)
+    }
+
+    /// It rewrites the levels of the course to the target ids of `items`.
+    ///
+    /// Returns:
+    ///
+    /// The rewritten course and the new level ids by the old ones.
+    fn remap_course(&self, items: &[ImportItem]) -> (CourseModel, HashMap<String, String>) {
+        let ids: HashMap<&str, &str> = items
+            .iter()
+            .map(|item| (item.source_id.as_str(), item.target_id.as_str()))
+            .collect();
+        let mut course = self.course.clone();
+        let duplicated = items
+            .iter()
+            .any(|item| item.kind == ContentKind::Course && item.source_id != item.target_id);
+        let level_ids: HashMap<String, String> = course
+            .levels
+            .values()
+            .flatten()
+            .map(|level| {
+                let id = if duplicated {
+                    Uuid::new_v4().to_string()
+                } else {
+                    level.id.clone()
+                };
+                (level.id.clone(), id)
+            })
+            .collect();
+        for level in course.levels.values_mut().flatten() {
+            level.id = level_ids[&level.id].clone();
+            for id in level.ids.iter_mut() {
+                if let Some(target) = ids.get(id.as_str()) {
+                    *id = target.to_string();
+                }
+            }
+            if let Some(prerequisites) = level.prerequisites.as_mut() {
+                for id in prerequisites.iter_mut() {
+                    if let Some(target) = level_ids.get(id) {
+                        *id = target.clone();
+                    }
+                }
+            }
+        }
+        (course, level_ids)
+    }
+
+    /// It returns the documents of the bundle with the target ids of `items`.
+    fn place(&self, items: &[ImportItem]) -> Vec<(ImportItem, Document)> {
+        self.documents()
+            .into_iter()
+            .zip(items)
+            .map(|((_, _, mut document), item)| {
+                if let Ok(id) = ObjectId::parse_str(&item.target_id) {
+                    document.insert("_id", id);
+                }
+                (item.clone(), document)
+            })
+            .collect()
+    }
+
+    /// It packs the bundle into a zip archive with one JSON file per kind of document.
+    pub fn to_zip(&self) -> Result<Vec<u8>, String> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let manifest = BundleManifest {
+            format_version: self.format_version,
+            exported_at: self.exported_at,
+        };
+        let files = [
+            ("manifest.json", serde_json::to_vec_pretty(&manifest)),
+            ("course.json", serde_json::to_vec_pretty(&self.course)),
+            ("infos.json", serde_json::to_vec_pretty(&self.infos)),
+            (
+                "choice_tests.json",
+                serde_json::to_vec_pretty(&self.choice_tests),
+            ),
+            (
+                "action_tests.json",
+                serde_json::to_vec_pretty(&self.action_tests),
+            ),
+            ("templates.json", serde_json::to_vec_pretty(&self.templates)),
+            ("media.json", serde_json::to_vec_pretty(&self.media)),
+        ];
+        for (name, data) in files {
+            let data = data.map_err(|e| e.to_string())?;
+            writer
+                .start_file(name, options)
+                .map_err(|e| e.to_string())?;
+            writer.write_all(&data).map_err(|e| e.to_string())?;
+        }
+        let cursor = writer.finish().map_err(|e| e.to_string())?;
+        Ok(cursor.into_inner())
+    }
+
+    /// It reads a bundle packed by `to_zip`. Files of kinds without documents can be left out.
+    pub fn from_zip(data: &[u8]) -> Result<Self, String> {
+        let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+        let manifest: BundleManifest = read_zip_file(&mut archive, "manifest.json")?
+            .ok_or("the archive has no manifest.json")?;
+        let course =
+            read_zip_file(&mut archive, "course.json")?.ok_or("the archive has no course.json")?;
+        Ok(Self {
+            format_version: manifest.format_version,
+            exported_at: manifest.exported_at,
+            course,
+            infos: read_zip_file(&mut archive, "infos.json")?.unwrap_or_default(),
+            choice_tests: read_zip_file(&mut archive, "choice_tests.json")?.unwrap_or_default(),
+            action_tests: read_zip_file(&mut archive, "action_tests.json")?.unwrap_or_default(),
+            templates: read_zip_file(&mut archive, "templates.json")?.unwrap_or_default(),
+            media: read_zip_file(&mut archive, "media.json")?.unwrap_or_default(),
+        })
+    }
+}
+
+/// It reads a JSON file of a zip archive, None if the archive has no such file.
+fn read_zip_file<T: DeserializeOwned>(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<T>, String> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut data = vec![];
+    file.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", name, e))
+}
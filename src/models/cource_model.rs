@@ -1,11 +1,15 @@
 // use std::str::FromStr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
 use uuid::Uuid;
+
+use crate::models::user_model::LearnerProgress;
+use crate::utils::locale::{default_locale, Localize, Locales};
 // use crate::models::{info_model::InfoModel, tests_model::TestModel};
 
 /*
@@ -34,6 +38,8 @@ use uuid::Uuid;
 /// * `title`: The title of the course.
 /// * `description`: A description of the course.
 /// * `levels`: HashMap<i32, Vec<Level>>,
+/// * `translations`: Translations of the course by locale.
+/// * `origin`: The course this course was cloned from, if it is a clone.
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct CourseModel {
@@ -43,6 +49,617 @@ pub struct CourseModel {
     pub description: String,
     #[serde_as(as = "Vec<(_, _)>")]
     pub levels: HashMap<i32, Vec<Level>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, CourseTranslation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<CourseOrigin>,
+}
+
+/// `CourseTranslation` is the translation of a course to one locale. Fields that are not set are
+/// shown in the default locale.
+///
+/// Properties:
+///
+/// * `title`: The translated title.
+/// * `description`: The translated description.
+/// * `level_titles`: The translated titles of the levels by level id.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct CourseTranslation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub level_titles: HashMap<String, String>,
+}
+
+/// `CourseOrigin` records where a cloned course comes from, so the clone can later be compared
+/// with its source.
+///
+/// Properties:
+///
+/// * `course_id`: The id of the source course.
+/// * `cloned_at`: Unix timestamp of the clone.
+/// * `deep`: Whether the infos, tests and templates were copied too, or are shared with the
+///   source.
+/// * `levels`: The ids of the source levels by the ids of the cloned levels.
+/// * `content`: The ids of the source content by the ids of the copied content, empty unless the
+///   clone is deep.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CourseOrigin {
+    pub course_id: String,
+    pub cloned_at: i64,
+    pub deep: bool,
+    #[serde(default)]
+    pub levels: HashMap<String, String>,
+    #[serde(default)]
+    pub content: HashMap<String, String>,
+}
+
+impl Localize for CourseModel {
+    fn localize(&mut self, locales: &Locales) -> String {
+        let served = match locales.pick(&self.translations) {
+            Some((locale, translation)) => {
+                if let Some(title) = &translation.title {
+                    self.title = title.clone();
+                }
+                if let Some(description) = &translation.description {
+                    self.description = description.clone();
+                }
+                for level in self.levels.values_mut().flatten() {
+                    if let Some(title) = translation.level_titles.get(&level.id) {
+                        level.title = title.clone();
+                    }
+                }
+                locale.to_string()
+            }
+            None => default_locale(),
+        };
+        self.translations.clear();
+        served
+    }
+
+    fn missing_translations(&self, locale: &str) -> Vec<String> {
+        let translation = self.translations.get(locale).cloned().unwrap_or_default();
+        let mut missing = vec![];
+        if translation.title.is_none() {
+            missing.push("title".to_string());
+        }
+        if translation.description.is_none() {
+            missing.push("description".to_string());
+        }
+        let mut layers: Vec<&i32> = self.levels.keys().collect();
+        layers.sort();
+        for layer in layers {
+            for level in &self.levels[layer] {
+                if !translation.level_titles.contains_key(&level.id) {
+                    missing.push(format!("levels.{}.title", level.id));
+                }
+            }
+        }
+        missing
+    }
+}
+
+/// The types a level can have.
+pub const LEVEL_TYPES: [&str; 2] = ["info", "test"];
+
+/// `LevelIssue` is a problem with one level of a course.
+///
+/// Properties:
+///
+/// * `layer`: The number of the layer the level is in.
+/// * `level_id`: The id of the level.
+/// * `detail`: What is wrong, e.g. the content id that does not exist.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LevelIssue {
+    pub layer: i32,
+    pub level_id: String,
+    pub detail: String,
+}
+
+/// `CourseReport` lists everything that is wrong with the level graph of a course.
+///
+/// Properties:
+///
+/// * `dangling_ids`: Content ids of levels that point at no info, test or template.
+/// * `type_mismatches`: Levels with an unknown `type_`, or with content of the other type.
+/// * `test_count_mismatches`: Levels whose `n_of_tests` is not the number of their ids.
+/// * `empty_layers`: Layers without levels.
+/// * `layer_gaps`: Layer numbers missing between the first and the last layer.
+/// * `duplicate_level_ids`: Level ids used by more than one level.
+/// * `unknown_prerequisites`: Prerequisites that are not levels of the course.
+/// * `cycles`: Level ids along prerequisite chains that lead back to their first level.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct CourseReport {
+    pub dangling_ids: Vec<LevelIssue>,
+    pub type_mismatches: Vec<LevelIssue>,
+    pub test_count_mismatches: Vec<LevelIssue>,
+    pub empty_layers: Vec<i32>,
+    pub layer_gaps: Vec<i32>,
+    pub duplicate_level_ids: Vec<String>,
+    #[serde(default)]
+    pub unknown_prerequisites: Vec<LevelIssue>,
+    #[serde(default)]
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl CourseReport {
+    /// It checks that nothing is wrong with the course.
+    pub fn is_valid(&self) -> bool {
+        self.dangling_ids.is_empty()
+            && self.type_mismatches.is_empty()
+            && self.test_count_mismatches.is_empty()
+            && self.empty_layers.is_empty()
+            && self.layer_gaps.is_empty()
+            && self.duplicate_level_ids.is_empty()
+            && self.unknown_prerequisites.is_empty()
+            && self.cycles.is_empty()
+    }
+}
+
+impl fmt::Display for CourseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = vec![];
+        for issue in &self.dangling_ids {
+            lines.push(format!(
+                "layer {}, level `{}`: unknown content `{}`",
+                issue.layer, issue.level_id, issue.detail
+            ));
+        }
+        for issue in self
+            .type_mismatches
+            .iter()
+            .chain(&self.test_count_mismatches)
+        {
+            lines.push(format!(
+                "layer {}, level `{}`: {}",
+                issue.layer, issue.level_id, issue.detail
+            ));
+        }
+        for layer in &self.empty_layers {
+            lines.push(format!("layer {} is empty", layer));
+        }
+        for layer in &self.layer_gaps {
+            lines.push(format!("layer {} is missing", layer));
+        }
+        for id in &self.duplicate_level_ids {
+            lines.push(format!("level id `{}` is used more than once", id));
+        }
+        for issue in &self.unknown_prerequisites {
+            lines.push(format!(
+                "layer {}, level `{}`: unknown prerequisite `{}`",
+                issue.layer, issue.level_id, issue.detail
+            ));
+        }
+        for cycle in &self.cycles {
+            lines.push(format!("prerequisite cycle: {}", cycle.join(" -> ")));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl CourseModel {
+    /// It returns the content ids of all the levels, every id once.
+    pub fn content_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .levels
+            .values()
+            .flatten()
+            .flat_map(|level| level.ids.iter().cloned())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /// It returns the prerequisites of every level: the declared ones, or all the levels of the
+    /// previous layer if the level declares none. Levels of the first layer have no default
+    /// prerequisites.
+    ///
+    /// Returns:
+    ///
+    /// The prerequisite level ids by level id.
+    pub fn prerequisite_graph(&self) -> HashMap<String, Vec<String>> {
+        let mut layers: Vec<&i32> = self.levels.keys().collect();
+        layers.sort();
+        let mut graph = HashMap::new();
+        let mut previous: Vec<String> = vec![];
+        for layer in layers {
+            for level in &self.levels[layer] {
+                let prerequisites = match &level.prerequisites {
+                    Some(prerequisites) => prerequisites.clone(),
+                    None => previous.clone(),
+                };
+                graph.insert(level.id.clone(), prerequisites);
+            }
+            if !self.levels[layer].is_empty() {
+                previous = self.levels[layer]
+                    .iter()
+                    .map(|level| level.id.clone())
+                    .collect();
+            }
+        }
+        graph
+    }
+
+    /// It finds the cycles of the prerequisite graph, the course can only be played if there
+    /// are none.
+    ///
+    /// Returns:
+    ///
+    /// Every cycle found, as the level ids along it starting and ending with the same level.
+    pub fn prerequisite_cycles(&self) -> Vec<Vec<String>> {
+        fn visit<'a>(
+            id: &'a str,
+            graph: &'a HashMap<String, Vec<String>>,
+            done: &mut HashSet<&'a str>,
+            path: &mut Vec<&'a str>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            if let Some(start) = path.iter().position(|step| *step == id) {
+                let mut cycle: Vec<String> = path[start..].iter().map(|s| s.to_string()).collect();
+                cycle.push(id.to_string());
+                cycles.push(cycle);
+                return;
+            }
+            if done.contains(id) {
+                return;
+            }
+            path.push(id);
+            for prerequisite in graph.get(id).into_iter().flatten() {
+                if graph.contains_key(prerequisite) {
+                    visit(prerequisite, graph, done, path, cycles);
+                }
+            }
+            path.pop();
+            done.insert(id);
+        }
+
+        let graph = self.prerequisite_graph();
+        let mut ids: Vec<&String> = graph.keys().collect();
+        ids.sort();
+        let mut done = HashSet::new();
+        let mut cycles = vec![];
+        for id in ids {
+            visit(id, &graph, &mut done, &mut vec![], &mut cycles);
+        }
+        cycles
+    }
+
+    /// It checks the level graph of the course.
+    ///
+    /// Arguments:
+    ///
+    /// * `content`: The type (`info` or `test`) of every content id that exists.
+    ///
+    /// Returns:
+    ///
+    /// A CourseReport, empty if the course is valid.
+    pub fn validate(&self, content: &HashMap<String, String>) -> CourseReport {
+        let mut report = CourseReport::default();
+        let mut layers: Vec<i32> = self.levels.keys().copied().collect();
+        layers.sort();
+        if let (Some(first), Some(last)) = (layers.first(), layers.last()) {
+            report.layer_gaps = (*first..=*last)
+                .filter(|layer| !self.levels.contains_key(layer))
+                .collect();
+        }
+
+        let level_ids: HashSet<&str> = self
+            .levels
+            .values()
+            .flatten()
+            .map(|level| level.id.as_str())
+            .collect();
+        let mut seen = HashSet::new();
+        for layer in layers {
+            let levels = &self.levels[&layer];
+            if levels.is_empty() {
+                report.empty_layers.push(layer);
+            }
+            for level in levels {
+                let issue = |detail: String| LevelIssue {
+                    layer,
+                    level_id: level.id.clone(),
+                    detail,
+                };
+                if !seen.insert(level.id.as_str())
+                    && !report.duplicate_level_ids.contains(&level.id)
+                {
+                    report.duplicate_level_ids.push(level.id.clone());
+                }
+                let known_type = LEVEL_TYPES.contains(&level.type_.as_str());
+                if !known_type {
+                    report.type_mismatches.push(issue(format!(
+                        "type `{}` is not `info` or `test`",
+                        level.type_
+                    )));
+                }
+                for id in &level.ids {
+                    match content.get(id) {
+                        None => report.dangling_ids.push(issue(id.clone())),
+                        Some(kind) if known_type && *kind != level.type_ => {
+                            report.type_mismatches.push(issue(format!(
+                                "`{}` is {} content, but the level is {}",
+                                id, kind, level.type_
+                            )))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                for prerequisite in level.prerequisites.iter().flatten() {
+                    if !level_ids.contains(prerequisite.as_str()) {
+                        report
+                            .unknown_prerequisites
+                            .push(issue(prerequisite.clone()));
+                    }
+                }
+                if let Some(n_of_tests) = level.n_of_tests {
+                    if usize::try_from(n_of_tests).ok() != Some(level.ids.len()) {
+                        report.test_count_mismatches.push(issue(format!(
+                            "n_of_tests is {}, but the level has {} ids",
+                            n_of_tests,
+                            level.ids.len()
+                        )));
+                    }
+                }
+            }
+        }
+        report.cycles = self.prerequisite_cycles();
+        report
+    }
+}
+
+/// `NodeState` is the state of a level for one learner.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeState {
+    /// Some prerequisite is not completed yet.
+    Locked,
+    /// All prerequisites are completed, the level is not started.
+    Available,
+    /// The learner has started the content of the level.
+    InProgress,
+    /// The level is passed.
+    Completed,
+}
+
+/// `MapLevel` is a level of a course with its state for the learner.
+///
+/// Properties:
+///
+/// * `level`: The level.
+/// * `state`: The state of the level.
+/// * `effective_prerequisites`: The declared prerequisites, or the levels of the previous layer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MapLevel {
+    #[serde(flatten)]
+    pub level: Level,
+    pub state: NodeState,
+    pub effective_prerequisites: Vec<String>,
+}
+
+/// `CourseMap` is a course as a learner sees it, every level annotated with its state.
+///
+/// Properties:
+///
+/// * `id`: The id of the course.
+/// * `title`: The title of the course.
+/// * `description`: A description of the course.
+/// * `layers`: The layers ordered by number, as `(number, levels)` pairs.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CourseMap {
+    pub id: Option<String>,
+    pub title: String,
+    pub description: String,
+    pub layers: Vec<(i32, Vec<MapLevel>)>,
+}
+
+impl CourseModel {
+    /// It finds a level of the course.
+    ///
+    /// Returns:
+    ///
+    /// The layer number and the level, or None if the course has no such level.
+    pub fn find_level(&self, level_id: &str) -> Option<(i32, &Level)> {
+        self.levels.iter().find_map(|(layer, levels)| {
+            levels
+                .iter()
+                .find(|level| level.id == level_id)
+                .map(|level| (*layer, level))
+        })
+    }
+
+    /// It finds the test levels a learner can start from after a placement test: the levels whose
+    /// tests are all at most as hard as the level the learner was placed at.
+    ///
+    /// Arguments:
+    ///
+    /// * `placed_out`: The ids of the tests and templates at or below the placed level of the
+    ///   learner for their theme.
+    ///
+    /// Returns:
+    ///
+    /// The ids of the unlocked levels.
+    pub fn placement_unlocked(&self, placed_out: &HashSet<String>) -> HashSet<String> {
+        self.levels
+            .values()
+            .flatten()
+            .filter(|level| {
+                level.type_ == "test"
+                    && !level.ids.is_empty()
+                    && level.ids.iter().all(|id| placed_out.contains(id))
+            })
+            .map(|level| level.id.clone())
+            .collect()
+    }
+
+    /// It computes the state of every level for a learner.
+    ///
+    /// Arguments:
+    ///
+    /// * `completed`: The ids of the levels the learner has passed.
+    /// * `started`: The ids of the infos and tests the learner has opened or answered.
+    /// * `unlocked`: The ids of the levels that are open whatever their prerequisites, see
+    ///   `placement_unlocked`.
+    ///
+    /// Returns:
+    ///
+    /// The state by level id.
+    pub fn node_states(
+        &self,
+        completed: &HashSet<String>,
+        started: &HashSet<String>,
+        unlocked: &HashSet<String>,
+    ) -> HashMap<String, NodeState> {
+        self.prerequisite_graph()
+            .into_iter()
+            .map(|(id, prerequisites)| {
+                let level = self.find_level(&id).map(|(_, level)| level);
+                let state = if completed.contains(&id) {
+                    NodeState::Completed
+                } else if !unlocked.contains(&id)
+                    && !prerequisites.iter().all(|p| completed.contains(p))
+                {
+                    NodeState::Locked
+                } else if level.is_some_and(|level| level.ids.iter().any(|c| started.contains(c))) {
+                    NodeState::InProgress
+                } else {
+                    NodeState::Available
+                };
+                (id, state)
+            })
+            .collect()
+    }
+
+    /// It builds the map of the course for a learner.
+    ///
+    /// Arguments:
+    ///
+    /// * `completed`: The ids of the levels the learner has passed.
+    /// * `started`: The ids of the infos and tests the learner has opened or answered.
+    /// * `unlocked`: The ids of the levels that are open whatever their prerequisites.
+    ///
+    /// Returns:
+    ///
+    /// A CourseMap
+    pub fn map(
+        self,
+        completed: &HashSet<String>,
+        started: &HashSet<String>,
+        unlocked: &HashSet<String>,
+    ) -> CourseMap {
+        let states = self.node_states(completed, started, unlocked);
+        let mut graph = self.prerequisite_graph();
+        let mut layers: Vec<(i32, Vec<Level>)> = self.levels.into_iter().collect();
+        layers.sort_by_key(|(number, _)| *number);
+        CourseMap {
+            id: self.id.map(|id| id.to_hex()),
+            title: self.title,
+            description: self.description,
+            layers: layers
+                .into_iter()
+                .map(|(number, levels)| {
+                    let levels = levels
+                        .into_iter()
+                        .map(|level| MapLevel {
+                            state: states[&level.id],
+                            effective_prerequisites: graph.remove(&level.id).unwrap_or_default(),
+                            level,
+                        })
+                        .collect();
+                    (number, levels)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// `CourseProgress` is how far through a course a learner is.
+///
+/// Properties:
+///
+/// * `cource_id`: The id of the course.
+/// * `title`: The title of the course.
+/// * `completed_nodes`, `total_nodes`: The passed levels and all the levels.
+/// * `completed_tests`, `total_tests`: The correctly answered tests and all the tests of the levels.
+/// * `completed_infos`, `total_infos`: The read infos and all the infos of the levels.
+/// * `current_layer`: The first layer with a level that is not passed, None once all are passed.
+/// * `last_activity`: Unix timestamp of the last attempt on a test of the course.
+/// * `completed`: Whether all the levels are passed.
+/// * `enrolled_at`: Unix timestamp of joining the course.
+/// * `completed_at`: Unix timestamp of completing the course.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CourseProgress {
+    pub cource_id: String,
+    pub title: String,
+    pub completed_nodes: usize,
+    pub total_nodes: usize,
+    pub completed_tests: usize,
+    pub total_tests: usize,
+    pub completed_infos: usize,
+    pub total_infos: usize,
+    pub current_layer: Option<i32>,
+    pub last_activity: Option<i64>,
+    pub completed: bool,
+    pub enrolled_at: Option<i64>,
+    pub completed_at: Option<i64>,
+}
+
+impl CourseModel {
+    /// It computes how far through the course a learner is.
+    ///
+    /// Arguments:
+    ///
+    /// * `progress`: The progress of the learner.
+    ///
+    /// Returns:
+    ///
+    /// A CourseProgress
+    pub fn progress(&self, progress: &LearnerProgress) -> CourseProgress {
+        let levels: Vec<&Level> = self.levels.values().flatten().collect();
+        let content = |type_: &str| -> HashSet<&String> {
+            levels
+                .iter()
+                .filter(|level| level.type_ == type_)
+                .flat_map(|level| &level.ids)
+                .collect()
+        };
+        let (tests, infos) = (content("test"), content("info"));
+        let completed_nodes = levels
+            .iter()
+            .filter(|level| progress.completed.contains(&level.id))
+            .count();
+        CourseProgress {
+            cource_id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
+            title: self.title.clone(),
+            completed_nodes,
+            total_nodes: levels.len(),
+            completed_tests: tests.iter().filter(|id| progress.passed.contains(**id)).count(),
+            total_tests: tests.len(),
+            completed_infos: infos.iter().filter(|id| progress.read.contains(**id)).count(),
+            total_infos: infos.len(),
+            current_layer: self
+                .levels
+                .iter()
+                .filter(|(_, levels)| {
+                    levels
+                        .iter()
+                        .any(|level| !progress.completed.contains(&level.id))
+                })
+                .map(|(layer, _)| *layer)
+                .min(),
+            last_activity: tests
+                .iter()
+                .filter_map(|id| progress.last_activity.get(*id))
+                .max()
+                .copied(),
+            completed: !levels.is_empty() && completed_nodes == levels.len(),
+            enrolled_at: None,
+            completed_at: None,
+        }
+    }
 }
 
 /* JSON example:
@@ -62,9 +679,15 @@ pub struct CourseModel {
 /// Properties:
 ///
 /// * `id`: The id of the level.
-/// * `ids`: The ids of the content in the level.
+/// * `ids`: The ids of the content in the level. Test levels can also point at question templates,
+/// a new question is generated from the template every time the level is played.
 /// * `title`: The title of the level.
 /// * `mini_image`: The image that will be displayed in the level selection screen.
+/// * `mini_image_success`: The image that is displayed once the level is passed.
+/// * `media_id`: An uploaded image. If it is set, `mini_image` and `mini_image_success` are
+/// generated from it when the course is saved.
+/// * `prerequisites`: The ids of the levels that must be passed before this one. If it is not set,
+/// the level depends on all the levels of the previous layer.
 /// * `type_`: The type of the level (info or test).
 /// * `n_of_tests`: The number of tests in the level.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -72,10 +695,16 @@ pub struct Level {
     pub id: String,              // UUID of level
     pub ids: Vec<String>,        // content ids
     pub title: String,           // title of info or test
+    #[serde(default)]
     pub mini_image: String,      // mini image of info or test, url
+    #[serde(default)]
     pub mini_image_success: String, // mini image of info or test, url
     pub type_: String,           // type of level (info or test)
     pub n_of_tests: Option<i32>, // number of tests in the level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_id: Option<String>, // uploaded image the mini images are generated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prerequisites: Option<Vec<String>>, // ids of levels that must be passed first
 }
 
 #[allow(dead_code)]
@@ -98,6 +727,8 @@ impl Level {
                 mini_image_success,
                 type_,
                 n_of_tests,
+                media_id: None,
+                prerequisites: None,
             },
             id,
         )
@@ -112,6 +743,8 @@ impl Level {
             mini_image_success: self.mini_image_success.clone(),
             type_: self.type_.clone(),
             n_of_tests: self.n_of_tests,
+            media_id: self.media_id.clone(),
+            prerequisites: self.prerequisites.clone(),
         }
     }
 }
@@ -6,6 +6,8 @@ use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
+use crate::utils::locale::{default_locale, Locales, Localize};
+
 /// `InfoModel` is a struct with three fields, `id`, `title`, and `content_levels`. The `id` field is an
 /// `Option<ObjectId>`, which means it can be `None` or `Some(ObjectId)`. The `title` field is a
 /// `String`. The `content_levels` field is a `HashMap<i32, Vec<ContentLevel>>`.
@@ -17,8 +19,12 @@ use serde_with::serde_as;
 ///
 /// * `id`: The id of the info model.
 /// * `title`: The title of the info model.
+/// * `theme`: The slug of the theme of the info.
+/// * `theme_id`: The id of the theme of the info in the `themes` collection.
+/// * `skills`: The ids of the skills the info teaches.
 /// * `content_levels`: A HashMap of the form `HashMap<i32, Vec<ContentLevel>>` where the key is the
 /// level of the content and the value is a vector of ContentLevels.
+/// * `translations`: Translations of the info by locale.
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct InfoModel {
@@ -26,25 +32,244 @@ pub struct InfoModel {
     pub id: Option<ObjectId>,
     pub title: String,
     pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skills: Vec<String>,
     #[serde_as(as = "Vec<(_, _)>")]
     pub content_levels: HashMap<i32, Vec<ContentLevel>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, InfoTranslation>,
 }
 
-/// `ContentLevel` is a struct that contains a `String` and a `Vec<u8>`.
-///
-/// The `#[serde_as]` attribute is a custom attribute that tells the `serde` library to use the
-/// `serde_as` crate to serialize and deserialize this type.
-///
-/// The `#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]` attribute is a standard `serde`
-/// attribute that tells the `serde` library to automatically generate the code needed to
+/// `InfoTranslation` is the translation of an info to one locale. A translated level replaces all
+/// the blocks of that level, levels that are not translated are shown in the default locale.
 ///
 /// Properties:
 ///
-/// * `content_type`: The content type of the data.
-/// * `data`: The actual data of the level. For text is text for image is url to IPFS.
+/// * `title`: The translated title.
+/// * `content_levels`: The translated levels.
 #[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct InfoTranslation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default)]
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub content_levels: HashMap<i32, Vec<ContentLevel>>,
+}
+
+/// `ContentLevel` is one block of content of an info. The kind of the block is stored in the
+/// `content_type` field, every kind has its own fields.
+///
+/// JSON example:
+///
+/// `{"content_type": "image", "url": "https://...", "alt": "A right triangle"}`
+///
+/// Variants:
+///
+/// * `Text`: Plain text.
+/// * `Markdown`: Text in Markdown.
+/// * `LaTeX`: Text with LaTeX math, e.g. `$x^2$`.
+/// * `Image`: An image, `url` points at the image (e.g. `/media/<id>` of an upload) and `alt`
+///   describes it.
+/// * `Video`: A video, `url` points at the video.
+/// * `Code`: A code snippet written in `lang`.
+/// * `Callout`: A highlighted note.
+/// * `Formula`: A single LaTeX formula shown on its own line.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct ContentLevel {
-    pub content_type: String,
-    pub data: String,
+#[serde(tag = "content_type", rename_all = "lowercase")]
+pub enum ContentLevel {
+    Text {
+        data: String,
+    },
+    Markdown {
+        data: String,
+    },
+    #[serde(rename = "latex")]
+    LaTeX {
+        data: String,
+    },
+    Image {
+        url: String,
+        #[serde(default)]
+        alt: String,
+    },
+    Video {
+        url: String,
+    },
+    Code {
+        lang: String,
+        data: String,
+    },
+    Callout {
+        data: String,
+    },
+    Formula {
+        data: String,
+    },
+}
+
+/// It checks that the url of an image or a video is absolute, relative to the server or an IPFS
+/// reference.
+fn is_media_url(url: &str) -> bool {
+    ["https://", "http://", "ipfs://", "/"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+impl ContentLevel {
+    /// It checks that the block can be rendered: text blocks are not empty, urls are absolute
+    /// http(s) urls or paths on this server and code blocks have a language.
+    ///
+    /// Returns:
+    ///
+    /// A Result<(), String> with the reason why the block is invalid.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ContentLevel::Text { data }
+            | ContentLevel::Markdown { data }
+            | ContentLevel::LaTeX { data }
+            | ContentLevel::Callout { data }
+            | ContentLevel::Formula { data }
+                if data.trim().is_empty() =>
+            {
+                Err("data is empty".to_string())
+            }
+            ContentLevel::Image { url, .. } | ContentLevel::Video { url } if !is_media_url(url) => {
+                Err(format!("`{}` is not a valid url", url))
+            }
+            ContentLevel::Code { lang, .. } if lang.trim().is_empty() => {
+                Err("code block has no language".to_string())
+            }
+            ContentLevel::Code { data, .. } if data.trim().is_empty() => {
+                Err("data is empty".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// It converts a block of the old `{content_type: String, data: String}` shape into a typed
+    /// block. Unknown content types become text, code without a language is plain text code and
+    /// image or video data that is not a url is an IPFS hash, as the old blocks stored them.
+    ///
+    /// Arguments:
+    ///
+    /// * `content_type`: The old free-form content type.
+    /// * `data`: The old data, for images and videos it is the url.
+    ///
+    /// Returns:
+    ///
+    /// The typed block and whether the content type was recognized.
+    pub fn from_legacy(content_type: &str, data: String) -> (Self, bool) {
+        let content_type = content_type.trim().to_lowercase();
+        let (kind, lang) = match content_type.split_once(':') {
+            Some((kind, lang)) => (kind.to_string(), lang.to_string()),
+            None => (content_type, String::new()),
+        };
+        let media_url = |data: String| {
+            let data = data.trim();
+            if data.is_empty() || is_media_url(data) {
+                data.to_string()
+            } else {
+                format!("ipfs://{}", data)
+            }
+        };
+        let block = match kind.as_str() {
+            "text" | "txt" | "plain" | "string" => ContentLevel::Text { data },
+            "markdown" | "md" => ContentLevel::Markdown { data },
+            "latex" | "tex" | "math" => ContentLevel::LaTeX { data },
+            "formula" | "equation" => ContentLevel::Formula { data },
+            "image" | "img" | "picture" | "photo" => ContentLevel::Image {
+                url: media_url(data),
+                alt: String::new(),
+            },
+            "video" => ContentLevel::Video { url: media_url(data) },
+            "code" if lang.trim().is_empty() => ContentLevel::Code {
+                lang: "text".to_string(),
+                data,
+            },
+            "code" => ContentLevel::Code { lang, data },
+            "callout" | "note" | "warning" | "tip" => ContentLevel::Callout { data },
+            _ => return (ContentLevel::Text { data }, false),
+        };
+        (block, true)
+    }
+}
+
+impl InfoModel {
+    /// It validates the title and every content block of the info and of its translations.
+    ///
+    /// Returns:
+    ///
+    /// A list of problems, empty if the info is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.title.trim().is_empty() {
+            errors.push("title is empty".to_string());
+        }
+        errors.extend(validate_levels(&self.content_levels, ""));
+
+        let mut locales: Vec<&String> = self.translations.keys().collect();
+        locales.sort();
+        for locale in locales {
+            let prefix = format!("translation `{}`, ", locale);
+            let translation = &self.translations[locale];
+            if matches!(&translation.title, Some(title) if title.trim().is_empty()) {
+                errors.push(format!("{}title is empty", prefix));
+            }
+            errors.extend(validate_levels(&translation.content_levels, &prefix));
+        }
+        errors
+    }
+}
+
+/// It validates every block of the levels, the problems start with `prefix`.
+fn validate_levels(content_levels: &HashMap<i32, Vec<ContentLevel>>, prefix: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut levels: Vec<&i32> = content_levels.keys().collect();
+    levels.sort();
+    for level in levels {
+        for (i, block) in content_levels[level].iter().enumerate() {
+            if let Err(e) = block.validate() {
+                errors.push(format!("{}level {}, block {}: {}", prefix, level, i + 1, e));
+            }
+        }
+    }
+    errors
+}
+
+impl Localize for InfoModel {
+    fn localize(&mut self, locales: &Locales) -> String {
+        let served = match locales.pick(&self.translations) {
+            Some((locale, translation)) => {
+                if let Some(title) = &translation.title {
+                    self.title = title.clone();
+                }
+                for (level, blocks) in &translation.content_levels {
+                    self.content_levels.insert(*level, blocks.clone());
+                }
+                locale.to_string()
+            }
+            None => default_locale(),
+        };
+        self.translations.clear();
+        served
+    }
+
+    fn missing_translations(&self, locale: &str) -> Vec<String> {
+        let translation = self.translations.get(locale).cloned().unwrap_or_default();
+        let mut missing = vec![];
+        if translation.title.is_none() {
+            missing.push("title".to_string());
+        }
+        let mut levels: Vec<&i32> = self.content_levels.keys().collect();
+        levels.sort();
+        for level in levels {
+            if !translation.content_levels.contains_key(level) {
+                missing.push(format!("content_levels.{}", level));
+            }
+        }
+        missing
+    }
 }
@@ -0,0 +1,129 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// The biggest upload that is accepted when `MEDIA_MAX_BYTES` is not set, 5 MiB.
+pub const DEFAULT_MAX_MEDIA_BYTES: usize = 5 * 1024 * 1024;
+
+/// Content types of the images that can be uploaded.
+pub const ALLOWED_MEDIA_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// `MediaModel` is the metadata of an uploaded file. The bytes live in a `MediaStorage` under
+/// `storage_key`.
+///
+/// Properties:
+///
+/// * `id`: The id of the media, used in the download url.
+/// * `storage_key`: The key of the bytes in the storage, the sha256 of the content.
+/// * `content_type`: The content type detected from the bytes.
+/// * `size`: The size in bytes.
+/// * `file_name`: The name of the uploaded file, if it was given.
+/// * `uploaded_at`: Unix timestamp of the upload.
+/// * `renditions`: The renditions that have been generated and stored so far.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MediaModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub storage_key: String,
+    pub content_type: String,
+    pub size: i64,
+    pub file_name: Option<String>,
+    pub uploaded_at: i64,
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+}
+
+/// `Rendition` is an image generated from an uploaded image.
+///
+/// Variants:
+///
+/// * `Thumbnail`: The image scaled down to a thumbnail.
+/// * `Success`: The thumbnail tinted to show that a level is passed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rendition {
+    Thumbnail,
+    Success,
+}
+
+impl Rendition {
+    /// It returns the name of the rendition used in urls and storage keys.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rendition::Thumbnail => "thumbnail",
+            Rendition::Success => "success",
+        }
+    }
+}
+
+impl MediaModel {
+    /// It returns the url the media is served from.
+    pub fn url(&self) -> String {
+        format!(
+            "/media/{}",
+            self.id.map(|id| id.to_hex()).unwrap_or_default()
+        )
+    }
+
+    /// It returns the url a rendition of the media is served from.
+    pub fn rendition_url(&self, rendition: Rendition) -> String {
+        format!("{}/{}", self.url(), rendition.name())
+    }
+
+    /// It returns the key a rendition of the media is stored under.
+    pub fn rendition_key(&self, rendition: Rendition) -> String {
+        format!("{}-{}", self.storage_key, rendition.name())
+    }
+}
+
+/// It detects the content type of an image from its first bytes. The content type sent by the
+/// client is not trusted.
+///
+/// Arguments:
+///
+/// * `data`: The content of the file.
+///
+/// Returns:
+///
+/// One of `ALLOWED_MEDIA_TYPES`, or None if the file is not a supported image.
+pub fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// It checks that an upload is a supported image that is not too big.
+///
+/// Arguments:
+///
+/// * `data`: The content of the file.
+/// * `max_bytes`: The biggest accepted size.
+///
+/// Returns:
+///
+/// The detected content type, or the reason the file is rejected.
+pub fn validate_upload(data: &[u8], max_bytes: usize) -> Result<&'static str, String> {
+    if data.is_empty() {
+        return Err("the file is empty".to_string());
+    }
+    if data.len() > max_bytes {
+        return Err(format!(
+            "the file has {} bytes, at most {} are allowed",
+            data.len(),
+            max_bytes
+        ));
+    }
+    sniff_content_type(data).ok_or_else(|| {
+        format!(
+            "unsupported file type, allowed are {}",
+            ALLOWED_MEDIA_TYPES.join(", ")
+        )
+    })
+}
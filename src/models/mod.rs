@@ -3,3 +3,18 @@ pub mod cource_model;
 pub mod info_model;
 pub mod tests_model;
 pub mod response;
+pub mod template_model;
+pub mod attempt_model;
+pub mod media_model;
+pub mod publication_model;
+pub mod revision_model;
+pub mod bundle_model;
+pub mod theme_model;
+pub mod skill_model;
+pub mod question_import_model;
+pub mod qti_model;
+pub mod scorm_model;
+pub mod rating_model;
+pub mod review_model;
+pub mod placement_model;
+pub mod quiz_model;
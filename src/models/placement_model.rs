@@ -0,0 +1,134 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    attempt_model::QuestionKind,
+    rating_model::{expected_score, rating_level, DEFAULT_RATING},
+    user_model::Placement,
+};
+
+/// How many questions a placement test asks.
+pub const PLACEMENT_QUESTIONS: usize = 6;
+
+/// How far the estimate moves after the first answer of a placement test, two levels for a
+/// correct answer to a question of the estimated level. The step is divided by the number of
+/// answers, so the estimate settles.
+pub const PLACEMENT_K_FACTOR: f64 = 400.0;
+
+/// `PlacementStep` is a question of a placement test.
+///
+/// Properties:
+///
+/// * `question_id`: The id of the test or of the template.
+/// * `kind`: The kind of the question.
+/// * `seed`: The seed of a question generated from a template.
+/// * `difficulty`: The rating of the question when it was asked.
+/// * `correct`: Whether the answer was correct, None until the question is answered.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlacementStep {
+    pub question_id: String,
+    pub kind: QuestionKind,
+    pub seed: Option<i64>,
+    pub difficulty: f64,
+    pub correct: Option<bool>,
+}
+
+/// `PlacementModel` is a placement test of a user for a theme, stored in the `placements`
+/// collection. Every question is picked near the current estimate, like the adaptive selection of
+/// tests, and every answer moves the estimate like an Elo rating.
+///
+/// Properties:
+///
+/// * `id`: The id of the placement test.
+/// * `user_id`: The id of the user.
+/// * `theme`: The slug of the theme, or the free-text theme as a slug.
+/// * `theme_id`: The id of the theme if it is in the taxonomy.
+/// * `rating`: The current estimate as a rating.
+/// * `steps`: The questions asked so far, the last one may not be answered yet.
+/// * `level`: The estimated level from 1 to 5, set when the test is finished.
+/// * `started_at`: Unix timestamp of the start.
+/// * `finished_at`: Unix timestamp of the end.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlacementModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    pub rating: f64,
+    pub steps: Vec<PlacementStep>,
+    pub level: Option<i32>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+impl PlacementModel {
+    /// It starts a placement test at level 3.
+    pub fn new(user_id: &str, theme: &str, theme_id: Option<String>, now: i64) -> Self {
+        Self {
+            id: None,
+            user_id: user_id.to_string(),
+            theme: theme.to_string(),
+            theme_id,
+            rating: DEFAULT_RATING,
+            steps: vec![],
+            level: None,
+            started_at: now,
+            finished_at: None,
+        }
+    }
+
+    /// It returns the question that waits for an answer.
+    pub fn current(&self) -> Option<&PlacementStep> {
+        self.steps.last().filter(|step| step.correct.is_none())
+    }
+
+    /// It returns how many questions were answered.
+    pub fn answered(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.correct.is_some())
+            .count()
+    }
+
+    /// It records the answer to the current question and moves the estimate.
+    ///
+    /// Arguments:
+    ///
+    /// * `correct`: Whether the answer was correct.
+    ///
+    /// Returns:
+    ///
+    /// False if no question waits for an answer.
+    pub fn answer(&mut self, correct: bool) -> bool {
+        let difficulty = match self.current() {
+            Some(step) => step.difficulty,
+            None => return false,
+        };
+        let k = PLACEMENT_K_FACTOR / (self.answered() + 1) as f64;
+        let outcome = if correct { 1.0 } else { 0.0 };
+        self.rating += k * (outcome - expected_score(self.rating, difficulty));
+        if let Some(step) = self.steps.last_mut() {
+            step.correct = Some(correct);
+        }
+        true
+    }
+
+    /// It ends the placement test with the level of the estimate.
+    ///
+    /// Returns:
+    ///
+    /// The placement to store on the profile of the user.
+    pub fn finish(&mut self, now: i64) -> Placement {
+        let level = rating_level(self.rating);
+        self.level = Some(level);
+        self.finished_at = Some(now);
+        Placement {
+            theme: self.theme.clone(),
+            theme_id: self.theme_id.clone(),
+            level,
+            placed_at: now,
+        }
+    }
+}
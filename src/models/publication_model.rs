@@ -0,0 +1,187 @@
+use mongodb::bson::{oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+/// `ContentKind` is the kind of a document that goes through the publication workflow.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    Course,
+    Info,
+    Choice,
+    Action,
+    Template,
+}
+
+impl ContentKind {
+    /// It returns the name of the kind, as used in routes and stored documents.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentKind::Course => "course",
+            ContentKind::Info => "info",
+            ContentKind::Choice => "choice",
+            ContentKind::Action => "action",
+            ContentKind::Template => "template",
+        }
+    }
+
+    /// It parses the name of a kind.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ContentKind::Course,
+            ContentKind::Info,
+            ContentKind::Choice,
+            ContentKind::Action,
+            ContentKind::Template,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+/// `PublicationStatus` is the step of the workflow a document is in. Learners only ever see the
+/// last published snapshot, whatever the status of the working copy is, unless it is archived.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicationStatus {
+    /// The document is being written.
+    #[default]
+    Draft,
+    /// The document waits for a reviewer.
+    InReview,
+    /// A reviewer approved the document and its snapshot is served to learners.
+    Published,
+    /// The document is withdrawn from learners.
+    Archived,
+}
+
+impl PublicationStatus {
+    /// It checks whether a document can move from this status to the next one.
+    ///
+    /// * A draft is submitted for review, or archived.
+    /// * A review ends with an approval (published) or a rejection (draft).
+    /// * Changes to a published document are submitted for review again.
+    /// * An archived document is restored as a draft.
+    pub fn can_become(self, next: PublicationStatus) -> bool {
+        use PublicationStatus::*;
+        matches!(
+            (self, next),
+            (Draft, InReview)
+                | (Draft, Archived)
+                | (InReview, Draft)
+                | (InReview, Published)
+                | (Published, InReview)
+                | (Published, Archived)
+                | (Archived, Draft)
+        )
+    }
+}
+
+/// `PublicationModel` is the publication state of one document.
+///
+/// Properties:
+///
+/// * `id`: The id of the publication.
+/// * `kind`: The kind of the document.
+/// * `content_id`: The id of the document.
+/// * `status`: The step of the workflow the working copy is in.
+/// * `version`: How many times the document was published.
+/// * `snapshot`: The document as it was when it was last approved, this is what learners see.
+/// * `published_at`: Unix timestamp of the last approval.
+/// * `reviewed_by`: The reviewer of the last approval or rejection.
+/// * `review_comment`: The comment of the reviewer.
+/// * `updated_at`: Unix timestamp of the last status change.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PublicationModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub kind: ContentKind,
+    pub content_id: String,
+    pub status: PublicationStatus,
+    pub version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Document>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_comment: Option<String>,
+    pub updated_at: i64,
+}
+
+impl PublicationModel {
+    /// It creates the publication of a document that was never submitted.
+    pub fn new(kind: ContentKind, content_id: &str) -> Self {
+        Self {
+            id: None,
+            kind,
+            content_id: content_id.to_string(),
+            status: PublicationStatus::Draft,
+            version: 0,
+            snapshot: None,
+            published_at: None,
+            reviewed_by: None,
+            review_comment: None,
+            updated_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// It moves the document to the next step of the workflow.
+    ///
+    /// Arguments:
+    ///
+    /// * `next`: The next status.
+    /// * `now`: Unix timestamp of the change.
+    ///
+    /// Returns:
+    ///
+    /// Nothing, or the reason the document can not move to the status.
+    pub fn transition(&mut self, next: PublicationStatus, now: i64) -> Result<(), String> {
+        if !self.status.can_become(next) {
+            return Err(format!(
+                "a {} document can not become {}",
+                status_name(self.status),
+                status_name(next)
+            ));
+        }
+        if self.status == PublicationStatus::Archived {
+            // a restored document is only visible again once it is approved again
+            self.snapshot = None;
+        }
+        self.status = next;
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// It stores the snapshot learners see from now on.
+    ///
+    /// Arguments:
+    ///
+    /// * `snapshot`: The document as it is approved.
+    /// * `now`: Unix timestamp of the approval.
+    ///
+    /// Returns:
+    ///
+    /// Nothing, or the reason the document can not be published.
+    pub fn publish(&mut self, snapshot: Document, now: i64) -> Result<(), String> {
+        self.transition(PublicationStatus::Published, now)?;
+        self.snapshot = Some(snapshot);
+        self.version += 1;
+        self.published_at = Some(now);
+        Ok(())
+    }
+
+    /// It checks whether learners can see the document.
+    pub fn is_visible(&self) -> bool {
+        self.snapshot.is_some() && self.status != PublicationStatus::Archived
+    }
+}
+
+fn status_name(status: PublicationStatus) -> &'static str {
+    match status {
+        PublicationStatus::Draft => "draft",
+        PublicationStatus::InReview => "in review",
+        PublicationStatus::Published => "published",
+        PublicationStatus::Archived => "archived",
+    }
+}
@@ -0,0 +1,526 @@
+use std::io::{Cursor, Read, Write};
+
+use quick_xml::{escape::escape, events::Event, Reader};
+use serde::Serialize;
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::models::{
+    publication_model::ContentKind,
+    question_import_model::{new_test, ImportDefaults, ImportIssue, ImportedQuestion},
+    template_model::QuestionTemplate,
+    tests_model::{TestModel, TestModelWithActions},
+};
+
+/// The namespace of QTI 2.1 items.
+pub const QTI_NAMESPACE: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1";
+
+/// The resource type of a QTI 2.1 item in the manifest of a content package.
+pub const QTI_ITEM_TYPE: &str = "imsqti_item_xmlv2p1";
+
+/// The file of a QTI package that lists the items that could not be exported.
+pub const QTI_REPORT_FILE: &str = "export-report.json";
+
+/// `QtiIssue` is a document that could not be represented in QTI.
+///
+/// Properties:
+///
+/// * `id`: The id of the document.
+/// * `kind`: The kind of the document.
+/// * `message`: Why it could not be represented.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct QtiIssue {
+    pub id: String,
+    pub kind: ContentKind,
+    pub message: String,
+}
+
+/// `QtiExportReport` is what an export to QTI contains.
+///
+/// Properties:
+///
+/// * `items`: The ids of the exported tests.
+/// * `skipped`: The tests and templates that could not be represented.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct QtiExportReport {
+    pub items: Vec<String>,
+    pub skipped: Vec<QtiIssue>,
+}
+
+/// `QtiPackage` is a question bank as QTI 2.1 items.
+///
+/// Properties:
+///
+/// * `items`: The identifier and the XML of every item.
+/// * `report`: What was exported and what was not.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QtiPackage {
+    pub items: Vec<(String, String)>,
+    pub report: QtiExportReport,
+}
+
+fn item_header(identifier: &str, question: &str) -> String {
+    let title: String = question.chars().take(60).collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <assessmentItem xmlns=\"{ns}\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"{ns} http://www.imsglobal.org/xsd/qti/qtiv2p1/imsqti_v2p1.xsd\" \
+         identifier=\"{id}\" title=\"{title}\" adaptive=\"false\" timeDependent=\"false\">\n",
+        ns = QTI_NAMESPACE,
+        id = identifier,
+        title = escape(&title),
+    )
+}
+
+/// It writes a choice test as a QTI item with a `choiceInteraction`, scored with the
+/// `match_correct` template. Hints, explanations and translations are not exported.
+///
+/// Returns:
+///
+/// The XML of the item, or why the test can not be represented.
+pub fn choice_item(identifier: &str, test: &TestModel) -> Result<String, String> {
+    let correct = test
+        .answers
+        .iter()
+        .position(|answer| *answer == test.answer)
+        .ok_or("the correct answer is not one of the answers")?;
+    if test.answers.len() < 2 {
+        return Err("a choice needs at least two answers".to_string());
+    }
+    let mut xml = item_header(identifier, &test.question);
+    xml.push_str(&format!(
+        "  <responseDeclaration identifier=\"RESPONSE\" cardinality=\"single\" baseType=\"identifier\">\n\
+         \x20   <correctResponse><value>CHOICE-{}</value></correctResponse>\n\
+         \x20 </responseDeclaration>\n\
+         \x20 <outcomeDeclaration identifier=\"SCORE\" cardinality=\"single\" baseType=\"float\"/>\n\
+         \x20 <itemBody>\n\
+         \x20   <choiceInteraction responseIdentifier=\"RESPONSE\" shuffle=\"false\" maxChoices=\"1\">\n\
+         \x20     <prompt>{}</prompt>\n",
+        correct + 1,
+        escape(&test.question)
+    ));
+    for (i, answer) in test.answers.iter().enumerate() {
+        xml.push_str(&format!(
+            "      <simpleChoice identifier=\"CHOICE-{}\">{}</simpleChoice>\n",
+            i + 1,
+            escape(answer)
+        ));
+    }
+    xml.push_str(
+        "    </choiceInteraction>\n\
+         \x20 </itemBody>\n\
+         \x20 <responseProcessing template=\"http://www.imsglobal.org/question/qti_v2p1/rptemplates/match_correct\"/>\n\
+         </assessmentItem>\n",
+    );
+    Ok(xml)
+}
+
+/// It writes an action test as a QTI item with a `textEntryInteraction`, scored with the
+/// `match_correct` template. The answer is compared as a number if it is one, as text otherwise.
+/// The steps of the solution, hints, explanations and translations are not exported.
+///
+/// Returns:
+///
+/// The XML of the item, or why the test can not be represented.
+pub fn text_entry_item(identifier: &str, test: &TestModelWithActions) -> Result<String, String> {
+    let answer = test.answer.trim();
+    if answer.is_empty() {
+        return Err("the correct answer is empty".to_string());
+    }
+    let base_type = if answer.parse::<f64>().is_ok() {
+        "float"
+    } else {
+        "string"
+    };
+    let mut xml = item_header(identifier, &test.question);
+    xml.push_str(&format!(
+        "  <responseDeclaration identifier=\"RESPONSE\" cardinality=\"single\" baseType=\"{}\">\n\
+         \x20   <correctResponse><value>{}</value></correctResponse>\n\
+         \x20 </responseDeclaration>\n\
+         \x20 <outcomeDeclaration identifier=\"SCORE\" cardinality=\"single\" baseType=\"float\"/>\n\
+         \x20 <itemBody>\n\
+         \x20   <p>{} <textEntryInteraction responseIdentifier=\"RESPONSE\" expectedLength=\"{}\"/></p>\n\
+         \x20 </itemBody>\n\
+         \x20 <responseProcessing template=\"http://www.imsglobal.org/question/qti_v2p1/rptemplates/match_correct\"/>\n\
+         </assessmentItem>\n",
+        base_type,
+        escape(answer),
+        escape(&test.question),
+        answer.chars().count()
+    ));
+    Ok(xml)
+}
+
+impl QtiPackage {
+    /// It writes a question bank as QTI items. Templates generate a new question for every
+    /// learner, they are reported as not representable.
+    ///
+    /// Arguments:
+    ///
+    /// * `choice_tests`: The choice tests, exported as `choiceInteraction` items.
+    /// * `action_tests`: The action tests, exported as `textEntryInteraction` items.
+    /// * `templates`: The templates of the bank.
+    ///
+    /// Returns:
+    ///
+    /// A QtiPackage
+    pub fn new(
+        choice_tests: &[TestModel],
+        action_tests: &[TestModelWithActions],
+        templates: &[QuestionTemplate],
+    ) -> Self {
+        let mut package = Self::default();
+        let id_of = |id: &Option<mongodb::bson::oid::ObjectId>| {
+            id.map(|id| id.to_hex()).unwrap_or_default()
+        };
+        for test in choice_tests {
+            let id = id_of(&test.id);
+            let item = choice_item(&format!("item-{}", id), test);
+            package.push(id, ContentKind::Choice, item);
+        }
+        for test in action_tests {
+            let id = id_of(&test.id);
+            let item = text_entry_item(&format!("item-{}", id), test);
+            package.push(id, ContentKind::Action, item);
+        }
+        for template in templates {
+            package.report.skipped.push(QtiIssue {
+                id: id_of(&template.id),
+                kind: ContentKind::Template,
+                message: "a template generates its questions, QTI items are fixed".to_string(),
+            });
+        }
+        package
+    }
+
+    fn push(&mut self, id: String, kind: ContentKind, item: Result<String, String>) {
+        match item {
+            Ok(xml) => {
+                self.items.push((format!("item-{}", id), xml));
+                self.report.items.push(id);
+            }
+            Err(message) => self.report.skipped.push(QtiIssue { id, kind, message }),
+        }
+    }
+
+    /// It packs the items into an IMS content package: `imsmanifest.xml` lists every item,
+    /// stored as `items/<identifier>.xml`, and `export-report.json` lists what was skipped.
+    pub fn to_zip(&self) -> Result<Vec<u8>, String> {
+        let mut manifest = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <manifest xmlns=\"http://www.imsglobal.org/xsd/imscp_v1p1\" identifier=\"MANIFEST-1\">\n\
+             \x20 <metadata>\n\
+             \x20   <schema>IMS Content</schema>\n\
+             \x20   <schemaversion>1.1</schemaversion>\n\
+             \x20 </metadata>\n\
+             \x20 <organizations/>\n\
+             \x20 <resources>\n",
+        );
+        for (identifier, _) in &self.items {
+            manifest.push_str(&format!(
+                "    <resource identifier=\"{id}\" type=\"{ty}\" href=\"items/{id}.xml\">\n\
+                 \x20     <file href=\"items/{id}.xml\"/>\n\
+                 \x20   </resource>\n",
+                id = identifier,
+                ty = QTI_ITEM_TYPE,
+            ));
+        }
+        manifest.push_str("  </resources>\n</manifest>\n");
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let report = serde_json::to_vec_pretty(&self.report).map_err(|e| e.to_string())?;
+        let mut files = vec![
+            ("imsmanifest.xml".to_string(), manifest.into_bytes()),
+            (QTI_REPORT_FILE.to_string(), report),
+        ];
+        for (identifier, xml) in &self.items {
+            files.push((
+                format!("items/{}.xml", identifier),
+                xml.clone().into_bytes(),
+            ));
+        }
+        for (name, data) in files {
+            writer
+                .start_file(name, options)
+                .map_err(|e| e.to_string())?;
+            writer.write_all(&data).map_err(|e| e.to_string())?;
+        }
+        let cursor = writer.finish().map_err(|e| e.to_string())?;
+        Ok(cursor.into_inner())
+    }
+}
+
+/// `QtiItem` is what the import reads from a QTI item.
+#[derive(Debug, Default)]
+struct QtiItem {
+    responses: usize,
+    cardinality: String,
+    correct: Vec<String>,
+    mapped: Vec<String>,
+    interactions: Vec<String>,
+    question: String,
+    choices: Vec<(String, String)>,
+}
+
+/// The mark of a `textEntryInteraction` in the text of the question.
+const BLANK: char = '\u{0}';
+
+fn attribute(element: &quick_xml::events::BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name.as_bytes())
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.to_string())
+}
+
+fn read_item(xml: &str) -> Result<QtiItem, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut item = QtiItem::default();
+    let mut path: Vec<String> = vec![];
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("at byte {}: {}", reader.buffer_position(), e))?;
+        let (element, empty) = match &event {
+            Event::Start(element) => (Some(element), false),
+            Event::Empty(element) => (Some(element), true),
+            _ => (None, false),
+        };
+        if let Some(element) = element {
+            let name = String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+            match name.as_str() {
+                "responseDeclaration" => {
+                    item.responses += 1;
+                    item.cardinality = attribute(element, "cardinality").unwrap_or_default();
+                }
+                "mapEntry" => {
+                    let mapped = attribute(element, "mappedValue")
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .unwrap_or(0.0);
+                    if let Some(key) = attribute(element, "mapKey").filter(|_| mapped > 0.0) {
+                        item.mapped.push(key);
+                    }
+                }
+                "simpleChoice" => {
+                    let identifier = attribute(element, "identifier").unwrap_or_default();
+                    item.choices.push((identifier, String::new()));
+                }
+                "textEntryInteraction" => item.question.push(BLANK),
+                _ => {}
+            }
+            if name.ends_with("Interaction") {
+                item.interactions.push(name.clone());
+            }
+            if !empty {
+                path.push(name);
+            }
+            continue;
+        }
+        match event {
+            Event::End(_) => {
+                path.pop();
+            }
+            Event::Text(_) | Event::CData(_) => {
+                let text = match &event {
+                    Event::Text(text) => text.unescape().map_err(|e| e.to_string())?.to_string(),
+                    Event::CData(data) => String::from_utf8_lossy(data).to_string(),
+                    _ => unreachable!(),
+                };
+                let inside = |name: &str| path.iter().any(|element| element == name);
+                if inside("correctResponse") && path.last().is_some_and(|last| last == "value") {
+                    item.correct.push(text.trim().to_string());
+                } else if inside("simpleChoice") {
+                    if let Some((_, choice)) = item.choices.last_mut() {
+                        choice.push_str(&text);
+                    }
+                } else if inside("itemBody") && !inside("feedbackInline") {
+                    if !item.question.is_empty() && !item.question.ends_with(BLANK) {
+                        item.question.push(' ');
+                    }
+                    item.question.push_str(text.trim());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(item)
+}
+
+/// It reads one QTI 2.1 item as a choice test (`choiceInteraction`) or an action test
+/// (`textEntryInteraction`). An action test has one correct answer, other answers a text entry
+/// accepts are dropped.
+///
+/// Arguments:
+///
+/// * `xml`: The XML of the item.
+/// * `defaults`: The theme and the level of the question, QTI has neither.
+///
+/// Returns:
+///
+/// The kind of the test and the test, or why the item can not be represented.
+pub fn parse_qti_item(
+    xml: &str,
+    defaults: &ImportDefaults,
+) -> Result<(ContentKind, TestModel), String> {
+    let item = read_item(xml)?;
+    let interaction = match item.interactions.as_slice() {
+        [interaction] => interaction.as_str(),
+        [] => return Err("the item has no interaction".to_string()),
+        _ => return Err("items with more than one interaction are not supported".to_string()),
+    };
+    if item.responses != 1 || item.cardinality != "single" {
+        return Err("only items with a single response are supported".to_string());
+    }
+    let question = match item.question.trim_end().strip_suffix(BLANK) {
+        Some(question) => question.trim().to_string(),
+        None => item.question.replace(BLANK, " _____ ").trim().to_string(),
+    };
+    match interaction {
+        "choiceInteraction" => {
+            let correct = item
+                .correct
+                .first()
+                .ok_or("the item has no correct response")?;
+            let answer = item
+                .choices
+                .iter()
+                .find(|(identifier, _)| identifier == correct)
+                .map(|(_, text)| text.trim().to_string())
+                .ok_or_else(|| format!("the correct response `{}` is not a choice", correct))?;
+            let answers = item
+                .choices
+                .iter()
+                .map(|(_, text)| text.trim().to_string())
+                .collect();
+            Ok((
+                ContentKind::Choice,
+                new_test(question, answers, answer, defaults),
+            ))
+        }
+        "textEntryInteraction" => {
+            // items without a correct response are scored by their mapping alone
+            let answer = item
+                .correct
+                .first()
+                .or(item.mapped.first())
+                .cloned()
+                .ok_or("the item has no correct response")?;
+            Ok((
+                ContentKind::Action,
+                new_test(question, vec![], answer, defaults),
+            ))
+        }
+        other => Err(format!("`{}` is not supported", other)),
+    }
+}
+
+/// It lists the items of a content package: the `imsqti_item_xmlv2p1` resources of
+/// `imsmanifest.xml`, or every XML file if there is no manifest.
+fn package_items(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Vec<String>, String> {
+    let manifest = match archive.by_name("imsmanifest.xml") {
+        Ok(mut file) => {
+            let mut manifest = String::new();
+            file.read_to_string(&mut manifest)
+                .map_err(|e| format!("imsmanifest.xml: {}", e))?;
+            Some(manifest)
+        }
+        Err(ZipError::FileNotFound) => None,
+        Err(e) => return Err(e.to_string()),
+    };
+    let Some(manifest) = manifest else {
+        let mut names: Vec<String> = archive
+            .file_names()
+            .filter(|name| name.ends_with(".xml"))
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        return Ok(names);
+    };
+    let mut reader = Reader::from_str(&manifest);
+    let mut items = vec![];
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| format!("imsmanifest.xml: {}", e))?
+        {
+            Event::Start(element) | Event::Empty(element)
+                if element.local_name().as_ref() == b"resource" =>
+            {
+                let is_item =
+                    attribute(&element, "type").is_some_and(|ty| ty.starts_with(QTI_ITEM_TYPE));
+                if let Some(href) = attribute(&element, "href").filter(|_| is_item) {
+                    items.push(href);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// It reads a QTI 2.1 question bank, a content package (zip) or a single item.
+///
+/// Arguments:
+///
+/// * `data`: The package or the XML of the item.
+/// * `defaults`: The theme and the level of the questions.
+///
+/// Returns:
+///
+/// The questions that could be read, and the items that could not be represented. `line` is the
+/// position of the item in the package.
+pub fn parse_qti(
+    data: &[u8],
+    defaults: &ImportDefaults,
+) -> (Vec<ImportedQuestion>, Vec<ImportIssue>) {
+    let (mut questions, mut issues) = (vec![], vec![]);
+    let issue = |line: usize, file: Option<String>, message: String| ImportIssue {
+        line,
+        file,
+        question: String::new(),
+        message,
+    };
+    let mut items: Vec<(Option<String>, Result<String, String>)> = vec![];
+    if data.starts_with(b"PK\x03\x04") {
+        let mut archive = match ZipArchive::new(Cursor::new(data)) {
+            Ok(archive) => archive,
+            Err(e) => return (questions, vec![issue(0, None, e.to_string())]),
+        };
+        let names = match package_items(&mut archive) {
+            Ok(names) => names,
+            Err(e) => return (questions, vec![issue(0, None, e)]),
+        };
+        for name in names {
+            let xml = archive
+                .by_name(&name)
+                .map_err(|e| e.to_string())
+                .and_then(|mut file| {
+                    let mut xml = String::new();
+                    file.read_to_string(&mut xml).map_err(|e| e.to_string())?;
+                    Ok(xml)
+                });
+            items.push((Some(name), xml));
+        }
+    } else {
+        let xml = String::from_utf8(data.to_vec()).map_err(|e| e.to_string());
+        items.push((None, xml));
+    }
+    for (i, (file, xml)) in items.into_iter().enumerate() {
+        match xml.and_then(|xml| parse_qti_item(&xml, defaults)) {
+            Ok((kind, test)) => questions.push(ImportedQuestion {
+                line: i + 1,
+                file,
+                kind,
+                test,
+                skills: vec![],
+            }),
+            Err(message) => issues.push(issue(i + 1, file, message)),
+        }
+    }
+    (questions, issues)
+}
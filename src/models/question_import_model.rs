@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    info_model::ContentLevel,
+    publication_model::ContentKind,
+    tests_model::{TestModel, TestModelWithActions},
+};
+
+/// The separator of the answers, hints and skills in a CSV cell, e.g. `1/2|1/3|2/3`.
+pub const LIST_SEPARATOR: char = '|';
+
+/// `QuestionFormat` is the format of a question bank.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionFormat {
+    Csv,
+    Gift,
+    Qti,
+}
+
+impl QuestionFormat {
+    /// It parses the name of a format, `csv`, `gift` or `qti`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(QuestionFormat::Csv),
+            "gift" => Some(QuestionFormat::Gift),
+            "qti" => Some(QuestionFormat::Qti),
+            _ => None,
+        }
+    }
+}
+
+/// `ImportDefaults` fills in what a row of a question bank leaves out.
+///
+/// Properties:
+///
+/// * `theme`: The theme of questions without a theme or a GIFT category.
+/// * `level`: The difficulty of questions without a level, GIFT has no levels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportDefaults {
+    pub theme: String,
+    pub level: i32,
+}
+
+/// `ImportedQuestion` is one question read from a question bank.
+///
+/// Properties:
+///
+/// * `line`: The line the question starts at, or its position in a QTI package.
+/// * `file`: The file of the question in a QTI package.
+/// * `kind`: `Choice` for a choice test or `Action` for an action test.
+/// * `test`: The question. An action test has the same fields, see `action_test`.
+/// * `skills`: The skills as written in the bank, ids or slugs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedQuestion {
+    pub line: usize,
+    pub file: Option<String>,
+    pub kind: ContentKind,
+    pub test: TestModel,
+    pub skills: Vec<String>,
+}
+
+impl ImportedQuestion {
+    /// It checks the question: it has a text and a correct answer, the correct answer of a choice
+    /// test is one of its answers, and the level and the hint penalty are in range.
+    ///
+    /// Returns:
+    ///
+    /// The problems, empty if the question is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let test = &self.test;
+        let mut errors = vec![];
+        if test.question.trim().is_empty() {
+            errors.push("the question is empty".to_string());
+        }
+        if test.answer.trim().is_empty() {
+            errors.push("the correct answer is empty".to_string());
+        } else if self.kind == ContentKind::Choice && !test.answers.contains(&test.answer) {
+            errors.push(format!(
+                "the correct answer `{}` is not one of the answers",
+                test.answer
+            ));
+        }
+        if self.kind == ContentKind::Choice && test.answers.len() < 2 {
+            errors.push("a choice test needs at least two answers".to_string());
+        }
+        if !(1..=5).contains(&test.level) {
+            errors.push(format!("level {} is not between 1 and 5", test.level));
+        }
+        if test.hint_penalty.is_some_and(|p| !(0..=100).contains(&p)) {
+            errors.push("the hint penalty is not between 0 and 100".to_string());
+        }
+        errors
+    }
+
+    /// It converts the question to an action test.
+    pub fn action_test(&self) -> TestModelWithActions {
+        let test = self.test.clone();
+        TestModelWithActions {
+            id: test.id,
+            theme: test.theme,
+            theme_id: test.theme_id,
+            skills: test.skills,
+            question: test.question,
+            answers: test.answers,
+            answer: test.answer,
+            level: test.level,
+            hints: test.hints,
+            explanation: test.explanation,
+            hint_penalty: test.hint_penalty,
+            translations: test.translations,
+        }
+    }
+}
+
+/// `ImportIssue` is a question of a bank that is not imported.
+///
+/// Properties:
+///
+/// * `line`: The line the question starts at, or its position in a QTI package.
+/// * `file`: The file of the question in a QTI package.
+/// * `question`: The text of the question, empty if it can not be read.
+/// * `message`: Why it is not imported.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ImportIssue {
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub question: String,
+    pub message: String,
+}
+
+/// `QuestionImportReport` is the result of importing a question bank, or what the import would do
+/// for a dry run.
+///
+/// Properties:
+///
+/// * `format`: The format of the bank.
+/// * `dry_run`: Whether nothing was written.
+/// * `parsed`: How many questions were read.
+/// * `choice_tests`: How many choice tests are (or would be) imported.
+/// * `action_tests`: How many action tests are (or would be) imported.
+/// * `imported`: The ids of the imported tests, empty for a dry run.
+/// * `duplicates`: Questions that are already in the bank or in the database.
+/// * `errors`: Questions that are invalid.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct QuestionImportReport {
+    pub format: QuestionFormat,
+    pub dry_run: bool,
+    pub parsed: usize,
+    pub choice_tests: usize,
+    pub action_tests: usize,
+    pub imported: Vec<String>,
+    pub duplicates: Vec<ImportIssue>,
+    pub errors: Vec<ImportIssue>,
+}
+
+fn split_list(cell: &str) -> Vec<String> {
+    cell.split(LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn text_blocks(items: Vec<String>) -> Vec<ContentLevel> {
+    items
+        .into_iter()
+        .map(|data| ContentLevel::Text { data })
+        .collect()
+}
+
+/// It creates an imported test with the default theme and level.
+pub fn new_test(
+    question: String,
+    answers: Vec<String>,
+    answer: String,
+    defaults: &ImportDefaults,
+) -> TestModel {
+    TestModel {
+        id: None,
+        theme: defaults.theme.clone(),
+        theme_id: None,
+        skills: vec![],
+        question,
+        answers,
+        answer,
+        level: defaults.level,
+        hints: vec![],
+        explanation: vec![],
+        hint_penalty: None,
+        translations: HashMap::new(),
+    }
+}
+
+/// One row of a CSV question bank, see `parse_csv`.
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    theme: String,
+    #[serde(default)]
+    level: Option<i32>,
+    question: String,
+    #[serde(default)]
+    answers: String,
+    answer: String,
+    #[serde(default)]
+    hints: String,
+    #[serde(default)]
+    explanation: String,
+    #[serde(default)]
+    hint_penalty: Option<i32>,
+    #[serde(default)]
+    skills: String,
+}
+
+/// It reads a CSV question bank. The first row names the columns, in any order:
+///
+/// * `type`: `choice` (default) or `action`.
+/// * `theme`: The id, the slug or the name of the theme, the default theme if empty.
+/// * `level`: The difficulty from 1 to 5, the default level if empty.
+/// * `question`: The question, required.
+/// * `answers`: The answers separated by `|`, for an action test the steps of the solution.
+/// * `answer`: The correct answer, one of the answers of a choice test, required.
+/// * `hints`: Progressive hints separated by `|`.
+/// * `explanation`: The worked explanation.
+/// * `hint_penalty`: Percent of the score taken off for every revealed hint.
+/// * `skills`: The ids or slugs of the skills separated by `|`.
+///
+/// Arguments:
+///
+/// * `data`: The CSV, UTF-8 with `,` as delimiter and `"` as quote.
+/// * `defaults`: What empty cells default to.
+///
+/// Returns:
+///
+/// The questions that could be read, and the rows that could not.
+pub fn parse_csv(
+    data: &[u8],
+    defaults: &ImportDefaults,
+) -> (Vec<ImportedQuestion>, Vec<ImportIssue>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let (mut questions, mut issues) = (vec![], vec![]);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            issues.push(ImportIssue {
+                line: 1,
+                file: None,
+                question: String::new(),
+                message: e.to_string(),
+            });
+            return (questions, issues);
+        }
+    };
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line() as usize);
+                issues.push(ImportIssue {
+                    line,
+                    file: None,
+                    question: String::new(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        let row: CsvRow = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                let question = headers
+                    .iter()
+                    .position(|header| header == "question")
+                    .and_then(|i| record.get(i))
+                    .unwrap_or_default();
+                issues.push(ImportIssue {
+                    line,
+                    file: None,
+                    question: question.to_string(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let kind = match row.kind.to_lowercase().as_str() {
+            "" | "choice" => ContentKind::Choice,
+            "action" => ContentKind::Action,
+            other => {
+                issues.push(ImportIssue {
+                    line,
+                    file: None,
+                    question: row.question,
+                    message: format!("unknown type `{}`, use `choice` or `action`", other),
+                });
+                continue;
+            }
+        };
+        let answers = split_list(&row.answers);
+        let mut test = new_test(row.question, answers, row.answer, defaults);
+        if !row.theme.is_empty() {
+            test.theme = row.theme;
+        }
+        test.level = row.level.unwrap_or(defaults.level);
+        test.hints = text_blocks(split_list(&row.hints));
+        if !row.explanation.is_empty() {
+            test.explanation = text_blocks(vec![row.explanation]);
+        }
+        test.hint_penalty = row.hint_penalty;
+        questions.push(ImportedQuestion {
+            line,
+            file: None,
+            kind,
+            test,
+            skills: split_list(&row.skills),
+        });
+    }
+    (questions, issues)
+}
+
+/// It splits GIFT text at the unescaped separators, escapes like `\=` are kept for `unescape`.
+///
+/// Returns:
+///
+/// The pieces with the separator in front of each, None for the piece before the first one.
+fn split_unescaped(text: &str, separators: &[char]) -> Vec<(Option<char>, String)> {
+    let mut pieces = vec![(None, String::new())];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let piece = &mut pieces.last_mut().unwrap().1;
+            piece.push(c);
+            if let Some(next) = chars.next() {
+                piece.push(next);
+            }
+        } else if separators.contains(&c) {
+            pieces.push((Some(c), String::new()));
+        } else {
+            pieces.last_mut().unwrap().1.push(c);
+        }
+    }
+    pieces
+}
+
+/// It finds the first unescaped occurrence of a pattern in GIFT text.
+fn find_unescaped(text: &str, pattern: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if text[i..].starts_with(pattern) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(next) => result.push(next),
+                None => result.push(c),
+            },
+            _ => result.push(c),
+        }
+    }
+    result.trim().to_string()
+}
+
+/// It reads the answer block of a GIFT question, the text between `{` and `}`.
+///
+/// Returns:
+///
+/// The kind of the test, the answers, the correct answer and the general feedback, or why the
+/// block is not supported.
+fn parse_gift_answers(
+    block: &str,
+) -> Result<(ContentKind, Vec<String>, String, Option<String>), String> {
+    let (block, feedback) = match find_unescaped(block, "####") {
+        Some(i) => (&block[..i], Some(unescape(&block[i + 4..]))),
+        None => (block, None),
+    };
+    let trimmed = block.trim();
+    if trimmed.is_empty() {
+        return Err("essay questions are not supported".to_string());
+    }
+    let without_feedback = |text: &str| match find_unescaped(text, "#") {
+        Some(i) => text[..i].to_string(),
+        None => text.to_string(),
+    };
+    let truth = without_feedback(trimmed).trim().to_uppercase();
+    if ["T", "TRUE", "F", "FALSE"].contains(&truth.as_str()) {
+        let answer = if truth.starts_with('T') {
+            "true"
+        } else {
+            "false"
+        };
+        let answers = vec!["true".to_string(), "false".to_string()];
+        return Ok((ContentKind::Choice, answers, answer.to_string(), feedback));
+    }
+    if let Some(numeric) = trimmed.strip_prefix('#') {
+        let value = unescape(&without_feedback(numeric));
+        if value.contains(':') || value.contains("..") || value.contains('=') {
+            return Err("numeric ranges and tolerances are not supported".to_string());
+        }
+        if value.parse::<f64>().is_err() {
+            return Err(format!("`{}` is not a number", value));
+        }
+        return Ok((ContentKind::Action, vec![], value, feedback));
+    }
+    if find_unescaped(trimmed, "->").is_some() {
+        return Err("matching questions are not supported".to_string());
+    }
+    let (mut right, mut wrong) = (vec![], vec![]);
+    for (separator, piece) in split_unescaped(trimmed, &['=', '~']) {
+        let piece = without_feedback(&piece);
+        let (piece, weight) = match piece
+            .trim()
+            .strip_prefix('%')
+            .and_then(|p| p.split_once('%'))
+        {
+            Some((weight, rest)) => (rest.to_string(), weight.parse::<f64>().ok()),
+            None => (piece, None),
+        };
+        let answer = unescape(&piece);
+        match separator {
+            None if answer.is_empty() => {}
+            None => {
+                return Err(format!(
+                    "`{}` is not an answer, start it with `=` or `~`",
+                    answer
+                ))
+            }
+            Some('=') => right.push(answer),
+            _ if weight == Some(100.0) => right.push(answer),
+            _ => wrong.push(answer),
+        }
+    }
+    match (right.len(), wrong.is_empty()) {
+        (0, _) => Err("there is no correct answer".to_string()),
+        (1, true) => Ok((ContentKind::Action, vec![], right[0].clone(), feedback)),
+        (_, true) => {
+            Err("short answers with more than one accepted answer are not supported".to_string())
+        }
+        (1, false) => {
+            let answer = right[0].clone();
+            let mut answers = right;
+            answers.extend(wrong);
+            Ok((ContentKind::Choice, answers, answer, feedback))
+        }
+        _ => Err("a multiple choice question needs exactly one correct answer".to_string()),
+    }
+}
+
+/// It reads one GIFT question, without the comments.
+fn parse_gift_question(
+    text: &str,
+    line: usize,
+    defaults: &ImportDefaults,
+) -> Result<ImportedQuestion, ImportIssue> {
+    let issue = |question: &str, message: String| ImportIssue {
+        line,
+        file: None,
+        question: question.to_string(),
+        message,
+    };
+    let mut text = text.trim();
+    if let Some(rest) = text.strip_prefix("::") {
+        text = match find_unescaped(rest, "::") {
+            Some(i) => rest[i + 2..].trim_start(),
+            None => return Err(issue(text, "the title is not closed with `::`".to_string())),
+        };
+    }
+    if text.starts_with('[') {
+        if let Some(i) = text.find(']') {
+            text = text[i + 1..].trim_start();
+        }
+    }
+    let open = find_unescaped(text, "{")
+        .ok_or_else(|| issue(text, "there is no answer block `{...}`".to_string()))?;
+    let close = find_unescaped(&text[open..], "}")
+        .map(|i| open + i)
+        .ok_or_else(|| issue(text, "the answer block is not closed with `}`".to_string()))?;
+    let (before, after) = (unescape(&text[..open]), unescape(&text[close + 1..]));
+    let question = if after.is_empty() {
+        before
+    } else {
+        format!("{} _____ {}", before, after)
+    };
+    let (kind, answers, answer, feedback) =
+        parse_gift_answers(&text[open + 1..close]).map_err(|message| issue(&question, message))?;
+    let mut test = new_test(question, answers, answer, defaults);
+    if let Some(feedback) = feedback.filter(|feedback| !feedback.is_empty()) {
+        test.explanation = text_blocks(vec![feedback]);
+    }
+    Ok(ImportedQuestion {
+        line,
+        file: None,
+        kind,
+        test,
+        skills: vec![],
+    })
+}
+
+/// It reads a question bank in the GIFT format of Moodle. Questions are separated by blank lines
+/// and lines starting with `//` are comments. `$CATEGORY: path` sets the theme of the questions
+/// after it to the last part of the path. Supported questions:
+///
+/// * Multiple choice, `{=right ~wrong ~wrong}`, becomes a choice test.
+/// * True/false, `{T}` or `{FALSE}`, becomes a choice test with the answers `true` and `false`.
+/// * Short answer, `{=answer}`, becomes an action test. Only one accepted answer is supported.
+/// * Numerical, `{#4}`, becomes an action test. Ranges and tolerances are not supported.
+///
+/// Titles `::title::` and formats `[markdown]` are dropped, feedback `#...` is dropped and
+/// general feedback `####...` becomes the explanation. A question with text after the answer
+/// block (missing word) gets `_____` where the block was.
+///
+/// Arguments:
+///
+/// * `text`: The GIFT text.
+/// * `defaults`: The theme and the level of the questions.
+///
+/// Returns:
+///
+/// The questions that could be read, and the ones that could not.
+pub fn parse_gift(
+    text: &str,
+    defaults: &ImportDefaults,
+) -> (Vec<ImportedQuestion>, Vec<ImportIssue>) {
+    let (mut questions, mut issues) = (vec![], vec![]);
+    let mut defaults = defaults.clone();
+    let mut block: Option<(usize, String)> = None;
+    let lines = text.lines().map(Some).chain(std::iter::once(None));
+    for (i, line) in lines.enumerate() {
+        let line = line.map(str::trim);
+        if line.is_some_and(|line| line.starts_with("//")) {
+            continue;
+        }
+        if let Some(category) = line.and_then(|line| line.strip_prefix("$CATEGORY:")) {
+            let theme = category
+                .split('/')
+                .map(str::trim)
+                .rfind(|part| !part.is_empty() && !part.starts_with('$'));
+            if let Some(theme) = theme {
+                defaults.theme = theme.to_string();
+            }
+            continue;
+        }
+        match line {
+            Some(line) if !line.is_empty() => {
+                let (_, text) = block.get_or_insert_with(|| (i + 1, String::new()));
+                text.push_str(line);
+                text.push('\n');
+            }
+            _ => {
+                if let Some((start, text)) = block.take() {
+                    match parse_gift_question(&text, start, &defaults) {
+                        Ok(question) => questions.push(question),
+                        Err(issue) => issues.push(issue),
+                    }
+                }
+            }
+        }
+    }
+    (questions, issues)
+}
@@ -0,0 +1,267 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::attempt_model::QuestionKind;
+
+/// How many questions a quiz of a theme or of a template has when the user does not ask for a
+/// number.
+pub const DEFAULT_QUIZ_QUESTIONS: usize = 10;
+
+/// The most questions a quiz can have.
+pub const MAX_QUIZ_QUESTIONS: usize = 50;
+
+/// The longest time limit of a quiz in seconds.
+pub const MAX_QUIZ_TIME_LIMIT: i64 = 4 * 60 * 60;
+
+/// `QuizSource` is where the questions of a quiz come from.
+///
+/// Variants:
+///
+/// * `Level`: The tests of a test level of a course.
+/// * `Theme`: Random questions of a theme at a level, the level from the placement test of the
+///   user when it is not given, or 1.
+/// * `Template`: Questions generated from a template.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum QuizSource {
+    Level {
+        course_id: String,
+        node_id: String,
+    },
+    Theme {
+        theme: String,
+        #[serde(default)]
+        level: Option<i32>,
+    },
+    Template {
+        template_id: String,
+    },
+}
+
+/// `QuizQuestion` is a question of a quiz, frozen when the quiz is created.
+///
+/// Properties:
+///
+/// * `question_id`: The id of the test or of the template.
+/// * `kind`: The kind of the question.
+/// * `seed`: The seed of a question generated from a template.
+/// * `theme`: The theme of the question.
+/// * `level`: The level of the question.
+/// * `answer`: The answer of the user, None until the question is answered.
+/// * `correct`: Whether the answer was correct.
+/// * `answered_at`: Unix timestamp of the answer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuizQuestion {
+    pub question_id: String,
+    pub kind: QuestionKind,
+    pub seed: Option<i64>,
+    pub theme: String,
+    pub level: i32,
+    pub answer: Option<String>,
+    pub correct: Option<bool>,
+    pub answered_at: Option<i64>,
+}
+
+impl QuizQuestion {
+    /// It creates a question the user has not answered yet.
+    pub fn new(
+        question_id: &str,
+        kind: QuestionKind,
+        seed: Option<i64>,
+        theme: &str,
+        level: i32,
+    ) -> Self {
+        Self {
+            question_id: question_id.to_string(),
+            kind,
+            seed,
+            theme: theme.to_string(),
+            level,
+            answer: None,
+            correct: None,
+            answered_at: None,
+        }
+    }
+}
+
+/// `LevelScore` is how a user did on the questions of one level of a quiz.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LevelScore {
+    pub level: i32,
+    pub correct: usize,
+    pub total: usize,
+}
+
+/// `QuizResult` is the score breakdown of a finished quiz.
+///
+/// Properties:
+///
+/// * `total`: The number of questions.
+/// * `answered`: The number of answered questions.
+/// * `correct`: The number of correct answers.
+/// * `score`: The share of correct answers from 0 to 1, unanswered questions count as wrong.
+/// * `duration`: The number of seconds from the start to the end of the quiz.
+/// * `timed_out`: Whether the time limit was over when the quiz was finished.
+/// * `levels`: The correct answers by level of the questions, the easiest first.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuizResult {
+    pub total: usize,
+    pub answered: usize,
+    pub correct: usize,
+    pub score: f64,
+    pub duration: i64,
+    pub timed_out: bool,
+    pub levels: Vec<LevelScore>,
+}
+
+/// `QuizModel` is a quiz session of a user, stored in the `quizzes` collection. The questions are
+/// frozen when the quiz starts, every question can be answered once, and no answer is taken after
+/// the time limit.
+///
+/// Properties:
+///
+/// * `id`: The id of the quiz.
+/// * `user_id`: The id of the user.
+/// * `source`: Where the questions come from.
+/// * `questions`: The questions, in the order they are shown.
+/// * `time_limit`: The time limit in seconds, None for an untimed quiz.
+/// * `started_at`: Unix timestamp of the start.
+/// * `expires_at`: Unix timestamp after which answers are refused.
+/// * `finished_at`: Unix timestamp of the end.
+/// * `result`: The score breakdown, set when the quiz is finished.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuizModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub source: QuizSource,
+    pub questions: Vec<QuizQuestion>,
+    pub time_limit: Option<i64>,
+    pub started_at: i64,
+    pub expires_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub result: Option<QuizResult>,
+}
+
+impl QuizModel {
+    /// It starts a quiz.
+    pub fn new(
+        user_id: &str,
+        source: QuizSource,
+        questions: Vec<QuizQuestion>,
+        time_limit: Option<i64>,
+        now: i64,
+    ) -> Self {
+        Self {
+            id: None,
+            user_id: user_id.to_string(),
+            source,
+            questions,
+            time_limit,
+            started_at: now,
+            expires_at: time_limit.map(|limit| now + limit),
+            finished_at: None,
+            result: None,
+        }
+    }
+
+    /// It checks whether the time limit is over.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now > expires_at)
+    }
+
+    /// It records the graded answer to a question.
+    ///
+    /// Arguments:
+    ///
+    /// * `index`: The index of the question.
+    /// * `answer`: The answer of the user.
+    /// * `correct`: Whether the answer is correct.
+    /// * `now`: Unix timestamp of the answer.
+    ///
+    /// Returns:
+    ///
+    /// The reason the answer is refused: the quiz is finished, the time limit is over or the
+    /// question is already answered.
+    pub fn answer(
+        &mut self,
+        index: usize,
+        answer: &str,
+        correct: bool,
+        now: i64,
+    ) -> Result<(), String> {
+        if self.finished_at.is_some() {
+            return Err("the quiz is finished".to_string());
+        }
+        if self.is_expired(now) {
+            return Err("the time limit is over".to_string());
+        }
+        let question = self
+            .questions
+            .get_mut(index)
+            .ok_or_else(|| format!("unknown question {}", index + 1))?;
+        if question.answer.is_some() {
+            return Err(format!("question {} is already answered", index + 1));
+        }
+        question.answer = Some(answer.to_string());
+        question.correct = Some(correct);
+        question.answered_at = Some(now);
+        Ok(())
+    }
+
+    /// It ends the quiz and computes its score breakdown.
+    ///
+    /// Returns:
+    ///
+    /// The score breakdown, or the reason if the quiz is already finished.
+    pub fn finish(&mut self, now: i64) -> Result<QuizResult, String> {
+        if self.finished_at.is_some() {
+            return Err("the quiz is finished".to_string());
+        }
+        let mut levels: Vec<LevelScore> = vec![];
+        for question in &self.questions {
+            let correct = question.correct == Some(true);
+            match levels
+                .iter_mut()
+                .find(|score| score.level == question.level)
+            {
+                Some(score) => {
+                    score.total += 1;
+                    score.correct += correct as usize;
+                }
+                None => levels.push(LevelScore {
+                    level: question.level,
+                    correct: correct as usize,
+                    total: 1,
+                }),
+            }
+        }
+        levels.sort_by_key(|score| score.level);
+        let total = self.questions.len();
+        let correct = levels.iter().map(|score| score.correct).sum::<usize>();
+        let end = match self.expires_at {
+            Some(expires_at) => now.min(expires_at),
+            None => now,
+        };
+        let result = QuizResult {
+            total,
+            answered: self
+                .questions
+                .iter()
+                .filter(|question| question.answer.is_some())
+                .count(),
+            correct,
+            score: if total == 0 {
+                0.0
+            } else {
+                correct as f64 / total as f64
+            },
+            duration: end - self.started_at,
+            timed_out: self.is_expired(now),
+            levels,
+        };
+        self.finished_at = Some(now);
+        self.result = Some(result.clone());
+        Ok(result)
+    }
+}
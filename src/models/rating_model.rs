@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+
+/// The rating of a user who has not answered a question of a theme yet, and of a question of
+/// level 3.
+pub const DEFAULT_RATING: f64 = 1500.0;
+
+/// How far apart the initial ratings of two neighbouring levels are.
+pub const LEVEL_RATING_STEP: f64 = 200.0;
+
+/// How far the rating of a user moves after an attempt that went completely unexpected.
+pub const USER_K_FACTOR: f64 = 32.0;
+
+/// How far the rating of a new question moves, it shrinks as the question is answered more.
+pub const QUESTION_K_FACTOR: f64 = 32.0;
+
+/// The smallest step of the rating of a question.
+pub const MIN_QUESTION_K_FACTOR: f64 = 8.0;
+
+/// How many graded attempts a question needs before its rating replaces its hand-set level.
+pub const CALIBRATION_ATTEMPTS: i32 = 20;
+
+/// How far the rating of a question may be from the rating of the user to be picked first by the
+/// adaptive selection.
+pub const ADAPTIVE_WINDOW: f64 = 100.0;
+
+/// It returns the initial rating of a question of a hand-set level from 1 to 5.
+pub fn level_rating(level: i32) -> f64 {
+    DEFAULT_RATING + (level.clamp(1, 5) - 3) as f64 * LEVEL_RATING_STEP
+}
+
+/// It returns the level from 1 to 5 whose initial rating is the nearest to a rating.
+pub fn rating_level(rating: f64) -> i32 {
+    (3.0 + (rating - DEFAULT_RATING) / LEVEL_RATING_STEP)
+        .round()
+        .clamp(1.0, 5.0) as i32
+}
+
+/// It returns the score from 0 to 1 a user of `rating` is expected to get on a question of
+/// `difficulty`.
+pub fn expected_score(rating: f64, difficulty: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((difficulty - rating) / 400.0))
+}
+
+/// `UserRatingModel` is how strong a user is at a theme, stored in the `user_ratings`
+/// collection.
+///
+/// Properties:
+///
+/// * `id`: The id of the rating.
+/// * `user_id`: The id of the user.
+/// * `theme`: The slug of the theme, or the free-text theme of the questions.
+/// * `theme_id`: The id of the theme if it is in the taxonomy.
+/// * `rating`: The Elo rating of the user.
+/// * `attempts`: How many graded attempts of the theme the rating is based on.
+/// * `updated_at`: Unix timestamp of the last graded attempt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserRatingModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    pub rating: f64,
+    pub attempts: i32,
+    pub updated_at: i64,
+}
+
+impl UserRatingModel {
+    /// It creates the rating of a user who has not answered a question of the theme yet.
+    pub fn new(user_id: &str, theme: &str, theme_id: &Option<String>) -> Self {
+        Self {
+            id: None,
+            user_id: user_id.to_string(),
+            theme: theme.to_string(),
+            theme_id: theme_id.clone(),
+            rating: DEFAULT_RATING,
+            attempts: 0,
+            updated_at: 0,
+        }
+    }
+}
+
+/// `QuestionRatingModel` is how hard a test or a template turned out to be, stored in the
+/// `question_ratings` collection.
+///
+/// Properties:
+///
+/// * `id`: The id of the rating.
+/// * `question_id`: The id of the test or of the template.
+/// * `rating`: The Elo rating of the question, it starts at the rating of the hand-set level.
+/// * `attempts`: How many graded attempts the rating is based on.
+/// * `updated_at`: Unix timestamp of the last graded attempt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuestionRatingModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub question_id: String,
+    pub rating: f64,
+    pub attempts: i32,
+    pub updated_at: i64,
+}
+
+impl QuestionRatingModel {
+    /// It creates the rating of a question nobody has answered yet from its hand-set level.
+    pub fn new(question_id: &str, level: i32) -> Self {
+        Self {
+            id: None,
+            question_id: question_id.to_string(),
+            rating: level_rating(level),
+            attempts: 0,
+            updated_at: 0,
+        }
+    }
+
+    /// It checks whether enough attempts were graded for the rating to replace the hand-set
+    /// level.
+    pub fn is_calibrated(&self) -> bool {
+        self.attempts >= CALIBRATION_ATTEMPTS
+    }
+
+    /// It returns how far the rating moves after the next attempt, ratings of questions that
+    /// were answered often move less.
+    pub fn k_factor(&self) -> f64 {
+        (QUESTION_K_FACTOR / (1.0 + self.attempts as f64 / CALIBRATION_ATTEMPTS as f64))
+            .max(MIN_QUESTION_K_FACTOR)
+    }
+}
+
+/// It updates the ratings of a user and of a question after a graded attempt, like a game the
+/// user wins with the score of the attempt.
+///
+/// Arguments:
+///
+/// * `user`: The rating of the user for the theme of the question.
+/// * `question`: The rating of the question.
+/// * `score`: The score of the attempt from 0 to 1.
+/// * `now`: Unix timestamp of the grading.
+pub fn record_rating(
+    user: &mut UserRatingModel,
+    question: &mut QuestionRatingModel,
+    score: f64,
+    now: i64,
+) {
+    let surprise = score.clamp(0.0, 1.0) - expected_score(user.rating, question.rating);
+    user.rating += USER_K_FACTOR * surprise;
+    question.rating -= question.k_factor() * surprise;
+    user.attempts += 1;
+    question.attempts += 1;
+    user.updated_at = now;
+    question.updated_at = now;
+}
+
+/// It returns the rating of a user for a group of themes, the mean of the ratings of the themes
+/// weighted by their attempts, `DEFAULT_RATING` if the user has answered none.
+pub fn combined_rating<'a>(ratings: impl IntoIterator<Item = &'a UserRatingModel>) -> f64 {
+    let (sum, attempts) = ratings
+        .into_iter()
+        .fold((0.0, 0), |(sum, attempts), rating| {
+            (
+                sum + rating.rating * rating.attempts as f64,
+                attempts + rating.attempts,
+            )
+        });
+    if attempts == 0 {
+        DEFAULT_RATING
+    } else {
+        sum / attempts as f64
+    }
+}
+
+/// It returns the level of a question: the level of its rating once it is calibrated, the
+/// hand-set level before.
+///
+/// Arguments:
+///
+/// * `ratings`: The ratings of the questions by question id.
+/// * `question_id`: The id of the test or of the template.
+/// * `level`: The hand-set level of the question.
+pub fn calibrated_level(
+    ratings: &HashMap<String, QuestionRatingModel>,
+    question_id: &str,
+    level: i32,
+) -> i32 {
+    match ratings.get(question_id) {
+        Some(rating) if rating.is_calibrated() => rating_level(rating.rating),
+        _ => level,
+    }
+}
+
+/// It returns the rating of a question, the rating of its hand-set level if nobody has answered
+/// it yet.
+pub fn question_rating(
+    ratings: &HashMap<String, QuestionRatingModel>,
+    question_id: &str,
+    level: i32,
+) -> f64 {
+    ratings
+        .get(question_id)
+        .map(|rating| rating.rating)
+        .unwrap_or_else(|| level_rating(level))
+}
+
+/// It picks the questions whose ratings are the nearest to the rating of the user. The distances
+/// are counted in steps of `ADAPTIVE_WINDOW`, questions the same number of steps away are equally
+/// good and are picked at random.
+///
+/// Arguments:
+///
+/// * `candidates`: The questions with their ratings.
+/// * `rating`: The rating of the user.
+/// * `count`: How many questions to pick.
+///
+/// Returns:
+///
+/// The picked questions, the nearest first.
+pub fn pick_adaptive<T>(mut candidates: Vec<(f64, T)>, rating: f64, count: usize) -> Vec<T> {
+    candidates.shuffle(&mut thread_rng());
+    candidates.sort_by_key(|(question, _)| ((question - rating).abs() / ADAPTIVE_WINDOW) as i64);
+    candidates
+        .into_iter()
+        .take(count)
+        .map(|(_, question)| question)
+        .collect()
+}
@@ -0,0 +1,115 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::attempt_model::QuestionKind;
+
+/// The ease of a question the user has not reviewed yet.
+pub const DEFAULT_EASE: f64 = 2.5;
+
+/// The smallest ease, questions that are always missed still come back less often than daily.
+pub const MIN_EASE: f64 = 1.3;
+
+/// The lowest quality of a review that counts as remembered.
+pub const PASSING_QUALITY: i32 = 3;
+
+/// How many questions the review set has when the user does not ask for a number.
+pub const DEFAULT_REVIEW_LIMIT: usize = 20;
+
+/// The length of a day in seconds.
+pub const DAY: i64 = 24 * 60 * 60;
+
+/// It turns a graded attempt into the quality of an SM-2 review from 0 to 5: a wrong answer is 1,
+/// a correct answer is 5, 4 if hints took up to 30% off the score and 3 if they took more.
+///
+/// Arguments:
+///
+/// * `correct`: Whether the answer was correct.
+/// * `score`: The score of the attempt from 0 to 1.
+pub fn review_quality(correct: bool, score: f64) -> i32 {
+    if !correct {
+        1
+    } else if score >= 1.0 {
+        5
+    } else if score >= 0.7 {
+        4
+    } else {
+        3
+    }
+}
+
+/// It returns the end of the UTC day of a timestamp, the review set of the day has every question
+/// due before it.
+pub fn end_of_day(now: i64) -> i64 {
+    (now.div_euclid(DAY) + 1) * DAY
+}
+
+/// `ReviewModel` is when a user should see a question again, stored in the `reviews` collection,
+/// one document per user and question.
+///
+/// Properties:
+///
+/// * `id`: The id of the review.
+/// * `user_id`: The id of the user.
+/// * `question_id`: The id of the test.
+/// * `kind`: The kind of the test.
+/// * `ease`: How fast the interval grows, lowered by every poor review.
+/// * `interval`: The number of days until the next review.
+/// * `repetitions`: How many reviews in a row were remembered.
+/// * `due_at`: Unix timestamp of the next review.
+/// * `reviewed_at`: Unix timestamp of the last review.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReviewModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub question_id: String,
+    pub kind: QuestionKind,
+    pub ease: f64,
+    pub interval: i32,
+    pub repetitions: i32,
+    pub due_at: i64,
+    pub reviewed_at: i64,
+}
+
+impl ReviewModel {
+    /// It creates the review of a question the user has not answered yet.
+    pub fn new(user_id: &str, question_id: &str, kind: QuestionKind) -> Self {
+        Self {
+            id: None,
+            user_id: user_id.to_string(),
+            question_id: question_id.to_string(),
+            kind,
+            ease: DEFAULT_EASE,
+            interval: 0,
+            repetitions: 0,
+            due_at: 0,
+            reviewed_at: 0,
+        }
+    }
+
+    /// It schedules the next review with SM-2: a forgotten question comes back the next day, a
+    /// remembered one after 1 day, then 6 days, then the last interval times the ease.
+    ///
+    /// Arguments:
+    ///
+    /// * `quality`: The quality of the review from 0 to 5, see `review_quality`.
+    /// * `now`: Unix timestamp of the review.
+    pub fn record(&mut self, quality: i32, now: i64) {
+        let quality = quality.clamp(0, 5);
+        if quality < PASSING_QUALITY {
+            self.repetitions = 0;
+            self.interval = 1;
+        } else {
+            self.repetitions += 1;
+            self.interval = match self.repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval as f64 * self.ease).round() as i32,
+            };
+        }
+        let miss = (5 - quality) as f64;
+        self.ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+        self.reviewed_at = now;
+        self.due_at = now + self.interval as i64 * DAY;
+    }
+}
@@ -0,0 +1,190 @@
+use mongodb::bson::{oid::ObjectId, to_document, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::publication_model::ContentKind;
+
+/// `RevisionModel` is one saved state of a course, info, test or template.
+///
+/// Properties:
+///
+/// * `id`: The id of the revision.
+/// * `kind`: The kind of the document.
+/// * `content_id`: The id of the document.
+/// * `number`: The number of the revision, the first revision of a document is 1.
+/// * `author`: The id of the user who saved the revision, unknown for the state before the first
+///   recorded update.
+/// * `created_at`: Unix timestamp of the save.
+/// * `rollback_of`: The number of the revision this one restored, if it is a rollback.
+/// * `snapshot`: The whole document without its `_id`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RevisionModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub kind: ContentKind,
+    pub content_id: String,
+    pub number: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback_of: Option<i32>,
+    pub snapshot: Document,
+}
+
+/// `RevisionSummary` is a revision without its snapshot, as it is listed in the history.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct RevisionSummary {
+    pub number: i32,
+    pub author: Option<String>,
+    pub created_at: i64,
+    pub rollback_of: Option<i32>,
+}
+
+impl From<&RevisionModel> for RevisionSummary {
+    fn from(revision: &RevisionModel) -> Self {
+        Self {
+            number: revision.number,
+            author: revision.author.clone(),
+            created_at: revision.created_at,
+            rollback_of: revision.rollback_of,
+        }
+    }
+}
+
+/// It converts a document to the snapshot stored in a revision. The `_id` is left out, so a
+/// snapshot can be written back over the document. Maps are stored as lists of `[key, value]`
+/// pairs in no particular order, the pairs are sorted by key so two snapshots of the same
+/// document are equal.
+///
+/// Arguments:
+///
+/// * `model`: The course, info, test or template.
+///
+/// Returns:
+///
+/// The snapshot, or None if the model can not be stored as a document.
+pub fn snapshot<T: Serialize>(model: &T) -> Option<Document> {
+    let mut document = to_document(model).ok()?;
+    document.remove("_id");
+    for (_, value) in document.iter_mut() {
+        sort_pairs(value);
+    }
+    Some(document)
+}
+
+fn sort_pairs(value: &mut Bson) {
+    match value {
+        Bson::Document(document) => {
+            for (_, value) in document.iter_mut() {
+                sort_pairs(value);
+            }
+        }
+        Bson::Array(items) => {
+            items.iter_mut().for_each(sort_pairs);
+            let is_pair = |item: &Bson| matches!(item, Bson::Array(pair) if pair.len() == 2);
+            if !items.is_empty() && items.iter().all(is_pair) {
+                items.sort_by_key(|item| match item {
+                    Bson::Array(pair) => pair_key(&pair[0]),
+                    _ => unreachable!(),
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn pair_key(key: &Bson) -> (i64, String) {
+    match key {
+        Bson::Int32(number) => (*number as i64, String::new()),
+        Bson::Int64(number) => (*number, String::new()),
+        key => (0, key.to_string()),
+    }
+}
+
+/// `FieldChange` is one difference between two snapshots.
+///
+/// Properties:
+///
+/// * `path`: The dotted path of the field, array items are addressed by their index, e.g.
+///   `levels.0.1.name`.
+/// * `before`: The old value, None if the field was added.
+/// * `after`: The new value, None if the field was removed.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Bson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Bson>,
+}
+
+/// It compares two snapshots field by field. Nested documents and arrays are compared item by
+/// item, so a change deep inside a course is reported at its own path instead of as a new
+/// `levels` field.
+///
+/// Arguments:
+///
+/// * `before`: The old snapshot.
+/// * `after`: The new snapshot.
+///
+/// Returns:
+///
+/// The changes, in the order of the fields of the old snapshot, then the added fields.
+pub fn diff(before: &Document, after: &Document) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    diff_documents("", before, after, &mut changes);
+    changes
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn diff_documents(
+    prefix: &str,
+    before: &Document,
+    after: &Document,
+    changes: &mut Vec<FieldChange>,
+) {
+    for (key, old) in before {
+        diff_values(join(prefix, key), Some(old), after.get(key), changes);
+    }
+    for (key, new) in after {
+        if !before.contains_key(key) {
+            diff_values(join(prefix, key), None, Some(new), changes);
+        }
+    }
+}
+
+fn diff_values(
+    path: String,
+    before: Option<&Bson>,
+    after: Option<&Bson>,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (before, after) {
+        (Some(Bson::Document(old)), Some(Bson::Document(new))) => {
+            diff_documents(&path, old, new, changes)
+        }
+        (Some(Bson::Array(old)), Some(Bson::Array(new))) => {
+            for index in 0..old.len().max(new.len()) {
+                diff_values(
+                    join(&path, &index.to_string()),
+                    old.get(index),
+                    new.get(index),
+                    changes,
+                );
+            }
+        }
+        (old, new) if old != new => changes.push(FieldChange {
+            path,
+            before: old.cloned(),
+            after: new.cloned(),
+        }),
+        _ => {}
+    }
+}
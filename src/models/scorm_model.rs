@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use mongodb::bson::oid::ObjectId;
+use quick_xml::escape::escape;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::models::{
+    bundle_model::CourseBundle,
+    info_model::{ContentLevel, InfoModel},
+    media_model::MediaModel,
+    template_model::QuestionTemplate,
+    tests_model::{TestModel, TestModelWithActions},
+};
+use crate::utils::render::{escape_html, render_block, render_info, render_question};
+
+/// The score in percent a learner needs to pass a course when the export does not set one.
+pub const DEFAULT_MASTERY_SCORE: i32 = 80;
+
+/// The page of a SCORM package, the only SCO of the package.
+pub const SCORM_PAGE_FILE: &str = "index.html";
+
+/// The script of a SCORM package that talks to the API of the LMS.
+pub const SCORM_RUNTIME_FILE: &str = "scorm.js";
+
+/// The SCORM 1.2 runtime of an exported course. It finds the `API` object of the LMS in the
+/// parent frames or in the opener, marks the course as incomplete when it is opened and, when the
+/// learner presses Finish, grades the questions like `AttemptModel::grade` (the trimmed answer
+/// must be the correct one) and reports the score and `passed`, `failed` or, for a course without
+/// questions, `completed`.
+const SCORM_RUNTIME: &str = r#"// SCORM 1.2 runtime of a course exported by FunLe.
+(function () {
+  "use strict";
+
+  var api = null;
+  var started = new Date();
+  var finished = false;
+
+  function findApi(win) {
+    for (var depth = 0; win && depth < 10; depth++) {
+      if (win.API) {
+        return win.API;
+      }
+      if (win.parent === win) {
+        break;
+      }
+      win = win.parent;
+    }
+    return null;
+  }
+
+  function setValue(name, value) {
+    if (api) {
+      api.LMSSetValue(name, String(value));
+    }
+  }
+
+  function pad(number, width) {
+    var text = String(number);
+    while (text.length < width) {
+      text = "0" + text;
+    }
+    return text;
+  }
+
+  function sessionTime() {
+    var seconds = Math.floor((new Date() - started) / 1000);
+    return pad(Math.floor(seconds / 3600), 4) + ":" + pad(Math.floor(seconds / 60) % 60, 2) +
+      ":" + pad(seconds % 60, 2);
+  }
+
+  function masteryScore() {
+    var score = api ? parseFloat(api.LMSGetValue("cmi.student_data.mastery_score")) : NaN;
+    if (isNaN(score)) {
+      score = parseFloat(document.body.getAttribute("data-mastery-score"));
+    }
+    return isNaN(score) ? 0 : score;
+  }
+
+  function response(question) {
+    if (question.getAttribute("data-kind") === "choice") {
+      var checked = question.querySelector("input:checked");
+      return checked ? checked.value : null;
+    }
+    return question.querySelector("input").value;
+  }
+
+  function grade() {
+    var questions = document.querySelectorAll(".question");
+    var correct = 0;
+    for (var i = 0; i < questions.length; i++) {
+      var question = questions[i];
+      var answer = response(question);
+      var ok = answer !== null && answer.trim() === question.getAttribute("data-answer").trim();
+      if (ok) {
+        correct++;
+      }
+      question.className = "question " + (ok ? "correct" : "wrong");
+      var explanation = question.querySelector(".explanation");
+      if (explanation) {
+        explanation.hidden = false;
+      }
+    }
+    return { correct: correct, total: questions.length };
+  }
+
+  function finish() {
+    var result = grade();
+    var status = "completed";
+    var text = "Completed.";
+    if (result.total > 0) {
+      var score = Math.round(100 * result.correct / result.total);
+      status = score >= masteryScore() ? "passed" : "failed";
+      setValue("cmi.core.score.min", 0);
+      setValue("cmi.core.score.max", 100);
+      setValue("cmi.core.score.raw", score);
+      text = result.correct + " / " + result.total + " (" + score + "%), " + status + ".";
+    }
+    setValue("cmi.core.lesson_status", status);
+    setValue("cmi.core.session_time", sessionTime());
+    if (api) {
+      api.LMSCommit("");
+      api.LMSFinish("");
+    }
+    finished = true;
+    document.getElementById("finish").disabled = true;
+    var node = document.getElementById("result");
+    node.textContent = text;
+    node.hidden = false;
+  }
+
+  window.addEventListener("load", function () {
+    api = findApi(window) || (window.opener ? findApi(window.opener) : null);
+    if (api) {
+      api.LMSInitialize("");
+      var status = api.LMSGetValue("cmi.core.lesson_status");
+      if (status === "" || status === "not attempted") {
+        setValue("cmi.core.lesson_status", "incomplete");
+      }
+    }
+    document.getElementById("finish").addEventListener("click", finish);
+  });
+
+  window.addEventListener("unload", function () {
+    if (api && !finished) {
+      finished = true;
+      setValue("cmi.core.session_time", sessionTime());
+      api.LMSFinish("");
+    }
+  });
+})();
+"#;
+
+const SCORM_STYLE: &str = "body { font-family: sans-serif; max-width: 48em; margin: 0 auto; \
+     padding: 1em; }\n\
+     .question { margin: 1em 0; border: 1px solid #ccc; border-radius: 4px; }\n\
+     .question.correct { border-color: #2e7d32; }\n\
+     .question.wrong { border-color: #c62828; }\n\
+     .question label { display: block; }\n\
+     .callout { background: #f5f5f5; padding: 0.5em 1em; }\n";
+
+/// `ScormPackage` is a course rendered as a SCORM 1.2 package: one page with all the levels of
+/// the course, the runtime that reports to the LMS and the uploaded media the page shows.
+///
+/// Properties:
+///
+/// * `manifest`: The `imsmanifest.xml` of the package.
+/// * `page`: The HTML of the page.
+/// * `media`: The path in the package and the bytes of every media.
+/// * `questions`: The number of questions of the page.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScormPackage {
+    pub manifest: String,
+    pub page: String,
+    pub media: Vec<(String, Vec<u8>)>,
+    pub questions: usize,
+}
+
+/// It returns the extension of the file a media is written to.
+fn media_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "bin",
+    }
+}
+
+/// It renders a list of content blocks, the problems are prefixed with `context`.
+fn render_blocks(blocks: &[ContentLevel], context: &str, errors: &mut Vec<String>) -> String {
+    let mut html = String::new();
+    for (i, block) in blocks.iter().enumerate() {
+        match render_block(block) {
+            Ok(block) => html.push_str(&block),
+            Err(e) => errors.push(format!("{}, block {}: {}", context, i + 1, e)),
+        }
+    }
+    html
+}
+
+/// It renders the blocks of an info.
+fn info_section(info: &InfoModel, errors: &mut Vec<String>) -> String {
+    let id = info.id.map(|id| id.to_hex()).unwrap_or_default();
+    match render_info(info) {
+        Ok(levels) => {
+            let mut html = format!("<article class=\"info\" id=\"info-{}\">\n", id);
+            for (_, blocks) in levels {
+                for block in blocks {
+                    html.push_str(&block);
+                    html.push('\n');
+                }
+            }
+            html.push_str("</article>\n");
+            html
+        }
+        Err(e) => {
+            errors.extend(e.into_iter().map(|e| format!("info `{}`: {}", id, e)));
+            String::new()
+        }
+    }
+}
+
+/// `Question` is what the page shows of a test: the answers of a choice test, or none for an
+/// action test whose answer is typed.
+struct Question<'a> {
+    text: &'a str,
+    answers: Option<&'a [String]>,
+    answer: &'a str,
+    explanation: &'a [ContentLevel],
+}
+
+impl<'a> Question<'a> {
+    fn choice(test: &'a TestModel) -> Self {
+        Self {
+            text: &test.question,
+            answers: Some(&test.answers),
+            answer: &test.answer,
+            explanation: &test.explanation,
+        }
+    }
+}
+
+/// It renders a question as a fieldset the runtime grades: radio buttons whose values are the
+/// indexes of the answers for a choice test, a text field for an action test. The correct
+/// answer is in `data-answer` and the explanation is shown once the course is finished.
+fn question_fieldset(number: usize, question: &Question, errors: &mut Vec<String>) -> String {
+    let context = format!("question `{}`", question.text);
+    let rendered = match render_question(question.text, question.answers.unwrap_or_default()) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            errors.extend(e.into_iter().map(|e| format!("{}: {}", context, e)));
+            return String::new();
+        }
+    };
+    let (kind, answer) = match question.answers {
+        Some(answers) => match answers.iter().position(|answer| answer == question.answer) {
+            Some(index) => ("choice", index.to_string()),
+            None => {
+                errors.push(format!("{}: the answer is not among the answers", context));
+                return String::new();
+            }
+        },
+        None => ("text", question.answer.to_string()),
+    };
+    let mut html = format!(
+        "<fieldset class=\"question\" data-kind=\"{}\" data-answer=\"{}\">\n\
+         <div class=\"prompt\">{}</div>\n",
+        kind,
+        escape_html(&answer),
+        rendered.question
+    );
+    if question.answers.is_some() {
+        for (i, answer) in rendered.answers.iter().enumerate() {
+            html.push_str(&format!(
+                "<label><input type=\"radio\" name=\"q{}\" value=\"{}\"/> {}</label>\n",
+                number, i, answer
+            ));
+        }
+    } else {
+        html.push_str(&format!(
+            "<input type=\"text\" name=\"q{}\" autocomplete=\"off\"/>\n",
+            number
+        ));
+    }
+    if !question.explanation.is_empty() {
+        let context = format!("{}, explanation", context);
+        html.push_str(&format!(
+            "<div class=\"explanation\" hidden>{}</div>\n",
+            render_blocks(question.explanation, &context, errors)
+        ));
+    }
+    html.push_str("</fieldset>\n");
+    html
+}
+
+fn by_id<T>(documents: &[T], id: impl Fn(&T) -> Option<String>) -> HashMap<String, &T> {
+    documents
+        .iter()
+        .filter_map(|document| id(document).map(|id| (id, document)))
+        .collect()
+}
+
+impl ScormPackage {
+    /// It renders a course into a SCORM 1.2 package. The layers are shown in order and every
+    /// level as a section with its infos or its questions. Questions of templates are generated
+    /// with `seed`, so every learner of the package gets the same questions. The urls of the
+    /// uploaded media are rewritten to the copies in the package.
+    ///
+    /// Arguments:
+    ///
+    /// * `bundle`: The course with its content, see `CourseBundle`.
+    /// * `media`: The uploaded media the course uses with their bytes.
+    /// * `mastery_score`: The score in percent a learner needs to pass, written to the manifest.
+    /// * `seed`: The seed the questions of the templates are generated with.
+    ///
+    /// Returns:
+    ///
+    /// The package, or the content that can not be rendered.
+    pub fn new(
+        bundle: &CourseBundle,
+        media: &[(MediaModel, Vec<u8>)],
+        mastery_score: i32,
+        seed: i64,
+    ) -> Result<Self, Vec<String>> {
+        let hex = |id: &Option<ObjectId>| id.map(|id| id.to_hex());
+        let infos = by_id(&bundle.infos, |info: &InfoModel| hex(&info.id));
+        let choice_tests = by_id(&bundle.choice_tests, |test: &TestModel| hex(&test.id));
+        let action_tests = by_id(&bundle.action_tests, |test: &TestModelWithActions| {
+            hex(&test.id)
+        });
+        let templates = by_id(&bundle.templates, |template: &QuestionTemplate| {
+            hex(&template.id)
+        });
+        let course = &bundle.course;
+        let mut errors = vec![];
+        let mut questions = 0;
+        let mut body = String::new();
+        let mut layers: Vec<&i32> = course.levels.keys().collect();
+        layers.sort();
+        for layer in layers {
+            for level in &course.levels[layer] {
+                body.push_str(&format!(
+                    "<section class=\"level\" id=\"level-{}\">\n<h2>{}</h2>\n",
+                    escape_html(&level.id),
+                    escape_html(&level.title)
+                ));
+                for id in &level.ids {
+                    if let Some(info) = infos.get(id) {
+                        body.push_str(&info_section(info, &mut errors));
+                        continue;
+                    }
+                    let instance;
+                    let question = if let Some(test) = choice_tests.get(id) {
+                        Question::choice(test)
+                    } else if let Some(test) = action_tests.get(id) {
+                        Question {
+                            text: &test.question,
+                            answers: None,
+                            answer: &test.answer,
+                            explanation: &test.explanation,
+                        }
+                    } else if let Some(template) = templates.get(id) {
+                        instance = match template.instantiate(seed) {
+                            Ok(instance) => instance,
+                            Err(e) => {
+                                errors.push(format!("template `{}`: {}", id, e));
+                                continue;
+                            }
+                        };
+                        Question::choice(&instance.test)
+                    } else {
+                        errors.push(format!("level `{}`: unknown content `{}`", level.id, id));
+                        continue;
+                    };
+                    questions += 1;
+                    body.push_str(&question_fieldset(questions, &question, &mut errors));
+                }
+                body.push_str("</section>\n");
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut files = vec![];
+        for (model, data) in media {
+            let id = hex(&model.id).unwrap_or_default();
+            let path = format!("media/{}.{}", id, media_extension(&model.content_type));
+            body = body.replace(&format!("\"{}\"", model.url()), &format!("\"{}\"", path));
+            files.push((path, data.clone()));
+        }
+
+        let page = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\"/>\n\
+             <title>{title}</title>\n<style>\n{style}</style>\n\
+             <script src=\"{runtime}\"></script>\n</head>\n\
+             <body data-mastery-score=\"{mastery}\">\n<h1>{title}</h1>\n\
+             <p class=\"description\">{description}</p>\n{body}\
+             <p id=\"result\" hidden></p>\n\
+             <button type=\"button\" id=\"finish\">Finish</button>\n</body>\n</html>\n",
+            title = escape_html(&course.title),
+            style = SCORM_STYLE,
+            runtime = SCORM_RUNTIME_FILE,
+            mastery = mastery_score,
+            description = escape_html(&course.description),
+            body = body,
+        );
+
+        let mut resources = format!(
+            "      <file href=\"{}\"/>\n      <file href=\"{}\"/>\n",
+            SCORM_PAGE_FILE, SCORM_RUNTIME_FILE
+        );
+        for (path, _) in &files {
+            resources.push_str(&format!("      <file href=\"{}\"/>\n", path));
+        }
+        let manifest = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <manifest identifier=\"course-{id}\" version=\"1.0\" \
+             xmlns=\"http://www.imsproject.org/xsd/imscp_rootv1p1p2\" \
+             xmlns:adlcp=\"http://www.adlnet.org/xsd/adlcp_rootv1p2\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+             xsi:schemaLocation=\"http://www.imsproject.org/xsd/imscp_rootv1p1p2 imscp_rootv1p1p2.xsd \
+             http://www.imsglobal.org/xsd/imsmd_rootv1p2p1 imsmd_rootv1p2p1.xsd \
+             http://www.adlnet.org/xsd/adlcp_rootv1p2 adlcp_rootv1p2.xsd\">\n\
+             \x20 <metadata>\n\
+             \x20   <schema>ADL SCORM</schema>\n\
+             \x20   <schemaversion>1.2</schemaversion>\n\
+             \x20 </metadata>\n\
+             \x20 <organizations default=\"ORG-1\">\n\
+             \x20   <organization identifier=\"ORG-1\">\n\
+             \x20     <title>{title}</title>\n\
+             \x20     <item identifier=\"ITEM-1\" identifierref=\"RES-1\" isvisible=\"true\">\n\
+             \x20       <title>{title}</title>\n\
+             \x20       <adlcp:masteryscore>{mastery}</adlcp:masteryscore>\n\
+             \x20     </item>\n\
+             \x20   </organization>\n\
+             \x20 </organizations>\n\
+             \x20 <resources>\n\
+             \x20   <resource identifier=\"RES-1\" type=\"webcontent\" adlcp:scormtype=\"sco\" \
+             href=\"{page}\">\n\
+             {resources}\
+             \x20   </resource>\n\
+             \x20 </resources>\n\
+             </manifest>\n",
+            id = hex(&course.id).unwrap_or_default(),
+            title = escape(&course.title),
+            mastery = mastery_score,
+            page = SCORM_PAGE_FILE,
+            resources = resources,
+        );
+
+        Ok(Self {
+            manifest,
+            page,
+            media: files,
+            questions,
+        })
+    }
+
+    /// It packs the package into a zip archive with the manifest at the root.
+    pub fn to_zip(&self) -> Result<Vec<u8>, String> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut files = vec![
+            ("imsmanifest.xml", self.manifest.as_bytes()),
+            (SCORM_PAGE_FILE, self.page.as_bytes()),
+            (SCORM_RUNTIME_FILE, SCORM_RUNTIME.as_bytes()),
+        ];
+        for (path, data) in &self.media {
+            files.push((path, data));
+        }
+        for (name, data) in files {
+            writer
+                .start_file(name, options)
+                .map_err(|e| e.to_string())?;
+            writer.write_all(data).map_err(|e| e.to_string())?;
+        }
+        writer
+            .finish()
+            .map(|cursor| cursor.into_inner())
+            .map_err(|e| e.to_string())
+    }
+}
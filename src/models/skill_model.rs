@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::theme_model::slugify;
+
+/// How much of the distance to the score of a new attempt the mastery score moves.
+pub const MASTERY_RATE: f64 = 0.3;
+
+/// The mastery score from which a skill counts as mastered.
+pub const MASTERED_SCORE: f64 = 0.8;
+
+/// How many graded attempts a skill needs before it can count as mastered.
+pub const MASTERED_ATTEMPTS: i32 = 3;
+
+/// `SkillModel` is one skill of the `skills` catalog, e.g. "common denominator". Tests, infos and
+/// templates are tagged with the ids of the skills they exercise.
+///
+/// Properties:
+///
+/// * `id`: The id of the skill.
+/// * `slug`: The unique url name of the skill, e.g. `common-denominator`.
+/// * `name`: The display name of the skill.
+/// * `description`: What a learner who has the skill can do.
+/// * `theme_id`: The theme the skill belongs to, if any.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SkillModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(default)]
+    pub slug: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+}
+
+impl SkillModel {
+    /// It checks a skill before it is saved. An empty slug is made from the name.
+    ///
+    /// Returns:
+    ///
+    /// The problems, empty if the skill is valid.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut errors = vec![];
+        self.name = self.name.trim().to_string();
+        if self.slug.is_empty() {
+            self.slug = slugify(&self.name);
+        }
+        if self.name.is_empty() {
+            errors.push("the name is empty".to_string());
+        }
+        if self.slug.is_empty() || slugify(&self.slug) != self.slug {
+            errors.push(format!(
+                "slug `{}` must be lowercase letters and digits separated by dashes",
+                self.slug
+            ));
+        }
+        errors
+    }
+}
+
+/// `MasteryLevel` sums up a mastery score for learners and teachers.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MasteryLevel {
+    Unseen,
+    Learning,
+    Mastered,
+}
+
+/// `MasteryModel` is how well a user masters a skill, stored in the `mastery` collection.
+///
+/// Properties:
+///
+/// * `id`: The id of the mastery.
+/// * `user_id`: The id of the user.
+/// * `skill_id`: The id of the skill.
+/// * `score`: From 0 to 1, a moving average of the scores of the graded attempts, recent attempts
+///   weigh the most.
+/// * `attempts`: How many graded attempts exercised the skill.
+/// * `correct`: How many of them were answered correctly.
+/// * `updated_at`: Unix timestamp of the last graded attempt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MasteryModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub skill_id: String,
+    pub score: f64,
+    pub attempts: i32,
+    pub correct: i32,
+    pub updated_at: i64,
+}
+
+impl MasteryModel {
+    /// It creates the mastery of a skill the user has not practiced yet.
+    pub fn new(user_id: &str, skill_id: &str) -> Self {
+        Self {
+            id: None,
+            user_id: user_id.to_string(),
+            skill_id: skill_id.to_string(),
+            score: 0.0,
+            attempts: 0,
+            correct: 0,
+            updated_at: 0,
+        }
+    }
+
+    /// It moves the score `MASTERY_RATE` of the way to the score of a graded attempt.
+    ///
+    /// Arguments:
+    ///
+    /// * `score`: The score of the attempt from 0 to 1.
+    /// * `correct`: Whether the attempt was answered correctly.
+    /// * `now`: Unix timestamp of the grading.
+    pub fn record(&mut self, score: f64, correct: bool, now: i64) {
+        self.score += MASTERY_RATE * (score.clamp(0.0, 1.0) - self.score);
+        self.attempts += 1;
+        if correct {
+            self.correct += 1;
+        }
+        self.updated_at = now;
+    }
+
+    /// It sums up the score: a skill is mastered once the score reaches `MASTERED_SCORE` after at
+    /// least `MASTERED_ATTEMPTS` attempts.
+    pub fn level(&self) -> MasteryLevel {
+        if self.attempts == 0 {
+            MasteryLevel::Unseen
+        } else if self.attempts >= MASTERED_ATTEMPTS && self.score >= MASTERED_SCORE {
+            MasteryLevel::Mastered
+        } else {
+            MasteryLevel::Learning
+        }
+    }
+}
+
+/// `SkillMastery` is one skill of the knowledge map of a learner.
+///
+/// Properties:
+///
+/// * `skill`: The skill.
+/// * `score`: The mastery score from 0 to 1.
+/// * `attempts`: How many graded attempts exercised the skill.
+/// * `level`: The mastery level.
+/// * `infos`: The published infos that teach the skill, to suggest for skills that are not
+///   mastered.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SkillMastery {
+    pub skill: SkillModel,
+    pub score: f64,
+    pub attempts: i32,
+    pub level: MasteryLevel,
+    pub infos: Vec<String>,
+}
+
+/// It builds the knowledge map of a learner: every skill of the catalog with the mastery of the
+/// learner, skills that were never practiced are unseen.
+///
+/// Arguments:
+///
+/// * `skills`: The skills of the catalog.
+/// * `mastery`: The mastery of the learner.
+/// * `infos`: The ids of the infos that teach each skill, by skill id.
+///
+/// Returns:
+///
+/// The skills sorted by name.
+pub fn knowledge_map(
+    skills: &[SkillModel],
+    mastery: &[MasteryModel],
+    infos: &HashMap<String, Vec<String>>,
+) -> Vec<SkillMastery> {
+    let mut map: Vec<SkillMastery> = skills
+        .iter()
+        .map(|skill| {
+            let id = skill.id.map(|id| id.to_hex()).unwrap_or_default();
+            let found = mastery.iter().find(|m| m.skill_id == id);
+            let mastery = found.cloned().unwrap_or_else(|| MasteryModel::new("", &id));
+            SkillMastery {
+                skill: skill.clone(),
+                score: mastery.score,
+                attempts: mastery.attempts,
+                level: mastery.level(),
+                infos: infos.get(&id).cloned().unwrap_or_default(),
+            }
+        })
+        .collect();
+    map.sort_by(|a, b| a.skill.name.cmp(&b.skill.name));
+    map
+}
+
+/// `LearnerMastery` is the mastery of one learner of a class.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LearnerMastery {
+    pub user_id: String,
+    pub username: String,
+    pub score: f64,
+    pub attempts: i32,
+    pub level: MasteryLevel,
+}
+
+/// `ClassSkill` is how well a class masters one skill.
+///
+/// Properties:
+///
+/// * `skill`: The skill.
+/// * `mean_score`: The mean mastery score of the learners, unseen counts as 0.
+/// * `mastered`, `learning`, `unseen`: How many learners are at each level.
+/// * `learners`: The mastery of every learner, weakest first.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ClassSkill {
+    pub skill: SkillModel,
+    pub mean_score: f64,
+    pub mastered: usize,
+    pub learning: usize,
+    pub unseen: usize,
+    pub learners: Vec<LearnerMastery>,
+}
+
+/// It sums up the mastery of a class per skill.
+///
+/// Arguments:
+///
+/// * `skills`: The skills to report.
+/// * `learners`: The ids and names of the learners of the class.
+/// * `mastery`: The mastery of the learners.
+///
+/// Returns:
+///
+/// The skills, the least mastered first.
+pub fn class_skills(
+    skills: &[SkillModel],
+    learners: &[(String, String)],
+    mastery: &[MasteryModel],
+) -> Vec<ClassSkill> {
+    let mut report: Vec<ClassSkill> = skills
+        .iter()
+        .map(|skill| {
+            let id = skill.id.map(|id| id.to_hex()).unwrap_or_default();
+            let mut learners: Vec<LearnerMastery> = learners
+                .iter()
+                .map(|(user_id, username)| {
+                    let mastery = mastery
+                        .iter()
+                        .find(|m| m.skill_id == id && m.user_id == *user_id)
+                        .cloned()
+                        .unwrap_or_else(|| MasteryModel::new(user_id, &id));
+                    LearnerMastery {
+                        user_id: user_id.clone(),
+                        username: username.clone(),
+                        score: mastery.score,
+                        attempts: mastery.attempts,
+                        level: mastery.level(),
+                    }
+                })
+                .collect();
+            learners.sort_by(|a, b| a.score.total_cmp(&b.score));
+            let count = |level| learners.iter().filter(|l| l.level == level).count();
+            ClassSkill {
+                skill: skill.clone(),
+                mean_score: if learners.is_empty() {
+                    0.0
+                } else {
+                    learners.iter().map(|l| l.score).sum::<f64>() / learners.len() as f64
+                },
+                mastered: count(MasteryLevel::Mastered),
+                learning: count(MasteryLevel::Learning),
+                unseen: count(MasteryLevel::Unseen),
+                learners,
+            }
+        })
+        .collect();
+    report.sort_by(|a, b| a.mean_score.total_cmp(&b.mean_score));
+    report
+}
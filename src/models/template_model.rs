@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    info_model::ContentLevel,
+    tests_model::{TestModel, TestTranslation},
+};
+use crate::utils::formula::{eval, format_number};
+
+/// How many times we try to draw variable values that satisfy all constraints of a template.
+const MAX_DRAWS: usize = 200;
+
+/// `QuestionTemplate` is a parameterized choice question. The server draws values for the
+/// variables and evaluates the formulas to get a concrete `TestModel`.
+///
+/// Properties:
+///
+/// * `id`: The id of the template.
+/// * `theme`: The slug of the theme of the generated questions.
+/// * `theme_id`: The id of the theme in the `themes` collection.
+/// * `skills`: The ids of the skills the generated questions exercise.
+/// * `question`: Text of the question. Every `{...}` is a formula that is replaced by its value,
+/// e.g. `{a} + {b}`.
+/// * `variables`: The variables and the ranges their values are drawn from.
+/// * `constraints`: Formulas that must all be true for the drawn values, e.g. `a > b`.
+/// * `answer`: Formula of the correct answer.
+/// * `distractors`: Formulas of the wrong answers.
+/// * `level`: 1-5, 1 being the easiest and 5 being the hardest
+/// * `hints`: Progressive hints of the generated questions.
+/// * `explanation`: The worked explanation of the generated questions.
+/// * `hint_penalty`: Percent of the score taken off for every revealed hint, no discount if empty.
+/// * `translations`: Translations by locale. A translated question has the same `{...}`
+/// formulas, the answers are numbers and are not translated.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuestionTemplate {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skills: Vec<String>,
+    pub question: String,
+    pub variables: Vec<TemplateVariable>,
+    #[serde(default)]
+    pub constraints: Vec<String>,
+    pub answer: String,
+    pub distractors: Vec<String>,
+    pub level: i32, // mean difficulty 1-5
+    #[serde(default)]
+    pub hints: Vec<ContentLevel>,
+    #[serde(default)]
+    pub explanation: Vec<ContentLevel>,
+    #[serde(default)]
+    pub hint_penalty: Option<i32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, TestTranslation>,
+}
+
+/// `TemplateVariable` is an integer variable of a `QuestionTemplate`.
+///
+/// Properties:
+///
+/// * `name`: The name used in formulas.
+/// * `min`: The smallest value (inclusive).
+/// * `max`: The biggest value (inclusive).
+/// * `step`: Values are `min + k * step`, 1 by default.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub min: i64,
+    pub max: i64,
+    pub step: Option<i64>,
+}
+
+/// `TemplateInstance` is a concrete question generated from a template. The same `template_id`
+/// and `seed` always give the same question, so an answer can be regraded later.
+///
+/// Properties:
+///
+/// * `template_id`: The id of the template.
+/// * `seed`: The seed the question was generated with.
+/// * `test`: The generated question.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TemplateInstance {
+    pub template_id: String,
+    pub seed: i64,
+    pub test: TestModel,
+}
+
+impl QuestionTemplate {
+    /// It generates a new random seed for the template.
+    ///
+    /// Returns:
+    ///
+    /// A non negative seed
+    pub fn random_seed() -> i64 {
+        rand::thread_rng().gen_range(0..i64::MAX)
+    }
+
+    /// It draws values for the variables of the template that satisfy all the constraints.
+    ///
+    /// Arguments:
+    ///
+    /// * `rng`: The random generator to draw the values with.
+    ///
+    /// Returns:
+    ///
+    /// A Result<HashMap<String, f64>, String>
+    fn draw_values(&self, rng: &mut StdRng) -> Result<HashMap<String, f64>, String> {
+        for _ in 0..MAX_DRAWS {
+            let mut values = HashMap::new();
+            for variable in &self.variables {
+                let step = variable.step.unwrap_or(1);
+                if step <= 0 || variable.max < variable.min {
+                    return Err(format!("variable `{}` has an empty range", variable.name));
+                }
+                let steps = (variable.max - variable.min) / step;
+                let value = variable.min + rng.gen_range(0..=steps) * step;
+                values.insert(variable.name.clone(), value as f64);
+            }
+            let mut satisfied = true;
+            for constraint in &self.constraints {
+                let result = eval(constraint, &values)
+                    .map_err(|e| format!("constraint `{}`: {}", constraint, e))?;
+                if result == 0.0 {
+                    satisfied = false;
+                    break;
+                }
+            }
+            if satisfied {
+                return Ok(values);
+            }
+        }
+        Err("constraints can not be satisfied".to_string())
+    }
+
+    /// It replaces every `{formula}` in the question with the value of the formula.
+    fn render_question(&self, text: &str, values: &HashMap<String, f64>) -> Result<String, String> {
+        let mut question = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "unclosed `{` in question".to_string())?
+                + start;
+            let formula = &rest[start + 1..end];
+            let value =
+                eval(formula, values).map_err(|e| format!("placeholder `{}`: {}", formula, e))?;
+            question.push_str(&rest[..start]);
+            question.push_str(&format_number(value));
+            rest = &rest[end + 1..];
+        }
+        question.push_str(rest);
+        Ok(question)
+    }
+
+    /// It generates a concrete question from the template. The generation is deterministic: the
+    /// same seed always gives the same question with the same order of answers.
+    ///
+    /// Arguments:
+    ///
+    /// * `seed`: The seed for the random generator.
+    ///
+    /// Returns:
+    ///
+    /// A Result<TemplateInstance, String>, the error describes what is wrong with the template.
+    pub fn instantiate(&self, seed: i64) -> Result<TemplateInstance, String> {
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let values = self.draw_values(&mut rng)?;
+
+        let question = self.render_question(&self.question, &values)?;
+        let answer = eval(&self.answer, &values)
+            .map(format_number)
+            .map_err(|e| format!("answer `{}`: {}", self.answer, e))?;
+
+        let mut answers = vec![answer.clone()];
+        for distractor in &self.distractors {
+            let value = eval(distractor, &values)
+                .map(format_number)
+                .map_err(|e| format!("distractor `{}`: {}", distractor, e))?;
+            if !answers.contains(&value) {
+                answers.push(value);
+            }
+        }
+        answers.shuffle(&mut rng);
+
+        let mut translations = HashMap::new();
+        for (locale, translation) in &self.translations {
+            let question = match &translation.question {
+                Some(text) => Some(
+                    self.render_question(text, &values)
+                        .map_err(|e| format!("translation `{}`: {}", locale, e))?,
+                ),
+                None => None,
+            };
+            translations.insert(
+                locale.clone(),
+                TestTranslation {
+                    question,
+                    answers: None,
+                    hints: translation.hints.clone(),
+                    explanation: translation.explanation.clone(),
+                },
+            );
+        }
+
+        Ok(TemplateInstance {
+            template_id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
+            seed,
+            test: TestModel {
+                id: self.id,
+                theme: self.theme.clone(),
+                theme_id: self.theme_id.clone(),
+                skills: self.skills.clone(),
+                question,
+                answers,
+                answer,
+                level: self.level,
+                hints: self.hints.clone(),
+                explanation: self.explanation.clone(),
+                hint_penalty: self.hint_penalty,
+                translations,
+            },
+        })
+    }
+
+    /// It lists the fields of the template that have no translation to the locale.
+    pub fn missing_translations(&self, locale: &str) -> Vec<String> {
+        let translation = self.translations.get(locale).cloned().unwrap_or_default();
+        let mut missing = vec![];
+        if translation.question.is_none() {
+            missing.push("question".to_string());
+        }
+        if !self.hints.is_empty() && translation.hints.is_none() {
+            missing.push("hints".to_string());
+        }
+        if !self.explanation.is_empty() && translation.explanation.is_none() {
+            missing.push("explanation".to_string());
+        }
+        missing
+    }
+}
@@ -3,10 +3,14 @@
 #![allow(unused_parens)]
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use mongodb::{bson::oid::ObjectId, options::UpdateModifications};
 
+use crate::models::info_model::ContentLevel;
+use crate::utils::locale::{default_locale, Locales, Localize};
+
 /// `TestModel` is a struct that has an `id` field of type `Option<ObjectId>`, a `text_of_question`
 /// field of type `String`, an `answers` field of type `Vec<String>`, a `correct_answer` field of type
 /// `String`, and a `level` field of type `i32`.
@@ -17,19 +21,38 @@ use mongodb::{bson::oid::ObjectId, options::UpdateModifications};
 /// Properties:
 ///
 /// * `id`: The id of the test.
+/// * `theme`: The slug of the theme of the test.
+/// * `theme_id`: The id of the theme of the test in the `themes` collection.
+/// * `skills`: The ids of the skills the test exercises.
 /// * `text_of_question`: The question itself
 /// * `answers`: A vector of strings that are the possible answers to the question.
 /// * `correct_answer`: The correct answer to the question
 /// * `level`: 1-5, 1 being the easiest and 5 being the hardest
+/// * `hints`: Progressive hints, revealed to the learner one by one.
+/// * `explanation`: The worked explanation shown after the question is answered.
+/// * `hint_penalty`: Percent of the score taken off for every revealed hint, no discount if empty.
+/// * `translations`: Translations of the question by locale.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct TestModel {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skills: Vec<String>,
     pub question: String,
     pub answers: Vec<String>,
     pub answer: String,
     pub level: i32, // mean difficulty 1-5
+    #[serde(default)]
+    pub hints: Vec<ContentLevel>,
+    #[serde(default)]
+    pub explanation: Vec<ContentLevel>,
+    #[serde(default)]
+    pub hint_penalty: Option<i32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, TestTranslation>,
 }
 
 /// `TestModelWithActions` is a struct that has an `id` field of type `Option<ObjectId>`, a `example`
@@ -42,17 +65,252 @@ pub struct TestModel {
 /// Properties:
 ///
 /// * `id`: The id of the test model.
+/// * `theme`: The slug of the theme of the test.
+/// * `theme_id`: The id of the theme of the test in the `themes` collection.
+/// * `skills`: The ids of the skills the test exercises.
 /// * `example`: The example sentence that the user will see.
 /// * `actions`: A list of actions that the user can take.
 /// * `answer`: The correct answer to the question
 /// * `level`: The difficulty of the question.
+/// * `hints`: Progressive hints, revealed to the learner one by one.
+/// * `explanation`: The worked explanation shown after the question is answered.
+/// * `hint_penalty`: Percent of the score taken off for every revealed hint, no discount if empty.
+/// * `translations`: Translations of the question by locale.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct TestModelWithActions {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skills: Vec<String>,
     pub question: String,
     pub answers: Vec<String>,
     pub answer: String,
     pub level: i32, // mean difficulty 1-5
+    #[serde(default)]
+    pub hints: Vec<ContentLevel>,
+    #[serde(default)]
+    pub explanation: Vec<ContentLevel>,
+    #[serde(default)]
+    pub hint_penalty: Option<i32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, TestTranslation>,
+}
+
+/// `TestTranslation` is the translation of a question to one locale. Fields that are not set are
+/// shown in the default locale.
+///
+/// Properties:
+///
+/// * `question`: The translated question.
+/// * `answers`: The translated answers, in the same order as the answers of the question.
+/// * `hints`: The translated hints.
+/// * `explanation`: The translated explanation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct TestTranslation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answers: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hints: Option<Vec<ContentLevel>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Vec<ContentLevel>>,
+}
+
+/// It replaces the fields of a question with the translation the user prefers. The correct answer
+/// becomes the translated answer at its position, so it stays one of the translated answers.
+///
+/// Returns:
+///
+/// The locale of the translation, or the default locale.
+fn localize_question(
+    question: &mut String,
+    answer: &mut String,
+    answers: &mut Vec<String>,
+    hints: &mut Vec<ContentLevel>,
+    explanation: &mut Vec<ContentLevel>,
+    translations: &mut HashMap<String, TestTranslation>,
+    locales: &Locales,
+) -> String {
+    let served = match locales.pick(translations) {
+        Some((locale, translation)) => {
+            if let Some(text) = &translation.question {
+                *question = text.clone();
+            }
+            match &translation.answers {
+                Some(translated) if translated.len() == answers.len() => {
+                    if let Some(position) = answers.iter().position(|a| a == answer) {
+                        *answer = translated[position].clone();
+                    }
+                    *answers = translated.clone()
+                }
+                _ => {}
+            }
+            if let Some(translated) = &translation.hints {
+                *hints = translated.clone();
+            }
+            if let Some(translated) = &translation.explanation {
+                *explanation = translated.clone();
+            }
+            locale.to_string()
+        }
+        None => default_locale(),
+    };
+    translations.clear();
+    served
+}
+
+/// It lists the fields of a question that have no translation to the locale.
+fn missing_question_translations(
+    answers: &[String],
+    hints: &[ContentLevel],
+    explanation: &[ContentLevel],
+    translations: &HashMap<String, TestTranslation>,
+    locale: &str,
+) -> Vec<String> {
+    let translation = translations.get(locale).cloned().unwrap_or_default();
+    let mut missing = vec![];
+    if translation.question.is_none() {
+        missing.push("question".to_string());
+    }
+    if translation.answers.map(|a| a.len()) != Some(answers.len()) {
+        missing.push("answers".to_string());
+    }
+    if !hints.is_empty() && translation.hints.is_none() {
+        missing.push("hints".to_string());
+    }
+    if !explanation.is_empty() && translation.explanation.is_none() {
+        missing.push("explanation".to_string());
+    }
+    missing
+}
+
+/// It maps an answer given in any locale to the same answer in the default locale, so a question
+/// can be graded whatever locale it was served in. Answers are matched by their position.
+///
+/// Arguments:
+///
+/// * `answers`: The answers of the question in the default locale.
+/// * `translations`: The translations of the question.
+/// * `answer`: The answer of the user.
+///
+/// Returns:
+///
+/// The answer in the default locale, or the answer itself if it is not one of the answers.
+pub fn canonical_answer(
+    answers: &[String],
+    translations: &HashMap<String, TestTranslation>,
+    answer: &str,
+) -> String {
+    let answer = answer.trim();
+    if answers.iter().any(|a| a.trim() == answer) {
+        return answer.to_string();
+    }
+    translations
+        .values()
+        .filter_map(|translation| translation.answers.as_ref())
+        .filter(|translated| translated.len() == answers.len())
+        .find_map(|translated| translated.iter().position(|a| a.trim() == answer))
+        .map(|position| answers[position].clone())
+        .unwrap_or_else(|| answer.to_string())
+}
+
+/// It checks that every translation has as many answers as the question.
+///
+/// Returns:
+///
+/// The list of problems
+pub fn validate_translations(
+    answers: &[String],
+    translations: &HashMap<String, TestTranslation>,
+) -> Vec<String> {
+    translations
+        .iter()
+        .filter_map(|(locale, translation)| match &translation.answers {
+            Some(translated) if translated.len() != answers.len() => Some(format!(
+                "translation `{}`: {} answers instead of {}",
+                locale,
+                translated.len(),
+                answers.len()
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Localize for TestModel {
+    fn localize(&mut self, locales: &Locales) -> String {
+        localize_question(
+            &mut self.question,
+            &mut self.answer,
+            &mut self.answers,
+            &mut self.hints,
+            &mut self.explanation,
+            &mut self.translations,
+            locales,
+        )
+    }
+
+    fn missing_translations(&self, locale: &str) -> Vec<String> {
+        missing_question_translations(
+            &self.answers,
+            &self.hints,
+            &self.explanation,
+            &self.translations,
+            locale,
+        )
+    }
+}
+
+impl Localize for TestModelWithActions {
+    fn localize(&mut self, locales: &Locales) -> String {
+        localize_question(
+            &mut self.question,
+            &mut self.answer,
+            &mut self.answers,
+            &mut self.hints,
+            &mut self.explanation,
+            &mut self.translations,
+            locales,
+        )
+    }
+
+    fn missing_translations(&self, locale: &str) -> Vec<String> {
+        missing_question_translations(
+            &self.answers,
+            &self.hints,
+            &self.explanation,
+            &self.translations,
+            locale,
+        )
+    }
+}
+
+impl TestModel {
+    /// It removes the hints and the explanation, learners get them through attempts.
+    ///
+    /// Returns:
+    ///
+    /// The test without hints and explanation
+    pub fn for_learner(mut self) -> Self {
+        self.hints.clear();
+        self.explanation.clear();
+        self
+    }
+}
+
+impl TestModelWithActions {
+    /// It removes the hints and the explanation, learners get them through attempts.
+    ///
+    /// Returns:
+    ///
+    /// The test without hints and explanation
+    pub fn for_learner(mut self) -> Self {
+        self.hints.clear();
+        self.explanation.clear();
+        self
+    }
 }
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// `ThemeLevel` is the place of a theme in the hierarchy, a subject is split into topics and a
+/// topic into subtopics.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeLevel {
+    Subject,
+    Topic,
+    Subtopic,
+}
+
+impl ThemeLevel {
+    /// It returns the name of the level as it is stored.
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemeLevel::Subject => "subject",
+            ThemeLevel::Topic => "topic",
+            ThemeLevel::Subtopic => "subtopic",
+        }
+    }
+
+    /// It returns the level the parent of a theme of this level has, None for subjects.
+    pub fn parent(self) -> Option<ThemeLevel> {
+        match self {
+            ThemeLevel::Subject => None,
+            ThemeLevel::Topic => Some(ThemeLevel::Subject),
+            ThemeLevel::Subtopic => Some(ThemeLevel::Topic),
+        }
+    }
+}
+
+/// `ThemeModel` is one theme of the `themes` collection. Tests, infos and templates point at it
+/// with their `theme_id` and keep its slug in `theme`.
+///
+/// Properties:
+///
+/// * `id`: The id of the theme.
+/// * `slug`: The unique url name of the theme, e.g. `fractions`.
+/// * `name`: The display name of the theme, e.g. `Fractions`.
+/// * `level`: Whether the theme is a subject, a topic or a subtopic.
+/// * `parent_id`: The id of the subject of a topic, or of the topic of a subtopic.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ThemeModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(default)]
+    pub slug: String,
+    pub name: String,
+    pub level: ThemeLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+}
+
+/// `ThemeNode` is a theme with its children, as themes are listed for learners.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ThemeNode {
+    #[serde(flatten)]
+    pub theme: ThemeModel,
+    pub children: Vec<ThemeNode>,
+}
+
+/// It turns a free-text theme into a slug: lowercase letters and digits separated by single
+/// dashes, so `"Fractions"`, `"fractions "` and `"  FRACTIONS"` are all `fractions`.
+///
+/// Arguments:
+///
+/// * `text`: The name of the theme.
+///
+/// Returns:
+///
+/// The slug, empty if the text has no letters or digits.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+impl ThemeModel {
+    /// It creates a theme, the slug is made from the name.
+    pub fn new(name: &str, level: ThemeLevel, parent_id: Option<String>) -> Self {
+        Self {
+            id: None,
+            slug: slugify(name),
+            name: name.trim().to_string(),
+            level,
+            parent_id,
+        }
+    }
+
+    /// It checks a theme before it is saved. An empty slug is made from the name.
+    ///
+    /// Arguments:
+    ///
+    /// * `parent`: The theme `parent_id` points at, None if it points at nothing.
+    ///
+    /// Returns:
+    ///
+    /// The problems, empty if the theme is valid.
+    pub fn validate(&mut self, parent: Option<&ThemeModel>) -> Vec<String> {
+        let mut errors = vec![];
+        self.name = self.name.trim().to_string();
+        if self.slug.is_empty() {
+            self.slug = slugify(&self.name);
+        }
+        if self.name.is_empty() {
+            errors.push("the name is empty".to_string());
+        }
+        if self.slug.is_empty() || slugify(&self.slug) != self.slug {
+            errors.push(format!(
+                "slug `{}` must be lowercase letters and digits separated by dashes",
+                self.slug
+            ));
+        }
+        match (self.level.parent(), &self.parent_id, parent) {
+            (None, None, _) => {}
+            (None, Some(_), _) => errors.push("a subject has no parent".to_string()),
+            (Some(_), None, _) => errors.push(format!("a {} needs a parent", self.level.name())),
+            (Some(_), Some(id), None) => errors.push(format!("unknown parent theme `{}`", id)),
+            (Some(level), Some(_), Some(parent)) => {
+                if parent.level != level {
+                    errors.push(format!(
+                        "the parent of a {} must be a {}, not a {}",
+                        self.level.name(),
+                        level.name(),
+                        parent.level.name()
+                    ));
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// It arranges themes into subjects with their topics and subtopics, sorted by name. Themes
+/// whose parent is missing are left out.
+pub fn theme_tree(themes: &[ThemeModel]) -> Vec<ThemeNode> {
+    fn children(themes: &[ThemeModel], parent_id: Option<String>) -> Vec<ThemeNode> {
+        let mut nodes: Vec<ThemeNode> = themes
+            .iter()
+            .filter(|theme| theme.parent_id == parent_id)
+            .map(|theme| ThemeNode {
+                theme: theme.clone(),
+                children: children(themes, theme.id.map(|id| id.to_hex())),
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.theme.name.cmp(&b.theme.name));
+        nodes
+    }
+    children(themes, None)
+}
+
+/// It collects the ids of a theme and of all the themes below it, so filtering by a subject also
+/// finds the content of its topics and subtopics.
+pub fn theme_descendants(themes: &[ThemeModel], id: &str) -> HashSet<String> {
+    let mut by_parent: HashMap<&str, Vec<String>> = HashMap::new();
+    for theme in themes {
+        if let (Some(parent_id), Some(id)) = (&theme.parent_id, theme.id) {
+            by_parent.entry(parent_id).or_default().push(id.to_hex());
+        }
+    }
+    let mut found = HashSet::from([id.to_string()]);
+    let mut queue = vec![id.to_string()];
+    while let Some(id) = queue.pop() {
+        for child in by_parent.get(id.as_str()).into_iter().flatten() {
+            if found.insert(child.clone()) {
+                queue.push(child.clone());
+            }
+        }
+    }
+    found
+}
+
+/// It checks whether content belongs to one of the themes, by `theme_id` or, for content that
+/// was not migrated yet, by the slug of its free-text theme.
+///
+/// Arguments:
+///
+/// * `theme`: The free-text theme of the content.
+/// * `theme_id`: The theme id of the content.
+/// * `ids`: The ids of the themes.
+/// * `slugs`: The slugs of the themes.
+pub fn matches_theme(
+    theme: &str,
+    theme_id: &Option<String>,
+    ids: &HashSet<String>,
+    slugs: &HashSet<String>,
+) -> bool {
+    match theme_id {
+        Some(theme_id) => ids.contains(theme_id),
+        None => slugs.contains(&slugify(theme)),
+    }
+}
@@ -1,8 +1,13 @@
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
+use crate::models::{attempt_model::AttemptModel, theme_model::slugify};
+
 /// `UserModel` is a struct that has an `id` field of type `Option<ObjectId>`, a `username` field of
 /// type `String`, an `email` field of type `String`, a `hashed_password` field of type `String`, a
 /// `role` field of type `UserRole`, a `progress` field of type `UserProgress`, and a `friends` field of
@@ -51,17 +56,370 @@ impl FromStr for UserRole {
     }
 }
 
-/// `UserProgress` is a struct that contains a vector of strings for courses, tests, and infos.
+/// `UserProgress` is the progress of a user: the courses they are enrolled in, and the content
+/// they have completed in any course.
 /// 
 /// Properties:
 /// 
-/// * `courses`: A list of course ids that the user has completed.
+/// * `enrollments`: The courses the user has joined, with the levels passed in each.
 /// * `tests`: A list of tests that the user has completed.
 /// * `infos`: A list of info ids that the user has read.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// * `placements`: The levels the placement tests estimated, one per theme.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub struct UserProgress {
-    pub courses: Vec<String>, // id of courses
+    #[serde(default)]
+    pub enrollments: Vec<Enrollment>,
+    #[serde(default)]
     pub tests: Vec<String>, // id of tests
+    #[serde(default)]
     pub infos: Vec<String>, // id of infos
-    pub nodes: Vec<String>, // id of nodes
-}
\ No newline at end of file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub placements: Vec<Placement>,
+}
+
+/// `Placement` is the level a placement test estimated for a theme.
+///
+/// Properties:
+///
+/// * `theme`: The slug of the theme, or the free-text theme as a slug.
+/// * `theme_id`: The id of the theme if it is in the taxonomy.
+/// * `level`: The estimated level from 1 to 5.
+/// * `placed_at`: Unix timestamp of the end of the placement test.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub theme: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_id: Option<String>,
+    pub level: i32,
+    pub placed_at: i64,
+}
+
+impl Placement {
+    /// It checks whether the placement is for the theme of some content.
+    pub fn matches(&self, theme: &str, theme_id: &Option<String>) -> bool {
+        match (&self.theme_id, theme_id) {
+            (Some(placed), Some(theme_id)) => placed == theme_id,
+            _ => self.theme == slugify(theme),
+        }
+    }
+}
+
+/// `EnrollmentStatus` tells whether the user is still working through a course.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnrollmentStatus {
+    Active,
+    Completed,
+}
+
+/// `Enrollment` is a course a user has joined.
+///
+/// Properties:
+///
+/// * `course_id`: The id of the course.
+/// * `enrolled_at`: Unix timestamp of joining the course.
+/// * `status`: Whether the course is completed.
+/// * `completed_at`: Unix timestamp of passing the last level of the course.
+/// * `nodes`: The levels of the course the user has passed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Enrollment {
+    pub course_id: String,
+    pub enrolled_at: i64,
+    pub status: EnrollmentStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<i64>,
+    #[serde(default)]
+    pub nodes: Vec<NodeProgress>,
+}
+
+/// `NodeProgress` is a level of a course the user has passed.
+///
+/// Properties:
+///
+/// * `node_id`: The id of the level.
+/// * `passed_at`: Unix timestamp of passing the level.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeProgress {
+    pub node_id: String,
+    pub passed_at: i64,
+}
+
+impl Enrollment {
+    /// It creates an active enrollment without passed levels.
+    pub fn new(course_id: String, enrolled_at: i64) -> Self {
+        Self {
+            course_id,
+            enrolled_at,
+            status: EnrollmentStatus::Active,
+            completed_at: None,
+            nodes: vec![],
+        }
+    }
+
+    /// It returns the ids of the passed levels.
+    pub fn passed_nodes(&self) -> HashSet<String> {
+        self.nodes.iter().map(|node| node.node_id.clone()).collect()
+    }
+}
+
+impl UserProgress {
+    /// It finds the enrollment of the user in a course.
+    pub fn enrollment(&self, course_id: &str) -> Option<&Enrollment> {
+        self.enrollments
+            .iter()
+            .find(|enrollment| enrollment.course_id == course_id)
+    }
+
+    /// It enrolls the user in a course, joining a course twice keeps the first enrollment.
+    ///
+    /// Arguments:
+    ///
+    /// * `course_id`: The id of the course.
+    /// * `now`: Unix timestamp of joining.
+    ///
+    /// Returns:
+    ///
+    /// The enrollment.
+    pub fn enroll(&mut self, course_id: &str, now: i64) -> &mut Enrollment {
+        let index = match self
+            .enrollments
+            .iter()
+            .position(|enrollment| enrollment.course_id == course_id)
+        {
+            Some(index) => index,
+            None => {
+                self.enrollments
+                    .push(Enrollment::new(course_id.to_string(), now));
+                self.enrollments.len() - 1
+            }
+        };
+        &mut self.enrollments[index]
+    }
+
+    /// It removes the enrollment in a course with the levels passed in it.
+    pub fn leave(&mut self, course_id: &str) {
+        self.enrollments
+            .retain(|enrollment| enrollment.course_id != course_id);
+    }
+
+    /// It records a passed level, the user is enrolled in the course if they are not yet.
+    ///
+    /// Arguments:
+    ///
+    /// * `course_id`: The id of the course of the level.
+    /// * `node_id`: The id of the level.
+    /// * `now`: Unix timestamp of passing the level.
+    pub fn pass_node(&mut self, course_id: &str, node_id: &str, now: i64) {
+        let enrollment = self.enroll(course_id, now);
+        if !enrollment.nodes.iter().any(|node| node.node_id == node_id) {
+            enrollment.nodes.push(NodeProgress {
+                node_id: node_id.to_string(),
+                passed_at: now,
+            });
+        }
+    }
+
+    /// It removes a passed level from the enrollment in a course.
+    pub fn unpass_node(&mut self, course_id: &str, node_id: &str) {
+        if let Some(enrollment) = self
+            .enrollments
+            .iter_mut()
+            .find(|enrollment| enrollment.course_id == course_id)
+        {
+            enrollment.nodes.retain(|node| node.node_id != node_id);
+        }
+    }
+
+    /// It stores the result of a placement test, replacing the previous one of the theme.
+    pub fn place(&mut self, placement: Placement) {
+        self.placements
+            .retain(|placed| !placed.matches(&placement.theme, &placement.theme_id));
+        self.placements.push(placement);
+    }
+
+    /// It returns the level the placement test estimated for a theme, if the user took one.
+    pub fn placed_level(&self, theme: &str, theme_id: &Option<String>) -> Option<i32> {
+        self.placements
+            .iter()
+            .find(|placement| placement.matches(theme, theme_id))
+            .map(|placement| placement.level)
+    }
+
+    /// It marks a course as completed, the first completion time is kept.
+    pub fn complete(&mut self, course_id: &str, now: i64) {
+        let enrollment = self.enroll(course_id, now);
+        enrollment.status = EnrollmentStatus::Completed;
+        enrollment.completed_at.get_or_insert(now);
+    }
+
+    /// It checks the enrollments before they are saved.
+    ///
+    /// Arguments:
+    ///
+    /// * `course_levels`: The level ids of every existing course by course id.
+    ///
+    /// Returns:
+    ///
+    /// The problems, empty if the progress is valid.
+    pub fn validate(&self, course_levels: &HashMap<String, HashSet<String>>) -> Vec<String> {
+        let mut errors = vec![];
+        let mut seen = HashSet::new();
+        for enrollment in &self.enrollments {
+            let course_id = &enrollment.course_id;
+            if !seen.insert(course_id.as_str()) {
+                errors.push(format!("course `{}` is enrolled more than once", course_id));
+            }
+            let levels = match course_levels.get(course_id) {
+                Some(levels) => levels,
+                None => {
+                    errors.push(format!("unknown course `{}`", course_id));
+                    continue;
+                }
+            };
+            for node in &enrollment.nodes {
+                if !levels.contains(&node.node_id) {
+                    errors.push(format!(
+                        "course `{}`: unknown node `{}`",
+                        course_id, node.node_id
+                    ));
+                }
+            }
+            if (enrollment.status == EnrollmentStatus::Completed) != enrollment.completed_at.is_some()
+            {
+                errors.push(format!(
+                    "course `{}`: completed_at must be set exactly when the status is completed",
+                    course_id
+                ));
+            }
+        }
+        errors
+    }
+
+    /// It adds the progress stored before enrollments existed. The flat list of passed levels is
+    /// split by course, a level that is in no course is dropped. What the progress already has is
+    /// kept.
+    ///
+    /// Arguments:
+    ///
+    /// * `legacy`: The old progress.
+    /// * `node_courses`: The course id of every level id.
+    /// * `now`: Unix timestamp used as the enrollment and completion time.
+    ///
+    /// Returns:
+    ///
+    /// The ids of the dropped levels.
+    pub fn merge_legacy(
+        &mut self,
+        legacy: LegacyUserProgress,
+        node_courses: &HashMap<String, String>,
+        now: i64,
+    ) -> Vec<String> {
+        for id in legacy.tests {
+            if !self.tests.contains(&id) {
+                self.tests.push(id);
+            }
+        }
+        for id in legacy.infos {
+            if !self.infos.contains(&id) {
+                self.infos.push(id);
+            }
+        }
+        for course_id in &legacy.courses {
+            self.enroll(course_id, now);
+        }
+        let mut dropped = vec![];
+        for node_id in legacy.nodes {
+            match node_courses.get(&node_id) {
+                Some(course_id) => self.pass_node(course_id, &node_id, now),
+                None => dropped.push(node_id),
+            }
+        }
+        for course_id in &legacy.completed_courses {
+            self.complete(course_id, now);
+        }
+        dropped
+    }
+}
+
+/// The keys only the progress stored before enrollments has.
+pub const LEGACY_PROGRESS_KEYS: [&str; 3] = ["courses", "nodes", "completed_courses"];
+
+/// `LegacyUserProgress` is the progress as it was stored before enrollments: flat lists of ids,
+/// where `courses` are the joined courses and `nodes` mixes the passed levels of all courses.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LegacyUserProgress {
+    #[serde(default)]
+    pub courses: Vec<String>,
+    #[serde(default)]
+    pub tests: Vec<String>,
+    #[serde(default)]
+    pub infos: Vec<String>,
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(default)]
+    pub completed_courses: Vec<String>,
+}
+
+/// `LearnerProgress` is what the server knows about the progress of a user, from the stored
+/// progress and the graded attempts.
+///
+/// Properties:
+///
+/// * `completed`: The ids of the passed levels of one course.
+/// * `started`: The ids of the infos and tests the user has opened or answered.
+/// * `passed`: The ids of the tests and templates the user has answered correctly.
+/// * `read`: The ids of the infos the user has read.
+/// * `last_activity`: Unix timestamp of the last attempt of the user by test or template id.
+/// * `unlocked`: The ids of the levels the placement tests of the user unlock, see
+///   `CourseModel::placement_unlocked`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LearnerProgress {
+    pub completed: HashSet<String>,
+    pub started: HashSet<String>,
+    pub passed: HashSet<String>,
+    pub read: HashSet<String>,
+    pub last_activity: HashMap<String, i64>,
+    pub unlocked: HashSet<String>,
+}
+
+impl LearnerProgress {
+    /// It collects the progress of a user.
+    ///
+    /// Arguments:
+    ///
+    /// * `progress`: The stored progress of the user.
+    /// * `attempts`: The attempts of the user.
+    /// * `course_id`: The course whose passed levels are collected.
+    ///
+    /// Returns:
+    ///
+    /// A LearnerProgress
+    pub fn new(progress: &UserProgress, attempts: &[AttemptModel], course_id: &str) -> Self {
+        Self {
+            completed: progress
+                .enrollment(course_id)
+                .map(Enrollment::passed_nodes)
+                .unwrap_or_default(),
+            started: progress
+                .tests
+                .iter()
+                .chain(&progress.infos)
+                .chain(attempts.iter().map(|attempt| &attempt.test_id))
+                .cloned()
+                .collect(),
+            passed: attempts
+                .iter()
+                .filter(|attempt| attempt.correct == Some(true))
+                .map(|attempt| attempt.test_id.clone())
+                .collect(),
+            read: progress.infos.iter().cloned().collect(),
+            last_activity: attempts.iter().fold(HashMap::new(), |mut activity, attempt| {
+                let time = attempt.answered_at.unwrap_or(attempt.started_at);
+                let last = activity.entry(attempt.test_id.clone()).or_insert(time);
+                *last = (*last).max(time);
+                activity
+            }),
+            unlocked: HashSet::new(),
+        }
+    }
+}
@@ -2,6 +2,7 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
+use std::collections::HashMap;
 use std::env;
 extern crate dotenv;
 use dotenv::dotenv;
@@ -113,6 +114,22 @@ impl CourceRepo {
         Some(cources)
     }
 
+    /// It maps the id of every level to the id of its course.
+    ///
+    /// Returns:
+    ///
+    /// The course id by level id.
+    pub async fn get_level_courses(&self) -> HashMap<String, String> {
+        let mut level_courses = HashMap::new();
+        for cource in self.get_all().await.unwrap_or_default() {
+            let cource_id = cource.id.map(|id| id.to_hex()).unwrap_or_default();
+            for level in cource.levels.values().flatten() {
+                level_courses.insert(level.id.clone(), cource_id.clone());
+            }
+        }
+        level_courses
+    }
+
     /// It takes a string as an argument, and returns an option of a string
     ///
     /// Arguments:
@@ -278,11 +295,13 @@ impl CourceRepo {
 
 #[cfg(test)]
 mod cource_repo_tests {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     use super::*;
+    use crate::models::bundle_model::*;
     use crate::models::cource_model::*;
     use crate::models::info_model::*;
+    use crate::models::publication_model::ContentKind;
     use crate::models::tests_model::*;
 
     use crate::repository::infos_repo::*;
@@ -336,14 +355,36 @@ mod cource_repo_tests {
         ObjectId::parse_str(id).unwrap()
     }
 
+    fn level(ids: &[&str], n_of_tests: Option<i32>, type_: &str) -> Level {
+        Level::new(
+            ids.iter().map(|id| id.to_string()).collect(),
+            "Level title".to_owned(),
+            String::new(),
+            String::new(),
+            n_of_tests,
+            type_.to_owned(),
+        )
+        .0
+    }
+
+    fn course(levels: HashMap<i32, Vec<Level>>) -> CourseModel {
+        CourseModel {
+            id: None,
+            title: "Cource title".to_string(),
+            description: "Cource description".to_string(),
+            levels,
+            translations: HashMap::new(),
+            origin: None,
+        }
+    }
+
     async fn gen_n_cource(n: i32) -> Vec<CourseModel> {
         let mut cource_vec = Vec::new();
         for i in 0..n {
             let cource = CourseModel {
-                id: None,
                 title: format!("Cource title {}", i),
                 description: format!("Cource description {}", i),
-                levels: HashMap::new(),
+                ..course(HashMap::new())
             };
             cource_vec.push(cource);
         }
@@ -354,12 +395,7 @@ mod cource_repo_tests {
     async fn create_test() {
         let cource_repo = setup_cource_db(true).await;
 
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(&cource.title).await;
@@ -380,12 +416,7 @@ mod cource_repo_tests {
     async fn get_test() {
         let cource_repo = setup_cource_db(true).await;
 
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(&cource.title).await;
@@ -405,12 +436,7 @@ mod cource_repo_tests {
     #[tokio::test]
     async fn update_test() {
         let cource_repo = setup_cource_db(true).await;
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(&cource.title).await;
@@ -419,7 +445,7 @@ mod cource_repo_tests {
             id: Some(str2oid(&cource_id.clone().unwrap()).await),
             title: "New cource title".to_string(),
             description: "New cource description".to_string(),
-            levels: HashMap::new(),
+            ..course(HashMap::new())
         };
 
         cource_repo
@@ -440,12 +466,7 @@ mod cource_repo_tests {
     #[tokio::test]
     async fn delete_test() {
         let cource_repo = setup_cource_db(true).await;
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
 
         cource_repo.create(cource.clone()).await;
 
@@ -481,12 +502,7 @@ mod cource_repo_tests {
     #[tokio::test]
     async fn add_level_test() {
         let cource_repo = setup_cource_db(true).await;
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(cource.title.as_str()).await;
@@ -494,17 +510,26 @@ mod cource_repo_tests {
         let info = InfoModel {
             id: None,
             theme: "Info theme".to_string(),
+            theme_id: None,
+            skills: vec![],
             title: "Info title".to_string(),
             content_levels: HashMap::new(),
+            translations: HashMap::new(),
         };
         let info_id = create_info_in_db(info).await;
         let test = TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answer: "2".to_string(),
             answers: vec!["3".to_string(), "4".to_string()],
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         let test_id = create_test_in_db(test).await;
 
@@ -553,12 +578,7 @@ mod cource_repo_tests {
     #[tokio::test]
     async fn delete_level_test() {
         let cource_repo = setup_cource_db(true).await;
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(&cource.title).await;
@@ -566,17 +586,26 @@ mod cource_repo_tests {
         let info = InfoModel {
             id: None,
             theme: "Info theme".to_string(),
+            theme_id: None,
+            skills: vec![],
             title: "Info title".to_string(),
             content_levels: HashMap::new(),
+            translations: HashMap::new(),
         };
         let info_id = create_info_in_db(info).await;
         let test = TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answer: "2".to_string(),
             answers: vec!["3".to_string(), "4".to_string()],
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         let test_id = create_test_in_db(test).await;
 
@@ -631,12 +660,7 @@ mod cource_repo_tests {
     #[tokio::test]
     async fn update_level_test() {
         let cource_repo = setup_cource_db(true).await;
-        let cource = CourseModel {
-            id: None,
-            title: "Cource title".to_string(),
-            description: "Cource description".to_string(),
-            levels: HashMap::new(),
-        };
+        let cource = course(HashMap::new());
         cource_repo.create(cource.clone()).await;
 
         let cource_id = cource_repo.get_cource_id(&cource.title).await;
@@ -644,17 +668,26 @@ mod cource_repo_tests {
         let info = InfoModel {
             id: None,
             theme: "Info theme".to_string(),
+            theme_id: None,
+            skills: vec![],
             title: "Info title".to_string(),
             content_levels: HashMap::new(),
+            translations: HashMap::new(),
         };
         let info_id = create_info_in_db(info).await;
         let test = TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answer: "2".to_string(),
             answers: vec!["3".to_string(), "4".to_string()],
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         let test_id = create_test_in_db(test).await;
 
@@ -719,4 +752,376 @@ mod cource_repo_tests {
             "New Level title"
         );
     }
+
+    #[test]
+    fn validate_level_graph() {
+        let info = level(&["info"], None, "info");
+        let mut cource = course(HashMap::from([
+            (1, vec![info.copy(), level(&["test"], Some(1), "test")]),
+            (2, vec![level(&["test", "info"], Some(2), "test")]),
+        ]));
+        let content = HashMap::from([
+            ("info".to_string(), "info".to_string()),
+            ("test".to_string(), "test".to_string()),
+        ]);
+        let report = cource.validate(&content);
+        assert_eq!(report.type_mismatches.len(), 1);
+        assert_eq!(report.type_mismatches[0].layer, 2);
+        assert!(!report.is_valid());
+
+        cource.levels.get_mut(&2).unwrap()[0].ids.pop();
+        assert!(cource.validate(&content).test_count_mismatches.len() == 1);
+        cource.levels.get_mut(&2).unwrap()[0].n_of_tests = Some(1);
+        assert_eq!(cource.validate(&content), CourseReport::default());
+
+        cource
+            .levels
+            .insert(4, vec![info.copy(), level(&["gone"], None, "quiz")]);
+        cource.levels.insert(5, vec![]);
+        let report = cource.validate(&content);
+        assert_eq!(report.layer_gaps, vec![3]);
+        assert_eq!(report.empty_layers, vec![5]);
+        assert_eq!(report.duplicate_level_ids, vec![info.id.clone()]);
+        assert_eq!(report.dangling_ids[0].detail, "gone");
+        assert_eq!(report.type_mismatches.len(), 1);
+    }
+
+    #[test]
+    fn prerequisite_graph() {
+        let (a1, a2) = (level(&[], None, "info"), level(&[], None, "info"));
+        let mut b = level(&[], None, "info");
+        b.prerequisites = Some(vec![a1.id.clone()]);
+        let c = level(&[], None, "info");
+        let mut cource = course(HashMap::from([
+            (1, vec![a1.copy(), a2.copy()]),
+            (3, vec![b.copy()]),
+            (4, vec![c.copy()]),
+        ]));
+        let graph = cource.prerequisite_graph();
+        assert!(graph[&a1.id].is_empty());
+        assert_eq!(graph[&b.id], vec![a1.id.clone()]);
+        assert_eq!(graph[&c.id], vec![b.id.clone()]);
+        assert!(cource.prerequisite_cycles().is_empty());
+
+        b.prerequisites = Some(vec![c.id.clone(), "gone".to_string()]);
+        cource.levels.insert(3, vec![b.copy()]);
+        let report = cource.validate(&HashMap::new());
+        assert_eq!(report.unknown_prerequisites[0].detail, "gone");
+        assert_eq!(report.cycles.len(), 1);
+        assert_eq!(report.cycles[0].len(), 3);
+        assert_eq!(report.cycles[0].first(), report.cycles[0].last());
+    }
+
+    #[test]
+    fn node_states_follow_prerequisites() {
+        let (a, b, c) = (
+            level(&["a"], None, "info"),
+            level(&["b"], None, "info"),
+            level(&["c"], None, "info"),
+        );
+        let cource = course(HashMap::from([
+            (1, vec![a.copy()]),
+            (2, vec![b.copy()]),
+            (3, vec![c.copy()]),
+        ]));
+        let completed = HashSet::from([a.id.clone()]);
+        let started = HashSet::from(["b".to_string(), "c".to_string()]);
+        let none = HashSet::new();
+        let states = cource.node_states(&completed, &started, &none);
+        assert_eq!(states[&a.id], NodeState::Completed);
+        assert_eq!(states[&b.id], NodeState::InProgress);
+        assert_eq!(states[&c.id], NodeState::Locked);
+        assert_eq!(
+            cource.node_states(&completed, &none, &none)[&b.id],
+            NodeState::Available
+        );
+        // a level unlocked by a placement test is open before its prerequisites are passed
+        let unlocked = HashSet::from([c.id.clone()]);
+        assert_eq!(
+            cource.node_states(&completed, &started, &unlocked)[&c.id],
+            NodeState::InProgress
+        );
+        // only test levels are unlocked by placement tests
+        assert!(cource.placement_unlocked(&started).is_empty());
+
+        let map = cource.map(&completed, &started, &none);
+        assert_eq!(map.layers.len(), 3);
+        assert_eq!(map.layers[2].1[0].state, NodeState::Locked);
+        assert_eq!(map.layers[2].1[0].effective_prerequisites, vec![b.id]);
+    }
+
+    #[test]
+    fn cource_progress() {
+        use crate::models::attempt_model::AttemptModel;
+        use crate::models::user_model::{LearnerProgress, UserProgress};
+
+        let (info, test) = (
+            level(&["i1"], None, "info"),
+            level(&["t1", "t2"], None, "test"),
+        );
+        let cource = course(HashMap::from([
+            (1, vec![info.copy()]),
+            (2, vec![test.copy()]),
+        ]));
+        let mut progress = LearnerProgress {
+            completed: HashSet::from([info.id.clone()]),
+            passed: HashSet::from(["t1".to_string(), "other".to_string()]),
+            read: HashSet::from(["i1".to_string()]),
+            last_activity: HashMap::from([("t1".to_string(), 10), ("t2".to_string(), 20)]),
+            ..Default::default()
+        };
+        let report = cource.progress(&progress);
+        assert_eq!((report.completed_nodes, report.total_nodes), (1, 2));
+        assert_eq!((report.completed_tests, report.total_tests), (1, 2));
+        assert_eq!((report.completed_infos, report.total_infos), (1, 1));
+        assert_eq!(report.current_layer, Some(2));
+        assert_eq!(report.last_activity, Some(20));
+        assert!(!report.completed);
+
+        progress.completed.insert(test.id.clone());
+        let report = cource.progress(&progress);
+        assert_eq!(report.current_layer, None);
+        assert!(report.completed);
+
+        // the tests a client wrote into the progress do not count, only graded attempts
+        let stored = UserProgress {
+            tests: vec!["t1".to_string(), "t2".to_string()],
+            ..Default::default()
+        };
+        let progress = LearnerProgress::new(&stored, &[], "cource");
+        assert_eq!(cource.progress(&progress).completed_tests, 0);
+        let attempts = [
+            AttemptModel::legacy_pass("user".to_string(), "t1".to_string(), 10),
+            AttemptModel::legacy_pass("user".to_string(), "t2".to_string(), 10),
+        ];
+        let progress = LearnerProgress::new(&stored, &attempts, "cource");
+        assert_eq!(cource.progress(&progress).completed_tests, 2);
+    }
+
+    fn gen_bundle() -> CourseBundle {
+        let (info_id, test_id) = (ObjectId::new(), ObjectId::new());
+        let first = level(&[&info_id.to_hex()], None, "info");
+        let mut second = level(&[&test_id.to_hex()], Some(1), "test");
+        second.prerequisites = Some(vec![first.id.clone()]);
+        let mut bundle = CourseBundle::new(CourseModel {
+            id: Some(ObjectId::new()),
+            ..course(HashMap::from([(1, vec![first]), (2, vec![second])]))
+        });
+        bundle.infos.push(InfoModel {
+            id: Some(info_id),
+            title: "Info title".to_string(),
+            theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
+            content_levels: HashMap::new(),
+            translations: HashMap::new(),
+        });
+        bundle.choice_tests.push(TestModel {
+            id: Some(test_id),
+            theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
+            question: "1 + 1".to_string(),
+            answers: vec!["2".to_string(), "3".to_string()],
+            answer: "2".to_string(),
+            level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: HashMap::new(),
+        });
+        bundle
+    }
+
+    #[test]
+    fn course_bundle_import() {
+        let bundle = gen_bundle();
+        let (info_id, test_id) = (
+            bundle.infos[0].id.unwrap(),
+            bundle.choice_tests[0].id.unwrap(),
+        );
+        let first = &bundle.course.levels[&1][0];
+        let without_tests = CourseBundle {
+            choice_tests: vec![],
+            ..bundle.clone()
+        };
+        assert_eq!(without_tests.validate().len(), 1);
+        assert!(bundle.validate().is_empty());
+        assert_eq!(
+            CourseBundle::from_zip(&bundle.to_zip().unwrap()).unwrap(),
+            bundle
+        );
+
+        // the info and the course exist, the test does not
+        let existing = HashSet::from([
+            (ContentKind::Info, info_id.to_hex()),
+            (ContentKind::Course, bundle.course.id.unwrap().to_hex()),
+        ]);
+        let items = bundle.plan(&existing, ConflictStrategy::Skip);
+        let outcomes: Vec<ImportOutcome> = items.iter().map(|item| item.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ImportOutcome::Skip,
+                ImportOutcome::Create,
+                ImportOutcome::Skip
+            ]
+        );
+
+        let items = bundle.plan(&existing, ConflictStrategy::Duplicate);
+        assert_eq!(items[1].target_id, test_id.to_hex());
+        assert_ne!(items[0].target_id, info_id.to_hex());
+        let documents = bundle.remap(&items);
+        let course: CourseModel = mongodb::bson::from_document(documents[2].1.clone()).unwrap();
+        assert_eq!(course.id.unwrap().to_hex(), items[2].target_id);
+        let (new_first, new_second) = (&course.levels[&1][0], &course.levels[&2][0]);
+        assert_eq!(new_first.ids, vec![items[0].target_id.clone()]);
+        assert_eq!(new_second.ids, vec![test_id.to_hex()]);
+        // a duplicated course gets its own level ids
+        assert_ne!(new_first.id, first.id);
+        assert_eq!(new_second.prerequisites, Some(vec![new_first.id.clone()]));
+    }
+
+    #[test]
+    fn course_clone() {
+        let bundle = gen_bundle();
+        let source_id = bundle.course.id.unwrap().to_hex();
+        let level_ids: HashSet<String> = bundle
+            .course
+            .levels
+            .values()
+            .flatten()
+            .map(|level| level.id.clone())
+            .collect();
+
+        // a shallow clone shares the content of the source
+        let documents = bundle.fork(false);
+        assert_eq!(documents.len(), 1);
+        let clone: CourseModel = mongodb::bson::from_document(documents[0].1.clone()).unwrap();
+        assert_ne!(clone.id.unwrap().to_hex(), source_id);
+        assert_eq!(clone.content_ids(), bundle.course.content_ids());
+        let origin = clone.origin.clone().unwrap();
+        assert_eq!(
+            (origin.course_id.as_str(), origin.deep),
+            (source_id.as_str(), false)
+        );
+        assert!(origin.content.is_empty());
+        assert_eq!(
+            origin.levels.values().cloned().collect::<HashSet<String>>(),
+            level_ids
+        );
+        assert!(origin.levels.keys().all(|id| !level_ids.contains(id)));
+
+        // a deep clone points at copies of the content
+        let documents = bundle.fork(true);
+        assert_eq!(documents.len(), 3);
+        assert!(documents
+            .iter()
+            .all(|(item, _)| item.source_id != item.target_id));
+        let clone: CourseModel = mongodb::bson::from_document(documents[2].1.clone()).unwrap();
+        let origin = clone.origin.clone().unwrap();
+        assert!(origin.deep);
+        for id in clone.content_ids() {
+            assert!(bundle.course.content_ids().contains(&origin.content[&id]));
+        }
+        let second = &clone.levels[&2][0];
+        assert_eq!(
+            second.prerequisites,
+            Some(vec![clone.levels[&1][0].id.clone()])
+        );
+        assert_eq!(documents[2].0.kind, ContentKind::Course);
+    }
+
+    #[test]
+    fn course_scorm_package() {
+        use crate::models::media_model::MediaModel;
+        use crate::models::scorm_model::*;
+
+        let mut bundle = gen_bundle();
+        let media = MediaModel {
+            id: Some(ObjectId::new()),
+            storage_key: "key".to_string(),
+            content_type: "image/png".to_string(),
+            size: 4,
+            file_name: None,
+            uploaded_at: 0,
+            renditions: vec![],
+        };
+        bundle.infos[0].content_levels = HashMap::from([(
+            1,
+            vec![
+                ContentLevel::Text {
+                    data: "Adding <numbers>".to_string(),
+                },
+                ContentLevel::Image {
+                    url: media.url(),
+                    alt: "sum".to_string(),
+                },
+            ],
+        )]);
+        let action_id = ObjectId::new();
+        bundle.action_tests.push(TestModelWithActions {
+            id: Some(action_id),
+            theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
+            question: "2 * 2 + 2".to_string(),
+            answers: vec!["2 * 2".to_string(), "4 + 2".to_string()],
+            answer: "6".to_string(),
+            level: 1,
+            hints: vec![],
+            explanation: vec![ContentLevel::Text {
+                data: "multiply first".to_string(),
+            }],
+            hint_penalty: None,
+            translations: HashMap::new(),
+        });
+        bundle.course.levels.get_mut(&2).unwrap()[0]
+            .ids
+            .push(action_id.to_hex());
+
+        let files = vec![(media.clone(), b"\x89PNG".to_vec())];
+        let package = ScormPackage::new(&bundle, &files, 70, 1).unwrap();
+        assert_eq!(package.questions, 2);
+        let path = format!("media/{}.png", media.id.unwrap().to_hex());
+        assert!(package.page.contains(&format!("src=\"{}\"", path)));
+        assert!(!package.page.contains(&media.url()));
+        assert!(package.page.contains("Adding &lt;numbers&gt;"));
+        // the choice answer is its index, the action answer is typed
+        assert!(package
+            .page
+            .contains("data-kind=\"choice\" data-answer=\"0\""));
+        assert!(package
+            .page
+            .contains("data-kind=\"text\" data-answer=\"6\""));
+        assert!(!package.page.contains("4 + 2"));
+        assert!(package.page.contains("data-mastery-score=\"70\""));
+        assert!(package
+            .manifest
+            .contains("<adlcp:masteryscore>70</adlcp:masteryscore>"));
+        assert!(package
+            .manifest
+            .contains(&format!("<file href=\"{}\"/>", path)));
+
+        let mut archive =
+            zip::ZipArchive::new(std::io::Cursor::new(package.to_zip().unwrap())).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "imsmanifest.xml",
+                "index.html",
+                path.as_str(),
+                SCORM_RUNTIME_FILE
+            ]
+        );
+        assert_eq!(archive.by_name(&path).unwrap().size(), 4);
+
+        // content that can not be rendered is reported
+        bundle.choice_tests[0].answer = "4".to_string();
+        let errors = ScormPackage::new(&bundle, &files, 70, 1).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("not among the answers"));
+    }
 }
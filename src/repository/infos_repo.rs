@@ -4,10 +4,10 @@ extern crate dotenv;
 use dotenv::dotenv;
 use rocket::futures::TryStreamExt;
 
-use crate::models::info_model::{InfoModel};
+use crate::models::info_model::{ContentLevel, InfoModel};
 
 use mongodb::{
-    bson::{doc, oid::ObjectId, extjson::de::Error},
+    bson::{doc, oid::ObjectId, extjson::de::Error, Bson, Document},
     options::UpdateModifications,
     results::{DeleteResult, InsertOneResult, UpdateResult},
     Client, Collection, Cursor
@@ -16,6 +16,22 @@ use base64::{
     Engine as _,
     engine::{general_purpose}
 };
+use serde::Serialize;
+
+/// `ContentMigrationReport` is the result of `InfosRepo::migrate_content_levels`.
+///
+/// Properties:
+///
+/// * `migrated_infos`: The ids of the infos that were rewritten.
+/// * `unknown_types`: Content types that were not recognized and were turned into text.
+/// * `invalid_blocks`: Migrated blocks that do not pass `ContentLevel::validate`, with the info,
+///   the level and the block, they must be fixed before the info can be saved again.
+#[derive(Debug, Default, Serialize)]
+pub struct ContentMigrationReport {
+    pub migrated_infos: Vec<String>,
+    pub unknown_types: Vec<String>,
+    pub invalid_blocks: Vec<String>,
+}
 
 pub struct InfosRepo {
     collection: Collection<InfoModel>,
@@ -145,6 +161,71 @@ impl InfosRepo {
         Ok(result.unwrap())
     }
 
+    /// It rewrites the content blocks stored in the old `{content_type, data}` shape into typed
+    /// blocks. Blocks that are already typed are left as they are, so the migration can be run
+    /// more than once.
+    ///
+    /// Returns:
+    ///
+    /// A ContentMigrationReport
+    pub async fn migrate_content_levels(&self) -> Result<ContentMigrationReport, Error> {
+        let raw = self.collection.clone_with_type::<Document>();
+        let mut cursor = raw.find(None, None).await.unwrap();
+        let mut report = ContentMigrationReport::default();
+
+        while let Some(mut info) = cursor.try_next().await.unwrap() {
+            let id = info.get_object_id("_id").unwrap();
+            let mut changed = false;
+            // content_levels is stored as a list of [level, blocks] pairs
+            if let Ok(levels) = info.get_array_mut("content_levels") {
+                for level in levels.iter_mut() {
+                    let (number, blocks) = match level {
+                        Bson::Array(pair) if pair.len() == 2 => {
+                            let (number, blocks) = pair.split_at_mut(1);
+                            (number[0].to_string(), &mut blocks[0])
+                        }
+                        _ => continue,
+                    };
+                    let blocks = match blocks {
+                        Bson::Array(blocks) => blocks,
+                        _ => continue,
+                    };
+                    for (index, block) in blocks.iter_mut().enumerate() {
+                        let doc = match block {
+                            Bson::Document(doc) => doc,
+                            _ => continue,
+                        };
+                        if mongodb::bson::from_document::<ContentLevel>(doc.clone()).is_ok() {
+                            continue;
+                        }
+                        let content_type = doc.get_str("content_type").unwrap_or("").to_string();
+                        let data = doc.get_str("data").unwrap_or("").to_string();
+                        let (typed, known) = ContentLevel::from_legacy(&content_type, data);
+                        if !known && !report.unknown_types.contains(&content_type) {
+                            report.unknown_types.push(content_type);
+                        }
+                        if let Err(e) = typed.validate() {
+                            report.invalid_blocks.push(format!(
+                                "info {}, level {}, block {}: {}",
+                                id.to_hex(),
+                                number,
+                                index + 1,
+                                e
+                            ));
+                        }
+                        *block = mongodb::bson::to_bson(&typed).unwrap();
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                raw.replace_one(doc! {"_id": id}, &info, None).await.unwrap();
+                report.migrated_infos.push(id.to_hex());
+            }
+        }
+        Ok(report)
+    }
+
     /// It encodes the data into a string.
     /// 
     /// Arguments:
@@ -216,8 +297,7 @@ mod info_repo_tests {
         // gen HashMap with content levels
         let mut content_levels: HashMap<i32, Vec<ContentLevel>> = HashMap::new();
         for i in 0..3 {
-            contents.push(ContentLevel {
-                content_type: "text".to_string(),
+            contents.push(ContentLevel::Text {
                 data: "test".to_owned(),
             });
             content_levels.insert(i, contents.clone());
@@ -225,8 +305,11 @@ mod info_repo_tests {
         InfoModel {
             id: None,
             theme: "test".to_string(),
+            theme_id: None,
+            skills: vec![],
             title: "test".to_string(),
             content_levels,
+            translations: HashMap::new(),
         }
     }
 
@@ -305,8 +388,11 @@ mod info_repo_tests {
         let new_info = InfoModel {
             id: ObjectId::parse_str(info_id.clone().as_str()).ok(),
             theme: "test2".to_string(),
+            theme_id: None,
+            skills: vec![],
             title: "test2".to_string(),
             content_levels: info.content_levels,
+            translations: HashMap::new(),
         };
 
         let result = client.update_info(&info_id, new_info).await;
@@ -322,6 +408,96 @@ mod info_repo_tests {
         }
     }
 
+    #[test]
+    fn content_level_from_legacy() {
+        let (block, known) = ContentLevel::from_legacy("Image", "https://img".to_string());
+        assert!(known);
+        assert_eq!(
+            block,
+            ContentLevel::Image {
+                url: "https://img".to_string(),
+                alt: String::new()
+            }
+        );
+
+        let (block, known) = ContentLevel::from_legacy("code:rust", "fn main() {}".to_string());
+        assert!(known);
+        assert!(matches!(block, ContentLevel::Code { lang, .. } if lang == "rust"));
+
+        let (block, known) = ContentLevel::from_legacy("txet", "typo".to_string());
+        assert!(!known);
+        assert!(matches!(block, ContentLevel::Text { .. }));
+
+        // old code blocks had no language and old images were IPFS hashes
+        let (block, _) = ContentLevel::from_legacy("code", "x = 1".to_string());
+        assert!(matches!(&block, ContentLevel::Code { lang, .. } if lang == "text"));
+        assert!(block.validate().is_ok());
+        let (block, _) = ContentLevel::from_legacy("image", "QmHash".to_string());
+        assert!(matches!(&block, ContentLevel::Image { url, .. } if url == "ipfs://QmHash"));
+        assert!(block.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_info() {
+        let mut info = InfoModel {
+            id: None,
+            theme: "test".to_string(),
+            theme_id: None,
+            skills: vec![],
+            title: "test".to_string(),
+            content_levels: HashMap::new(),
+            translations: HashMap::new(),
+        };
+        info.content_levels.insert(
+            1,
+            vec![
+                ContentLevel::Text {
+                    data: "test".to_string(),
+                },
+                ContentLevel::Image {
+                    url: "ipfs-hash".to_string(),
+                    alt: String::new(),
+                },
+            ],
+        );
+        assert_eq!(info.validate().len(), 1);
+        assert!(info.validate()[0].starts_with("level 1, block 2"));
+    }
+
+    #[tokio::test]
+    async fn migrate_content_levels() {
+        let client = setup(true).await;
+        let raw = client.collection.clone_with_type::<Document>();
+        raw.insert_one(
+            doc! {
+                "title": "legacy",
+                "theme": "test",
+                "content_levels": [[0, [
+                    {"content_type": "text", "data": "hello"},
+                    {"content_type": "image", "data": "https://img"},
+                    {"content_type": "markdown", "data": ""},
+                ]]],
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        let report = client.migrate_content_levels().await.unwrap();
+        assert_eq!(report.migrated_infos.len(), 1);
+        assert_eq!(report.invalid_blocks.len(), 1);
+        assert!(report.invalid_blocks[0].ends_with("level 0, block 3: data is empty"));
+        let info = client
+            .get_info_by_title(&"legacy".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(info.content_levels[&0][1], ContentLevel::Image { .. }));
+
+        let report = client.migrate_content_levels().await.unwrap();
+        assert!(report.migrated_infos.is_empty());
+    }
+
     #[tokio::test]
     async fn delete_info() {
         let client = setup(true).await;
@@ -0,0 +1,142 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use rocket::tokio::fs;
+
+use mongodb::{
+    bson::Bson,
+    error::{ErrorKind as MongoErrorKind, GridFsErrorKind},
+    gridfs::GridFsBucket,
+    Database,
+};
+
+/// `MediaStorage` keeps the bytes of uploaded media. The metadata is stored by `MediaRepo`, so a
+/// backend only has to map a key to bytes.
+#[rocket::async_trait]
+pub trait MediaStorage: Send + Sync {
+    /// It stores the bytes under the key, replacing what was stored before.
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+
+    /// It reads the bytes stored under the key, None if there are none.
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// It removes the bytes stored under the key. Removing a missing key is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+fn to_io_error(error: mongodb::error::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+fn is_file_not_found(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        MongoErrorKind::GridFs {
+            0: GridFsErrorKind::FileNotFound { .. },
+            ..
+        }
+    )
+}
+
+/// `GridFsStorage` stores media in the `media` GridFS bucket of the database, the key is used as
+/// the id of the GridFS file.
+pub struct GridFsStorage {
+    bucket: GridFsBucket,
+}
+
+impl GridFsStorage {
+    pub fn new(database: &Database) -> Self {
+        let options = mongodb::options::GridFsBucketOptions::builder()
+            .bucket_name("media".to_string())
+            .build();
+        GridFsStorage {
+            bucket: database.gridfs_bucket(options),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStorage for GridFsStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.delete(key).await?;
+        self.bucket
+            .upload_from_futures_0_3_reader_with_id(Bson::String(key.to_string()), key, data, None)
+            .await
+            .map_err(to_io_error)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        match self
+            .bucket
+            .download_to_futures_0_3_writer(Bson::String(key.to_string()), &mut data)
+            .await
+        {
+            Ok(()) => Ok(Some(data)),
+            Err(error) if is_file_not_found(&error) => Ok(None),
+            Err(error) => Err(to_io_error(error)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match self.bucket.delete(Bson::String(key.to_string())).await {
+            Err(error) if !is_file_not_found(&error) => Err(to_io_error(error)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `LocalStorage` stores every key as a file in a directory.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    /// Keys are generated by the server, but they are still checked so that a key can never
+    /// point outside of the directory.
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let valid = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !key.starts_with('.');
+        if valid {
+            Ok(self.root.join(key))
+        } else {
+            Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid media key `{}`", key),
+            ))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStorage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        fs::create_dir_all(&self.root).await?;
+        fs::write(path, data).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
@@ -2,4 +2,16 @@ pub mod user_repo;
 pub mod tests_repo;
 pub mod tests_with_actions_repo;
 pub mod infos_repo;
-pub mod cource_repo;
\ No newline at end of file
+pub mod cource_repo;
+pub mod templates_repo;
+pub mod attempts_repo;
+pub mod media_storage;
+pub mod media_repo;
+pub mod publications_repo;
+pub mod revisions_repo;
+pub mod themes_repo;
+pub mod skills_repo;
+pub mod ratings_repo;
+pub mod reviews_repo;
+pub mod placements_repo;
+pub mod quizzes_repo;
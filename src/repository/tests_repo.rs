@@ -74,6 +74,20 @@ impl TestsRepo {
         Ok(test.unwrap())
     }
 
+    /// It finds a test by the text of its question, so a question is not added twice.
+    ///
+    /// Arguments:
+    ///
+    /// * `question`: &str - The text of the question
+    ///
+    /// Returns:
+    ///
+    /// A Result<Option<Test>, TestsError>
+    pub async fn get_test_by_question(&self, question: &str) -> Result<Option<Test>, Error> {
+        let test = self.collection.find_one(doc! {"question": question}, None).await;
+        Ok(test.unwrap())
+    }
+
     /// It deletes a test from the database.
     /// 
     /// Arguments:
@@ -156,6 +170,8 @@ mod test_repo_tests {
         TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answers: vec!["2", "3", "4"]
                 .into_iter()
@@ -163,9 +179,47 @@ mod test_repo_tests {
                 .collect(),
             answer: "2".to_string(),
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         }
     }
 
+    #[tokio::test]
+    async fn localize_and_grade_in_any_locale() {
+        use crate::models::tests_model::{canonical_answer, TestTranslation};
+        use crate::utils::locale::{Localize, Locales};
+
+        let mut test = gen_test().await;
+        test.question = "Is 1 + 1 even?".to_string();
+        test.answers = vec!["yes".to_string(), "no".to_string()];
+        test.answer = "yes".to_string();
+        test.translations.insert(
+            "de".to_string(),
+            TestTranslation {
+                question: Some("Ist 1 + 1 gerade?".to_string()),
+                answers: Some(vec!["ja".to_string(), "nein".to_string()]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(test.missing_translations("de"), Vec::<String>::new());
+        assert_eq!(test.missing_translations("fr"), vec!["question", "answers"]);
+
+        let answers = test.answers.clone();
+        let translations = test.translations.clone();
+        assert_eq!(canonical_answer(&answers, &translations, " ja "), "yes");
+        assert_eq!(canonical_answer(&answers, &translations, "nein"), "no");
+        assert_eq!(canonical_answer(&answers, &translations, "yes"), "yes");
+
+        let locale = test.localize(&Locales::parse(None, Some("de-CH, en;q=0.5")));
+        assert_eq!(locale, "de");
+        assert_eq!(test.question, "Ist 1 + 1 gerade?");
+        assert_eq!(test.answers, vec!["ja", "nein"]);
+        assert_eq!(test.answer, "ja");
+        assert!(test.translations.is_empty());
+    }
+
     async fn gen_n_test(n: i32, level: i32) -> Vec<TestModel> {
         let mut tests = Vec::new();
         for _ in 0..n {
@@ -232,6 +286,8 @@ mod test_repo_tests {
         let test = TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answers: vec!["2", "3", "4"]
                 .into_iter()
@@ -239,6 +295,10 @@ mod test_repo_tests {
                 .collect(),
             answer: "2".to_string(),
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         client.create_test(test).await.unwrap();
         let test_id = get_test_id(&"1 + 1".to_string()).await;
@@ -246,6 +306,8 @@ mod test_repo_tests {
         let new_test = TestModel {
             id: Some(test_id),
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "2 + 2".to_string(),
             answers: vec!["2", "3", "4"]
                 .into_iter()
@@ -253,6 +315,10 @@ mod test_repo_tests {
                 .collect(),
             answer: "4".to_string(),
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         let updated_id = client
             .update_test_by_id(&test_id.to_string(), new_test)
@@ -275,6 +341,8 @@ mod test_repo_tests {
         let test = TestModel {
             id: None,
             theme: "addition".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "1 + 1".to_string(),
             answers: vec!["2", "3", "4"]
                 .into_iter()
@@ -282,6 +350,10 @@ mod test_repo_tests {
                 .collect(),
             answer: "2".to_string(),
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         };
         client.create_test(test).await.unwrap();
 
@@ -291,4 +363,83 @@ mod test_repo_tests {
         let result = client.get_test_by_id(&test_id.to_string()).await.unwrap();
         assert!(result.is_none())
     }
+
+    #[tokio::test]
+    async fn get_test_by_question() {
+        let client = setup(true).await;
+        client.create_test(gen_test().await).await.unwrap();
+        let test = client.get_test_by_question("1 + 1").await.unwrap();
+        assert_eq!(test.unwrap().answer, "2");
+        assert!(client.get_test_by_question("1 + 2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_question_banks() {
+        use crate::models::publication_model::ContentKind;
+        use crate::models::question_import_model::{parse_csv, parse_gift, ImportDefaults};
+
+        let defaults = ImportDefaults {
+            theme: "arithmetic".to_string(),
+            level: 2,
+        };
+        let csv = "type,theme,level,question,answers,answer,hints,skills\n\
+                   choice,Fractions,3,\"1/2 + 1/4\",\"3/4|2/6|1/8\",3/4,\"Common denominator|4\",signs\n\
+                   action,,,\"2 + 2 * 2\",,6,,\n\
+                   choice,,9,\"1 + 1\",\"2|3\",4,,\n\
+                   quiz,,,\"1 - 1\",\"0|1\",0,,\n\
+                   choice,,x,\"1 * 1\",\"1|2\",1,,\n";
+        let (questions, issues) = parse_csv(csv.as_bytes(), &defaults);
+        assert_eq!(questions.len(), 3);
+        let fractions = &questions[0];
+        assert_eq!((fractions.line, fractions.kind), (2, ContentKind::Choice));
+        assert_eq!(fractions.test.theme, "Fractions");
+        assert_eq!(fractions.test.answers, vec!["3/4", "2/6", "1/8"]);
+        assert_eq!(fractions.test.hints.len(), 2);
+        assert_eq!(fractions.skills, vec!["signs"]);
+        assert!(fractions.validate().is_empty());
+        let action = &questions[1];
+        assert_eq!(action.kind, ContentKind::Action);
+        assert_eq!((action.test.theme.as_str(), action.test.level), ("arithmetic", 2));
+        assert!(action.test.answers.is_empty());
+        assert!(action.validate().is_empty());
+        // level 9 and an answer that is not one of the answers
+        assert_eq!(questions[2].validate().len(), 2);
+        let lines: Vec<usize> = issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, vec![5, 6]);
+        assert_eq!(issues[0].question, "1 - 1");
+
+        let gift = "// arithmetic\n\
+                    $CATEGORY: $course$/top/Fractions\n\
+                    \n\
+                    ::half::What is 1/2 of 4? {=2 ~1 #no ~4 ####Divide 4 by 2.}\n\
+                    \n\
+                    2 + 2 is even.{T}\n\
+                    \n\
+                    Name a prime below 4 {=2 =3}\n\
+                    \n\
+                    What is 3 \\= 1 + ? {#2}\n\
+                    \n\
+                    The square of 3 is {~6 =9} of course.\n\
+                    \n\
+                    Describe fractions. {}\n\
+                    \n\
+                    Match {=1 -> one =2 -> two}\n";
+        let (questions, issues) = parse_gift(gift, &defaults);
+        assert_eq!(questions.len(), 4);
+        let half = &questions[0];
+        assert_eq!((half.line, half.kind), (4, ContentKind::Choice));
+        assert_eq!(half.test.question, "What is 1/2 of 4?");
+        assert_eq!(half.test.answers, vec!["2", "1", "4"]);
+        assert_eq!(half.test.theme, "Fractions");
+        assert_eq!(half.test.explanation.len(), 1);
+        assert_eq!(questions[1].test.answer, "true");
+        assert_eq!(questions[2].kind, ContentKind::Action);
+        assert_eq!(questions[2].test.question, "What is 3 = 1 + ?");
+        assert_eq!(questions[2].test.answer, "2");
+        assert_eq!(questions[3].test.question, "The square of 3 is _____ of course.");
+        assert!(questions.iter().all(|question| question.validate().is_empty()));
+        let lines: Vec<usize> = issues.iter().map(|issue| issue.line).collect();
+        // more than one accepted answer, an essay and a matching question
+        assert_eq!(lines, vec![8, 14, 16]);
+    }
 }
@@ -109,10 +109,16 @@ mod tests_with_actions_tests {
         Test {
             id: None,
             theme: "arithmetic".to_string(),
+            theme_id: None,
+            skills: vec![],
             question: "2 + 2 * 2".to_string(),
             answers: vec!["2 * 2".to_string(), "2 + 4".to_owned()],
             answer: "6".to_owned(),
             level: 1,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
         }
     }
 
@@ -248,10 +254,16 @@ mod tests_with_actions_tests {
                 let new_test = Test {
                     id: Some(test.id.unwrap()),
                     theme: "addition".to_string(),
+                    theme_id: None,
+                    skills: vec![],
                     question: "2 + 2 / 2".to_string(),
                     answers: vec!["2 / 2".to_string(), "2 + 1".to_string()],
                     answer: "3".to_string(),
                     level: 1,
+                    hints: vec![],
+                    explanation: vec![],
+                    hint_penalty: None,
+                    translations: Default::default(),
                 };
                 let result = client
                     .update_test(test_id.to_string().as_str(), new_test)
@@ -274,4 +286,63 @@ mod tests_with_actions_tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn qti_round_trip() {
+        use crate::models::publication_model::ContentKind;
+        use crate::models::qti_model::{parse_qti, QtiPackage};
+        use crate::models::question_import_model::ImportDefaults;
+        use crate::models::tests_model::TestModel;
+
+        let mut action = gen_test().await;
+        action.id = Some(ObjectId::new());
+        let choice = TestModel {
+            id: Some(ObjectId::new()),
+            theme: "fractions".to_string(),
+            theme_id: None,
+            skills: vec![],
+            question: "Is 1/2 < 2/3 & 3/4?".to_string(),
+            answers: vec!["yes".to_string(), "no".to_string()],
+            answer: "no".to_string(),
+            level: 2,
+            hints: vec![],
+            explanation: vec![],
+            hint_penalty: None,
+            translations: Default::default(),
+        };
+        let mut broken = choice.clone();
+        broken.id = Some(ObjectId::new());
+        broken.answer = "maybe".to_string();
+        let package = QtiPackage::new(&[choice.clone(), broken], &[action.clone()], &[]);
+        assert_eq!(package.items.len(), 2);
+        assert_eq!(package.report.skipped.len(), 1);
+        assert_eq!(package.report.skipped[0].kind, ContentKind::Choice);
+        assert!(package.items[0].1.contains("choiceInteraction"));
+        assert!(package.items[1].1.contains("baseType=\"float\""));
+
+        let defaults = ImportDefaults {
+            theme: "imported".to_string(),
+            level: 1,
+        };
+        let zip = package.to_zip().unwrap();
+        let (questions, issues) = parse_qti(&zip, &defaults);
+        assert!(issues.is_empty());
+        assert_eq!(questions.len(), 2);
+        assert_eq!(questions[0].kind, ContentKind::Choice);
+        assert_eq!(questions[0].test.question, choice.question);
+        assert_eq!(questions[0].test.answers, choice.answers);
+        assert_eq!(questions[0].test.answer, "no");
+        assert_eq!(questions[1].kind, ContentKind::Action);
+        assert_eq!(questions[1].test.question, action.question);
+        assert_eq!(questions[1].test.answer, "6");
+        assert!(questions.iter().all(|question| question.validate().is_empty()));
+
+        let hotspot = r#"<assessmentItem xmlns="http://www.imsglobal.org/xsd/imsqti_v2p1">
+            <responseDeclaration identifier="RESPONSE" cardinality="single" baseType="point"/>
+            <itemBody><hotspotInteraction responseIdentifier="RESPONSE" maxChoices="1"/></itemBody>
+        </assessmentItem>"#;
+        let (questions, issues) = parse_qti(hotspot.as_bytes(), &defaults);
+        assert!(questions.is_empty());
+        assert_eq!(issues[0].message, "`hotspotInteraction` is not supported");
+    }
 }
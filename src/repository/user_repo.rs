@@ -7,17 +7,37 @@ extern crate dotenv;
 use dotenv::dotenv;
 use rocket::futures::TryStreamExt;
 
-use crate::models::user_model::{UserModel, UserProgress, UserRole};
+use crate::models::user_model::{
+    LegacyUserProgress, Placement, UserModel, UserProgress, UserRole, LEGACY_PROGRESS_KEYS,
+};
 
 use mongodb::{
-    bson::{doc, extjson::de::Error, oid::ObjectId},
+    bson::{doc, extjson::de::Error, oid::ObjectId, Document},
     results::{DeleteResult, InsertOneResult},
     Client, Collection,
 };
 use sha256::digest;
 
+use serde::Serialize;
+use std::collections::HashMap;
 use tokio;
 
+/// `ProgressMigrationReport` is the result of `UserRepo::migrate_progress`.
+///
+/// Properties:
+///
+/// * `migrated_users`: The ids of the users whose progress was rewritten.
+/// * `dropped_nodes`: Passed level ids that are in no course, they are not kept.
+/// * `legacy_passes`: The ids of the tests each migrated user had passed, by user id. They only
+///   count as passed once they are saved as graded attempts, see
+///   `AttemptsRepo::carry_legacy_passes`.
+#[derive(Debug, Default, Serialize)]
+pub struct ProgressMigrationReport {
+    pub migrated_users: Vec<String>,
+    pub dropped_nodes: Vec<String>,
+    pub legacy_passes: HashMap<String, Vec<String>>,
+}
+
 pub struct UserRepo {
     pub collection: Collection<UserModel>,
 }
@@ -148,6 +168,25 @@ impl UserRepo {
         Ok(users)
     }
 
+    /// It gets the users enrolled in a course, whether they completed it or not.
+    /// 
+    /// Arguments:
+    /// 
+    /// * `cource_id`: &str - The id of the course
+    /// 
+    /// Returns:
+    /// 
+    /// A vector of UserModel
+    pub async fn get_enrolled_users(&self, cource_id: &str) -> Result<Vec<UserModel>, Error> {
+        let cursor = self
+            .collection
+            .find(doc! {"progress.enrollments.course_id": cource_id}, None)
+            .await
+            .unwrap();
+
+        Ok(cursor.try_collect().await.unwrap())
+    }
+
     /// It deletes a user by id.
     /// 
     /// Arguments:
@@ -192,7 +231,7 @@ impl UserRepo {
 
     // * Progress methods
 
-    /// It adds a course to a user's progress.
+    /// It enrolls a user in a course.
     /// 
     /// Arguments:
     /// 
@@ -200,7 +239,7 @@ impl UserRepo {
     /// * `cource_id`: The id of the course you want to add to the user.
     pub async fn add_cource_to_user(&self, user_id: String, cource_id: String) {
         let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
-        user.progress.courses.push(cource_id);
+        user.progress.enroll(&cource_id, chrono::Utc::now().timestamp());
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
@@ -234,24 +273,47 @@ impl UserRepo {
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
-    /// This Rust function adds a node to a user's progress if it is not already present.
+    /// It records a passed level in the enrollment of a user in its course. The user is enrolled
+    /// if they are not yet.
     /// 
     /// Arguments:
     /// 
-    /// * `user_id`: The user ID is a unique identifier for a user in the system. It is used to retrieve
-    /// and update information about the user, such as their progress in the system.
-    /// * `node_id`: `node_id` is a `String` parameter representing the unique identifier of a node that
-    /// needs to be added to a user's progress.
-    pub async fn add_node_to_user(&self, user_id: String, node_id: String) {
+    /// * `user_id`: The id of the user who passed the level.
+    /// * `cource_id`: The id of the course of the level.
+    /// * `node_id`: The id of the passed level.
+    pub async fn add_node_to_user(&self, user_id: String, cource_id: String, node_id: String) {
         let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
-        if user.progress.nodes.contains(&node_id) {
-            return;
-        }
-        user.progress.nodes.push(node_id);
+        user.progress
+            .pass_node(&cource_id, &node_id, chrono::Utc::now().timestamp());
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
-    /// It removes a course from a user's progress.
+    /// It marks the enrollment of a user in a course as completed, if it is not already.
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: The id of the user who completed the course.
+    /// * `cource_id`: The id of the completed course.
+    pub async fn complete_cource_for_user(&self, user_id: String, cource_id: String) {
+        let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
+        user.progress
+            .complete(&cource_id, chrono::Utc::now().timestamp());
+        self.put_user_by_id(&user_id, user).await.unwrap();
+    }
+
+    /// It stores the result of a placement test of a user, replacing the previous one of the theme.
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: The id of the user who took the placement test.
+    /// * `placement`: The estimated level for the theme.
+    pub async fn set_placement(&self, user_id: String, placement: Placement) {
+        let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
+        user.progress.place(placement);
+        self.put_user_by_id(&user_id, user).await.unwrap();
+    }
+
+    /// It removes the enrollment of a user in a course, with the levels passed in it.
     /// 
     /// Arguments:
     /// 
@@ -259,7 +321,7 @@ impl UserRepo {
     /// * `cource_id`: The id of the course to be removed from the user's progress.
     pub async fn remove_cource_from_user(&self, user_id: String, cource_id: String) {
         let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
-        user.progress.courses.retain(|x| x != &cource_id);
+        user.progress.leave(&cource_id);
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
@@ -287,19 +349,74 @@ impl UserRepo {
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
-    /// This Rust function removes a node ID from a user's progress.
+    /// This Rust function removes a passed level from the enrollment of a user in a course.
     /// 
     /// Arguments:
     /// 
     /// * `user_id`: A string representing the unique identifier of a user.
+    /// * `cource_id`: The id of the course of the level.
     /// * `node_id`: The `node_id` parameter is a `String` representing the unique identifier of a node
     /// that needs to be removed from a user's progress.
-    pub async fn remove_node_from_user(&self, user_id: String, node_id: String) {
+    pub async fn remove_node_from_user(&self, user_id: String, cource_id: String, node_id: String) {
         let mut user = self.get_user_by_id(&user_id).await.unwrap().unwrap();
-        user.progress.nodes.retain(|x| x != &node_id);
+        user.progress.unpass_node(&cource_id, &node_id);
         self.put_user_by_id(&user_id, user).await.unwrap();
     }
 
+    /// It rewrites the progress stored in the old flat shape (`courses`, `tests`, `infos`,
+    /// `nodes`) into enrollments. Only progress that still has one of `LEGACY_PROGRESS_KEYS` is
+    /// rewritten, enrollments it already has are kept, so the migration can be run more than once.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_courses`: The course id of every level id, to split the passed levels by course.
+    ///
+    /// Returns:
+    ///
+    /// A ProgressMigrationReport
+    pub async fn migrate_progress(
+        &self,
+        node_courses: &HashMap<String, String>,
+    ) -> Result<ProgressMigrationReport, Error> {
+        let raw = self.collection.clone_with_type::<Document>();
+        let mut cursor = raw.find(None, None).await.unwrap();
+        let mut report = ProgressMigrationReport::default();
+        let now = chrono::Utc::now().timestamp();
+
+        while let Some(user) = cursor.try_next().await.unwrap() {
+            let progress = match user.get_document("progress") {
+                Ok(progress) => progress,
+                Err(_) => continue,
+            };
+            if !LEGACY_PROGRESS_KEYS
+                .iter()
+                .any(|key| progress.contains_key(key))
+            {
+                continue;
+            }
+            let legacy: LegacyUserProgress =
+                mongodb::bson::from_document(progress.clone()).unwrap_or_default();
+            let mut current: UserProgress =
+                mongodb::bson::from_document(progress.clone()).unwrap_or_default();
+            let passed_tests = legacy.tests.clone();
+            let dropped = current.merge_legacy(legacy, node_courses, now);
+            let id = user.get_object_id("_id").unwrap();
+            raw.update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"progress": mongodb::bson::to_bson(&current).unwrap()}},
+                None,
+            )
+            .await
+            .unwrap();
+            report.migrated_users.push(id.to_hex());
+            report.dropped_nodes.extend(dropped);
+            if !passed_tests.is_empty() {
+                report.legacy_passes.insert(id.to_hex(), passed_tests);
+            }
+        }
+        Ok(report)
+    }
+
     /// `update_all_progress` updates the progress of a user
     /// 
     /// Arguments:
@@ -388,12 +505,7 @@ mod user_repo_tests {
                 email: format!("test{}", i),
                 hashed_password: UserRepo::hash_password_pub(format!("test{}", i)),
                 role: UserRole::User,
-                progress: UserProgress {
-                    courses: vec![],
-                    tests: vec![],
-                    infos: vec![],
-                    nodes: vec![]
-                },
+                progress: UserProgress::default(),
             })
         }
         users
@@ -414,13 +526,7 @@ mod user_repo_tests {
             email: "test".to_string(),
             hashed_password: client.hash_password("test".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         let result = client.create_user(user).await;
         println!("{:?}", result);
@@ -437,13 +543,7 @@ mod user_repo_tests {
             email: "test6".to_string(),
             hashed_password: client.hash_password("test6".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
 
         client.create_user(user).await.unwrap();
@@ -466,13 +566,7 @@ mod user_repo_tests {
             email: "test9".to_string(),
             hashed_password: client.hash_password("test9".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         client.create_user(user).await.unwrap();
 
@@ -494,13 +588,7 @@ mod user_repo_tests {
             email: "test4".to_string(),
             hashed_password: client.hash_password("test4".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         client.create_user(user).await.unwrap();
 
@@ -528,13 +616,7 @@ mod user_repo_tests {
             email: "test5".to_string(),
             hashed_password: client.hash_password("test5".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         client.create_user(user).await.unwrap();
 
@@ -555,13 +637,7 @@ mod user_repo_tests {
             email: "test7".to_string(),
             hashed_password: client.hash_password("test7".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         client.create_user(user).await.unwrap();
 
@@ -572,13 +648,7 @@ mod user_repo_tests {
             email: "test7".to_string(),
             hashed_password: client.hash_password("test7".to_string()),
             role: UserRole::User,
-            progress: UserProgress {
-                courses: vec![],
-                tests: vec![],
-                infos: vec![],
-                nodes: vec![]
-
-            },
+            progress: UserProgress::default(),
         };
         client
             .put_user_by_id(&user_for_search.id.unwrap().to_string(), user_for_update)
@@ -591,4 +661,74 @@ mod user_repo_tests {
 
     // *UserRepo Progress methods tests
     // todo: after finished CourceRepo, TestsRepo, InfoRepo write tests for progress methods in UserRepo
+
+    #[test]
+    fn progress_from_legacy() {
+        use crate::models::user_model::EnrollmentStatus;
+        use std::collections::HashSet;
+
+        let legacy = LegacyUserProgress {
+            courses: vec!["c1".to_string(), "c1".to_string()],
+            tests: vec!["t1".to_string()],
+            infos: vec![],
+            nodes: vec!["n1".to_string(), "n2".to_string(), "gone".to_string()],
+            completed_courses: vec!["c2".to_string()],
+        };
+        let node_courses = HashMap::from([
+            ("n1".to_string(), "c1".to_string()),
+            ("n2".to_string(), "c2".to_string()),
+        ]);
+        let mut progress = UserProgress::default();
+        let dropped = progress.merge_legacy(legacy.clone(), &node_courses, 100);
+        assert_eq!(dropped, vec!["gone"]);
+        assert_eq!(progress.tests, vec!["t1"]);
+        assert_eq!(progress.enrollments.len(), 2);
+        let c1 = progress.enrollment("c1").unwrap();
+        assert_eq!(c1.status, EnrollmentStatus::Active);
+        assert_eq!(c1.passed_nodes(), HashSet::from(["n1".to_string()]));
+        let c2 = progress.enrollment("c2").unwrap();
+        assert_eq!((c2.status, c2.completed_at), (EnrollmentStatus::Completed, Some(100)));
+
+        let course_levels = HashMap::from([
+            ("c1".to_string(), HashSet::from(["n1".to_string()])),
+            ("c2".to_string(), HashSet::new()),
+        ]);
+        assert_eq!(progress.validate(&course_levels).len(), 1);
+        progress.unpass_node("c2", "n2");
+        assert!(progress.validate(&course_levels).is_empty());
+        progress.leave("c1");
+        assert!(progress.enrollment("c1").is_none());
+
+        // progress written in the new shape before the migration keeps its enrollments
+        let mut progress = UserProgress::default();
+        progress.pass_node("c3", "n3", 50);
+        progress.tests.push("t1".to_string());
+        progress.merge_legacy(legacy, &node_courses, 100);
+        assert_eq!(progress.enrollments.len(), 3);
+        assert_eq!(progress.enrollment("c3").unwrap().nodes[0].passed_at, 50);
+        assert_eq!(progress.tests, vec!["t1"]);
+    }
+
+    #[tokio::test]
+    async fn migrate_progress_with_legacy_keys() {
+        let client = setup(true, Some(1)).await;
+        let id = _get_user_by_email(&"test0".to_string()).await.id.unwrap();
+        let raw = client.collection.clone_with_type::<Document>();
+        // an empty list of enrollments written before the migration does not hide the old keys
+        let legacy = doc! {"enrollments": [], "courses": ["c1"], "nodes": ["n1"], "tests": []};
+        raw.update_one(doc! {"_id": id}, doc! {"$set": {"progress": legacy}}, None)
+            .await
+            .unwrap();
+        let node_courses = HashMap::from([("n1".to_string(), "c1".to_string())]);
+        let report = client.migrate_progress(&node_courses).await.unwrap();
+        assert_eq!(report.migrated_users, vec![id.to_hex()]);
+
+        let stored = raw.find_one(doc! {"_id": id}, None).await.unwrap().unwrap();
+        let progress = stored.get_document("progress").unwrap();
+        assert!(!progress.contains_key("nodes"));
+        let progress: UserProgress = mongodb::bson::from_document(progress.clone()).unwrap();
+        assert!(progress.enrollment("c1").unwrap().passed_nodes().contains("n1"));
+        let report = client.migrate_progress(&node_courses).await.unwrap();
+        assert!(report.migrated_users.is_empty());
+    }
 }
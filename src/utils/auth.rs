@@ -9,6 +9,8 @@ use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header,
 
 use rocket::State;
 use rocket::http::hyper::header::AUTHORIZATION;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
 use serde::{Deserialize, Serialize};
 
 use super::errors::{Error, Result as JWTResult};
@@ -171,3 +173,42 @@ pub async fn authorize_token(token: String, db: &State<UserRepo>) -> (bool, Stri
     }
     (false, "null".to_string())
 }
+
+/// `AuthUser` is the user authenticated by the `Authorization: FunLe Security <token>` header.
+/// Routes that take it answer 401 if the header is missing, the token is not valid or the user
+/// does not exist.
+///
+/// Properties:
+///
+/// * `id`: The id of the user.
+/// * `role`: The role of the user.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: String,
+    pub role: UserRole,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthUser {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let claims = match jwt_from_header(request.headers())
+            .and_then(|token| decode_jwt(token.trim()))
+        {
+            Ok(claims) => claims,
+            Err(e) => return Outcome::Failure((Status::Unauthorized, e)),
+        };
+        let db = match request.guard::<&State<UserRepo>>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Failure((Status::InternalServerError, Error::JWTTokenNotValid)),
+        };
+        match db.get_user_by_id(&claims.sub).await {
+            Ok(Some(_)) => Outcome::Success(AuthUser {
+                id: claims.sub,
+                role: claims.role,
+            }),
+            _ => Outcome::Failure((Status::Unauthorized, Error::JWTTokenNotValid)),
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, FormulaError>;
+
+/// Errors that can occur while parsing or evaluating a formula.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum FormulaError {
+    #[error("unexpected character `{0}`")]
+    UnexpectedChar(char),
+    #[error("unexpected end of formula")]
+    UnexpectedEnd,
+    #[error("unexpected token `{0}`")]
+    UnexpectedToken(String),
+    #[error("unknown variable `{0}`")]
+    UnknownVariable(String),
+    #[error("unknown function `{0}`")]
+    UnknownFunction(String),
+    #[error("wrong number of arguments for `{0}`")]
+    WrongArity(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("`{0}` needs finite numbers")]
+    NotFinite(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// It splits a formula into tokens.
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let num = text
+                .parse::<f64>()
+                .map_err(|_| FormulaError::UnexpectedToken(text.clone()))?;
+            tokens.push(Token::Num(num));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        let next = chars.get(i + 1).copied();
+        let (op, len) = match (c, next) {
+            ('=', Some('=')) => ("==", 2),
+            ('!', Some('=')) => ("!=", 2),
+            ('<', Some('=')) => ("<=", 2),
+            ('>', Some('=')) => (">=", 2),
+            ('&', Some('&')) => ("&&", 2),
+            ('|', Some('|')) => ("||", 2),
+            ('<', _) => ("<", 1),
+            ('>', _) => (">", 1),
+            ('!', _) => ("!", 1),
+            ('+', _) => ("+", 1),
+            ('-', _) => ("-", 1),
+            ('*', _) => ("*", 1),
+            ('/', _) => ("/", 1),
+            ('%', _) => ("%", 1),
+            ('^', _) => ("^", 1),
+            ('(', _) => {
+                tokens.push(Token::LParen);
+                i += 1;
+                continue;
+            }
+            (')', _) => {
+                tokens.push(Token::RParen);
+                i += 1;
+                continue;
+            }
+            (',', _) => {
+                tokens.push(Token::Comma);
+                i += 1;
+                continue;
+            }
+            _ => return Err(FormulaError::UnexpectedChar(c)),
+        };
+        tokens.push(Token::Op(op));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// A parsed formula.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(f64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+/// Binary operators grouped by precedence, lowest first.
+const PRECEDENCE: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(FormulaError::UnexpectedToken(format!("{:?}", token))),
+            None => Err(FormulaError::UnexpectedEnd),
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !PRECEDENCE[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Op("-")) => {
+                self.pos += 1;
+                Ok(Expr::Unary("-", Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Op("!")) => {
+                self.pos += 1;
+                Ok(Expr::Unary("!", Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    // `^` is right associative and binds tighter than unary minus on its left side
+    fn parse_power(&mut self) -> Result<Expr> {
+        let base = self.parse_atom()?;
+        if let Some(Token::Op("^")) = self.peek() {
+            self.pos += 1;
+            let exponent = self.parse_unary()?;
+            return Ok(Expr::Binary("^", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.pos += 1;
+                    let mut args = Vec::new();
+                    if let Some(Token::RParen) = self.peek() {
+                        self.pos += 1;
+                        return Ok(Expr::Call(name, args));
+                    }
+                    loop {
+                        args.push(self.parse_binary(0)?);
+                        match self.next() {
+                            Some(Token::Comma) => continue,
+                            Some(Token::RParen) => break,
+                            Some(token) => {
+                                return Err(FormulaError::UnexpectedToken(format!("{:?}", token)))
+                            }
+                            None => return Err(FormulaError::UnexpectedEnd),
+                        }
+                    }
+                    Ok(Expr::Call(name, args))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            Some(Token::LParen) => {
+                let expr = self.parse_binary(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(token) => Err(FormulaError::UnexpectedToken(format!("{:?}", token))),
+            None => Err(FormulaError::UnexpectedEnd),
+        }
+    }
+}
+
+/// It computes the greatest common divisor of the rounded numbers. NaN and infinity never reach
+/// zero in the loop, so they are refused.
+fn gcd(name: &str, a: f64, b: f64) -> Result<f64> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err(FormulaError::NotFinite(name.to_string()));
+    }
+    let (mut a, mut b) = (a.abs().round(), b.abs().round());
+    while b != 0.0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    Ok(a)
+}
+
+fn bool_to_num(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn eval_expr(expr: &Expr, vars: &HashMap<String, f64>) -> Result<f64> {
+    match expr {
+        Expr::Num(n) => Ok(*n),
+        Expr::Var(name) => match name.as_str() {
+            "pi" => Ok(std::f64::consts::PI),
+            _ => vars
+                .get(name)
+                .copied()
+                .ok_or_else(|| FormulaError::UnknownVariable(name.clone())),
+        },
+        Expr::Unary(op, inner) => {
+            let value = eval_expr(inner, vars)?;
+            Ok(match *op {
+                "-" => -value,
+                _ => bool_to_num(value == 0.0),
+            })
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let a = eval_expr(lhs, vars)?;
+            let b = eval_expr(rhs, vars)?;
+            Ok(match *op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" | "%" if b == 0.0 => return Err(FormulaError::DivisionByZero),
+                "/" => a / b,
+                "%" => a.rem_euclid(b),
+                "^" => a.powf(b),
+                "==" => bool_to_num((a - b).abs() < 1e-9),
+                "!=" => bool_to_num((a - b).abs() >= 1e-9),
+                "<" => bool_to_num(a < b),
+                "<=" => bool_to_num(a <= b),
+                ">" => bool_to_num(a > b),
+                ">=" => bool_to_num(a >= b),
+                "&&" => bool_to_num(a != 0.0 && b != 0.0),
+                _ => bool_to_num(a != 0.0 || b != 0.0),
+            })
+        }
+        Expr::Call(name, args) => {
+            let values = args
+                .iter()
+                .map(|arg| eval_expr(arg, vars))
+                .collect::<Result<Vec<f64>>>()?;
+            let arity = |n: usize| {
+                if values.len() == n {
+                    Ok(())
+                } else {
+                    Err(FormulaError::WrongArity(name.clone()))
+                }
+            };
+            match name.as_str() {
+                "abs" => arity(1).map(|_| values[0].abs()),
+                "sqrt" => arity(1).map(|_| values[0].sqrt()),
+                "round" => arity(1).map(|_| values[0].round()),
+                "floor" => arity(1).map(|_| values[0].floor()),
+                "ceil" => arity(1).map(|_| values[0].ceil()),
+                "gcd" => arity(2).and_then(|_| gcd(name, values[0], values[1])),
+                "lcm" => arity(2).and_then(|_| {
+                    let d = gcd(name, values[0], values[1])?;
+                    Ok(if d == 0.0 {
+                        0.0
+                    } else {
+                        (values[0] * values[1]).abs() / d
+                    })
+                }),
+                "min" | "max" if values.is_empty() => Err(FormulaError::WrongArity(name.clone())),
+                "min" => Ok(values.iter().copied().fold(f64::INFINITY, f64::min)),
+                "max" => Ok(values.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+                _ => Err(FormulaError::UnknownFunction(name.clone())),
+            }
+        }
+    }
+}
+
+/// It parses and evaluates an arithmetic formula with the given variables.
+///
+/// Supported are `+ - * / % ^`, comparisons (`== != < <= > >=`), `&&`, `||`, `!`, parentheses
+/// and the functions `abs`, `sqrt`, `round`, `floor`, `ceil`, `gcd`, `lcm`, `min`, `max`.
+/// Comparisons and logical operators evaluate to `1` or `0`.
+///
+/// Arguments:
+///
+/// * `formula`: The formula to evaluate, e.g. `a * b + 1`.
+/// * `vars`: Values of the variables used in the formula.
+///
+/// Returns:
+///
+/// The value of the formula
+pub fn eval(formula: &str, vars: &HashMap<String, f64>) -> Result<f64> {
+    let mut parser = Parser {
+        tokens: tokenize(formula)?,
+        pos: 0,
+    };
+    let expr = parser.parse_binary(0)?;
+    if let Some(token) = parser.next() {
+        return Err(FormulaError::UnexpectedToken(format!("{:?}", token)));
+    }
+    eval_expr(&expr, vars)
+}
+
+/// It formats a number the way it is shown to learners: whole numbers without a fractional part,
+/// everything else rounded to at most two decimals.
+///
+/// Arguments:
+///
+/// * `value`: The number to format.
+///
+/// Returns:
+///
+/// A String
+pub fn format_number(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as i64)
+    } else {
+        let text = format!("{:.2}", rounded);
+        text.trim_end_matches('0').to_string()
+    }
+}
+
+#[cfg(test)]
+mod formula_tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn arithmetic_precedence() {
+        let empty = HashMap::new();
+        assert_eq!(eval("2 + 2 * 2", &empty).unwrap(), 6.0);
+        assert_eq!(eval("(2 + 2) * 2", &empty).unwrap(), 8.0);
+        assert_eq!(eval("-2 ^ 2", &empty).unwrap(), -4.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", &empty).unwrap(), 512.0);
+        assert_eq!(eval("7 % 3", &empty).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let v = vars(&[("a", 12.0), ("b", 18.0)]);
+        assert_eq!(eval("gcd(a, b)", &v).unwrap(), 6.0);
+        assert_eq!(eval("lcm(a, b)", &v).unwrap(), 36.0);
+        assert_eq!(eval("max(a, b, 20)", &v).unwrap(), 20.0);
+        assert_eq!(eval("a > b || a == 12", &v).unwrap(), 1.0);
+        assert_eq!(eval("a > b && a == 12", &v).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn errors() {
+        let empty = HashMap::new();
+        assert_eq!(
+            eval("x + 1", &empty),
+            Err(FormulaError::UnknownVariable("x".to_string()))
+        );
+        assert_eq!(eval("1 / 0", &empty), Err(FormulaError::DivisionByZero));
+        assert_eq!(eval("(1 + 2", &empty), Err(FormulaError::UnexpectedEnd));
+        assert!(eval("1 + + ", &empty).is_err());
+        // NaN and infinity would keep gcd looping forever
+        let not_finite = |name: &str| Err(FormulaError::NotFinite(name.to_string()));
+        assert_eq!(eval("gcd(sqrt(0 - 1), 2)", &empty), not_finite("gcd"));
+        assert_eq!(eval("gcd(10 ^ 400, 1)", &empty), not_finite("gcd"));
+        assert_eq!(eval("lcm(2, 10 ^ 400)", &empty), not_finite("lcm"));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_number(4.0), "4");
+        assert_eq!(format_number(-3.0), "-3");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(1.0 / 3.0), "0.33");
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, env};
+
+use rocket::request::{FromRequest, Outcome, Request};
+
+/// It returns the locale the content is written in when it is not translated, `DEFAULT_LOCALE` or
+/// `en`.
+pub fn default_locale() -> String {
+    env::var("DEFAULT_LOCALE").unwrap_or_else(|_| "en".to_string())
+}
+
+/// `Locales` are the locales the user prefers, the most preferred first. The `lang` query
+/// parameter comes first, then the locales of the `Accept-Language` header ordered by quality.
+/// A regional locale is followed by its language, e.g. `de-AT` by `de`, so it falls back to the
+/// general translation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Locales(pub Vec<String>);
+
+impl Locales {
+    /// It builds the preferences from the `lang` parameter and the `Accept-Language` header.
+    ///
+    /// Arguments:
+    ///
+    /// * `lang`: The value of the `lang` query parameter.
+    /// * `accept_language`: The value of the `Accept-Language` header.
+    ///
+    /// Returns:
+    ///
+    /// Locales
+    pub fn parse(lang: Option<&str>, accept_language: Option<&str>) -> Self {
+        let mut weighted: Vec<(String, f32)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|part| {
+                let mut params = part.split(';');
+                let tag = params.next()?.trim();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                    None
+                } else {
+                    Some((tag.to_string(), quality))
+                }
+            })
+            .collect();
+        // a stable sort keeps the order of the header for equal qualities
+        weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut locales: Vec<String> = Vec::new();
+        let tags = lang
+            .filter(|lang| !lang.trim().is_empty())
+            .map(|lang| lang.trim().to_string())
+            .into_iter()
+            .chain(weighted.into_iter().map(|(tag, _)| tag));
+        for tag in tags {
+            let tag = tag.to_lowercase();
+            let language = tag.split('-').next().unwrap_or_default().to_string();
+            for locale in [tag, language] {
+                if !locale.is_empty() && !locales.contains(&locale) {
+                    locales.push(locale);
+                }
+            }
+        }
+        Locales(locales)
+    }
+
+    /// It picks the translation of a document for the user. The default locale stops the search,
+    /// a user who prefers it gets the untranslated content even if other translations exist.
+    ///
+    /// Arguments:
+    ///
+    /// * `translations`: The translations of the document by locale.
+    ///
+    /// Returns:
+    ///
+    /// The locale and the translation, or None if the untranslated content should be used.
+    pub fn pick<'a, T>(&self, translations: &'a HashMap<String, T>) -> Option<(&'a str, &'a T)> {
+        let default = default_locale();
+        for locale in &self.0 {
+            if *locale == default {
+                return None;
+            }
+            if let Some((locale, translation)) = translations
+                .iter()
+                .find(|(key, _)| key.to_lowercase() == *locale)
+            {
+                return Some((locale.as_str(), translation));
+            }
+        }
+        None
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Locales {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let lang = request
+            .query_value::<&str>("lang")
+            .and_then(|lang| lang.ok());
+        let accept_language = request.headers().get_one("Accept-Language");
+        Outcome::Success(Locales::parse(lang, accept_language))
+    }
+}
+
+/// `Localize` is implemented by the content documents that have translations.
+pub trait Localize {
+    /// It replaces the translatable fields with the translation the user prefers. Fields that
+    /// are not translated keep the default content, the translations themselves are removed.
+    ///
+    /// Returns:
+    ///
+    /// The locale of the translation that was used, or the default locale.
+    fn localize(&mut self, locales: &Locales) -> String;
+
+    /// It lists the translatable fields that have no translation to the locale.
+    fn missing_translations(&self, locale: &str) -> Vec<String>;
+}
+
+#[cfg(test)]
+mod locale_tests {
+    use super::*;
+
+    #[test]
+    fn parse_preferences() {
+        let locales = Locales::parse(None, Some("de-AT;q=0.8, fr, en;q=0.1, *;q=0.5"));
+        assert_eq!(locales.0, vec!["fr", "de-at", "de", "en"]);
+
+        let locales = Locales::parse(Some("uk"), Some("de"));
+        assert_eq!(locales.0, vec!["uk", "de"]);
+
+        assert!(Locales::parse(None, None).0.is_empty());
+    }
+
+    #[test]
+    fn pick_translation() {
+        let translations =
+            HashMap::from([("de".to_string(), "Hallo"), ("fr-CA".to_string(), "Allo")]);
+        let pick = |lang| Locales::parse(Some(lang), None).pick(&translations);
+        assert_eq!(pick("de-AT"), Some(("de", &"Hallo")));
+        assert_eq!(pick("fr-ca"), Some(("fr-CA", &"Allo")));
+        assert_eq!(pick("it"), None);
+
+        let locales = Locales::parse(None, Some("en, de"));
+        assert_eq!(locales.pick(&translations), None);
+    }
+}
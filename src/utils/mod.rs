@@ -1,2 +1,6 @@
 pub mod auth;
-pub mod errors;
\ No newline at end of file
+pub mod errors;
+pub mod formula;
+pub mod locale;
+pub mod render;
+pub mod thumbnails;
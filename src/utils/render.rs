@@ -0,0 +1,772 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use pulldown_cmark::{html, Event, Parser, Tag};
+use rocket::FromFormField;
+use serde::Serialize;
+
+use crate::models::info_model::{ContentLevel, InfoModel};
+
+/// Formats the API can pre-render content into, selected with `?render=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum RenderFormat {
+    /// HTML with the math rendered as MathML.
+    Html,
+}
+
+/// It escapes the characters that have a special meaning in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TexToken {
+    Command(String),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Number(String),
+    Letter(char),
+    Symbol(char),
+}
+
+fn tokenize_tex(src: &str) -> Vec<TexToken> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                let start = i + 1;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                if i == start && i < chars.len() {
+                    // a single non letter, e.g. `\{` or `\,`
+                    i += 1;
+                }
+                tokens.push(TexToken::Command(chars[start..i].iter().collect()));
+                continue;
+            }
+            '{' => tokens.push(TexToken::Open),
+            '}' => tokens.push(TexToken::Close),
+            '^' => tokens.push(TexToken::Sup),
+            '_' => tokens.push(TexToken::Sub),
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                tokens.push(TexToken::Number(chars[start..i].iter().collect()));
+                continue;
+            }
+            c if c.is_alphabetic() => tokens.push(TexToken::Letter(c)),
+            c => tokens.push(TexToken::Symbol(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "pi" => "π",
+        "rho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "phi" | "varphi" => "φ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Phi" => "Φ",
+        "Omega" => "Ω",
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "cdot" => "⋅",
+        "times" => "×",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "le" | "leq" => "≤",
+        "ge" | "geq" => "≥",
+        "ne" | "neq" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "infty" => "∞",
+        "to" | "rightarrow" => "→",
+        "leftarrow" => "←",
+        "Rightarrow" => "⇒",
+        "Leftrightarrow" => "⇔",
+        "in" => "∈",
+        "notin" => "∉",
+        "subset" => "⊂",
+        "cup" => "∪",
+        "cap" => "∩",
+        "angle" => "∠",
+        "circ" => "∘",
+        "degree" => "°",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "sum" => "∑",
+        "prod" => "∏",
+        "int" => "∫",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "{" => "{",
+        "}" => "}",
+        "%" => "%",
+        "$" => "$",
+        "|" => "‖",
+        _ => return None,
+    })
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," => "0.167em",
+        ":" | ">" => "0.222em",
+        ";" => "0.278em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+const FUNCTIONS: [&str; 12] = [
+    "sin", "cos", "tan", "cot", "log", "ln", "lg", "exp", "lim", "max", "min", "gcd",
+];
+
+struct TexParser {
+    tokens: Vec<TexToken>,
+    pos: usize,
+}
+
+impl TexParser {
+    fn next(&mut self) -> Option<TexToken> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&TexToken> {
+        self.tokens.get(self.pos)
+    }
+
+    /// It parses elements until the end of input or until a closing brace, if `in_group` is set.
+    fn parse_row(&mut self, in_group: bool) -> Result<String, String> {
+        let mut row = String::new();
+        loop {
+            match self.peek() {
+                None if in_group => return Err("missing `}`".to_string()),
+                None => return Ok(row),
+                Some(TexToken::Close) if in_group => {
+                    self.pos += 1;
+                    return Ok(row);
+                }
+                Some(TexToken::Close) => return Err("unexpected `}`".to_string()),
+                _ => {}
+            }
+            let base = self.parse_atom()?;
+            row.push_str(&self.parse_scripts(base)?);
+        }
+    }
+
+    fn parse_scripts(&mut self, base: String) -> Result<String, String> {
+        let mut sup = None;
+        let mut sub = None;
+        loop {
+            match self.peek() {
+                Some(TexToken::Sup) if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument()?);
+                }
+                Some(TexToken::Sub) if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_argument()?);
+                }
+                Some(TexToken::Sup) | Some(TexToken::Sub) => {
+                    return Err("double script, use braces".to_string())
+                }
+                _ => break,
+            }
+        }
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        })
+    }
+
+    /// It parses the argument of a command or a script: a group or a single element.
+    fn parse_argument(&mut self) -> Result<String, String> {
+        match self.peek() {
+            None => Err("missing argument".to_string()),
+            Some(TexToken::Sup) | Some(TexToken::Sub) | Some(TexToken::Close) => {
+                Err("missing argument".to_string())
+            }
+            _ => self.parse_atom(),
+        }
+    }
+
+    fn parse_text(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(TexToken::Open) => {}
+            _ => return Err("`\\text` needs a `{`".to_string()),
+        }
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(TexToken::Close) => break,
+                Some(TexToken::Letter(c)) | Some(TexToken::Symbol(c)) => text.push(c),
+                Some(TexToken::Number(n)) => text.push_str(&n),
+                Some(TexToken::Command(c)) => text.push_str(&c),
+                Some(_) => {}
+                None => return Err("missing `}`".to_string()),
+            }
+        }
+        Ok(format!("<mtext>{}</mtext>", escape_html(&text)))
+    }
+
+    fn parse_atom(&mut self) -> Result<String, String> {
+        match self.next() {
+            None => Err("unexpected end of formula".to_string()),
+            Some(TexToken::Open) => Ok(format!("<mrow>{}</mrow>", self.parse_row(true)?)),
+            Some(TexToken::Close) => Err("unexpected `}`".to_string()),
+            Some(TexToken::Sup) | Some(TexToken::Sub) => Ok("<mrow></mrow>".to_string()),
+            Some(TexToken::Number(n)) => Ok(format!("<mn>{}</mn>", n)),
+            Some(TexToken::Letter(c)) => Ok(format!("<mi>{}</mi>", c)),
+            Some(TexToken::Symbol(c)) => Ok(format!("<mo>{}</mo>", escape_html(&c.to_string()))),
+            Some(TexToken::Command(name)) => self.parse_command(&name),
+        }
+    }
+
+    fn parse_command(&mut self, name: &str) -> Result<String, String> {
+        if let Some(letter) = greek(name) {
+            return Ok(format!("<mi>{}</mi>", letter));
+        }
+        if let Some(op) = operator(name) {
+            return Ok(format!("<mo>{}</mo>", op));
+        }
+        if let Some(width) = space(name) {
+            return Ok(format!("<mspace width=\"{}\"/>", width));
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(format!("<mi>{}</mi>", name));
+        }
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                Ok(format!("<mfrac>{}{}</mfrac>", numerator, denominator))
+            }
+            "sqrt" => {
+                if let Some(TexToken::Symbol('[')) = self.peek() {
+                    self.pos += 1;
+                    let mut index = String::new();
+                    loop {
+                        match self.peek() {
+                            Some(TexToken::Symbol(']')) => {
+                                self.pos += 1;
+                                break;
+                            }
+                            None => return Err("missing `]`".to_string()),
+                            _ => index.push_str(&self.parse_atom()?),
+                        }
+                    }
+                    let radicand = self.parse_argument()?;
+                    Ok(format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index))
+                } else {
+                    Ok(format!("<msqrt>{}</msqrt>", self.parse_argument()?))
+                }
+            }
+            "overline" | "bar" => Ok(format!(
+                "<mover>{}<mo>¯</mo></mover>",
+                self.parse_argument()?
+            )),
+            "vec" => Ok(format!(
+                "<mover>{}<mo>→</mo></mover>",
+                self.parse_argument()?
+            )),
+            "text" | "mathrm" => self.parse_text(),
+            "left" | "right" => match self.next() {
+                Some(TexToken::Symbol('.')) => Ok(String::new()),
+                Some(TexToken::Symbol(c)) => Ok(format!("<mo>{}</mo>", escape_html(&c.to_string()))),
+                Some(TexToken::Command(c)) if operator(&c).is_some() => {
+                    Ok(format!("<mo>{}</mo>", operator(&c).unwrap()))
+                }
+                _ => Err(format!("`\\{}` needs a delimiter", name)),
+            },
+            _ => Err(format!("unknown command `\\{}`", name)),
+        }
+    }
+}
+
+/// It converts a LaTeX formula into MathML.
+///
+/// Arguments:
+///
+/// * `tex`: The formula without `$` delimiters.
+/// * `display`: Whether the formula is shown on its own line.
+///
+/// Returns:
+///
+/// A Result<String, String> with the MathML or the reason the formula can not be rendered.
+pub fn latex_to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = TexParser {
+        tokens: tokenize_tex(tex),
+        pos: 0,
+    };
+    let row = parser.parse_row(false)?;
+    let display = if display { " display=\"block\"" } else { "" };
+    Ok(format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}><mrow>{}</mrow></math>",
+        display, row
+    ))
+}
+
+/// It replaces every `$...$` (inline) and `$$...$$` (display) formula in the text with MathML.
+/// Everything outside the formulas goes through `plain` and the MathML of every formula through
+/// `math`, `\$` is a literal dollar sign.
+fn replace_math(
+    text: &str,
+    plain: fn(&str) -> String,
+    mut math: impl FnMut(String) -> String,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut buffer = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'$') => {
+                chars.next();
+                buffer.push('$');
+            }
+            '$' => {
+                let display = chars.peek() == Some(&'$');
+                if display {
+                    chars.next();
+                }
+                let mut formula = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '$' {
+                        if display && chars.peek() != Some(&'$') {
+                            formula.push(c);
+                            continue;
+                        }
+                        if display {
+                            chars.next();
+                        }
+                        closed = true;
+                        break;
+                    }
+                    formula.push(c);
+                }
+                if !closed {
+                    return Err("unclosed `$`".to_string());
+                }
+                out.push_str(&plain(&buffer));
+                buffer.clear();
+                let mathml = latex_to_mathml(&formula, display)
+                    .map_err(|e| format!("formula `{}`: {}", formula.trim(), e))?;
+                out.push_str(&math(mathml));
+            }
+            c => buffer.push(c),
+        }
+    }
+    out.push_str(&plain(&buffer));
+    Ok(out)
+}
+
+/// It renders text with `$...$` formulas into HTML.
+pub fn render_text(text: &str) -> Result<String, String> {
+    replace_math(text, escape_html, |mathml| mathml)
+}
+
+/// It checks that a link or an image of Markdown points at a web page, an IPFS file or a relative
+/// path. Any other scheme, e.g. `javascript:`, could run code in the page of the learner.
+fn is_safe_url(url: &str) -> bool {
+    match url.find(':') {
+        Some(colon) if !url[..colon].contains(['/', '?', '#']) => {
+            let scheme = url[..colon].to_ascii_lowercase();
+            ["http", "https", "ipfs"].contains(&scheme.as_str())
+        }
+        _ => true,
+    }
+}
+
+/// It drops the destination of a link or an image that is not safe, see `is_safe_url`.
+fn safe_tag(tag: Tag) -> Tag {
+    match tag {
+        Tag::Link(kind, url, title) if !is_safe_url(&url) => Tag::Link(kind, "".into(), title),
+        Tag::Image(kind, url, title) if !is_safe_url(&url) => Tag::Image(kind, "".into(), title),
+        tag => tag,
+    }
+}
+
+/// It renders Markdown with `$...$` formulas into HTML. The formulas are converted before the
+/// Markdown, so `_` and `*` inside of them are not taken for emphasis. Raw HTML in the Markdown is
+/// escaped, only the MathML of the formulas is kept, and links and images only point at safe
+/// urls.
+pub fn render_markdown(text: &str) -> Result<String, String> {
+    // formulas are kept out of the Markdown behind placeholders, so their MathML is not taken
+    // for raw HTML
+    let placeholder = |index: usize| format!("\u{E000}{}\u{E001}", index);
+    let mut formulas = vec![];
+    let text = replace_math(
+        text,
+        |s| s.to_string(),
+        |mathml| {
+            formulas.push(mathml);
+            placeholder(formulas.len() - 1)
+        },
+    )?;
+    let events = Parser::new(&text).map(|event| match event {
+        Event::Html(html) => Event::Text(html),
+        Event::Start(tag) => Event::Start(safe_tag(tag)),
+        Event::End(tag) => Event::End(safe_tag(tag)),
+        event => event,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    for (index, mathml) in formulas.iter().enumerate() {
+        out = out.replace(&placeholder(index), mathml);
+    }
+    Ok(out)
+}
+
+/// It renders one content block into HTML.
+///
+/// Arguments:
+///
+/// * `block`: The block to render.
+///
+/// Returns:
+///
+/// A Result<String, String> with the HTML or the reason the block can not be rendered.
+pub fn render_block(block: &ContentLevel) -> Result<String, String> {
+    Ok(match block {
+        ContentLevel::Text { data } | ContentLevel::LaTeX { data } => {
+            format!("<p>{}</p>", render_text(data)?)
+        }
+        ContentLevel::Markdown { data } => render_markdown(data)?,
+        ContentLevel::Formula { data } => latex_to_mathml(data, true)?,
+        ContentLevel::Image { url, alt } => format!(
+            "<img src=\"{}\" alt=\"{}\"/>",
+            escape_html(url),
+            escape_html(alt)
+        ),
+        ContentLevel::Video { url } => {
+            format!("<video src=\"{}\" controls></video>", escape_html(url))
+        }
+        ContentLevel::Code { lang, data } => format!(
+            "<pre><code class=\"language-{}\">{}</code></pre>",
+            escape_html(lang),
+            escape_html(data)
+        ),
+        ContentLevel::Callout { data } => {
+            format!("<aside class=\"callout\">{}</aside>", render_markdown(data)?)
+        }
+    })
+}
+
+/// `RenderedInfo` is the HTML of the content blocks of an info, in the same layout as
+/// `InfoModel.content_levels`.
+pub type RenderedInfo = Vec<(i32, Vec<String>)>;
+
+/// It renders all content blocks of an info.
+///
+/// Arguments:
+///
+/// * `info`: The info to render.
+///
+/// Returns:
+///
+/// The rendered blocks sorted by level, or the list of problems if some blocks can not be rendered.
+pub fn render_info(info: &InfoModel) -> Result<RenderedInfo, Vec<String>> {
+    let mut levels: Vec<&i32> = info.content_levels.keys().collect();
+    levels.sort();
+    let mut rendered = Vec::new();
+    let mut errors = Vec::new();
+    for level in levels {
+        let mut blocks = Vec::new();
+        for (i, block) in info.content_levels[level].iter().enumerate() {
+            match render_block(block) {
+                Ok(html) => blocks.push(html),
+                Err(e) => errors.push(format!("level {}, block {}: {}", level, i + 1, e)),
+            }
+        }
+        rendered.push((*level, blocks));
+    }
+    if errors.is_empty() {
+        Ok(rendered)
+    } else {
+        Err(errors)
+    }
+}
+
+/// `RenderedQuestion` is the HTML of the question and of the answers of a test.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedQuestion {
+    pub question: String,
+    pub answers: Vec<String>,
+}
+
+/// It renders the question and the answers of a test.
+///
+/// Arguments:
+///
+/// * `question`: The text of the question.
+/// * `answers`: The answers.
+///
+/// Returns:
+///
+/// The rendered question, or the list of problems if it can not be rendered.
+pub fn render_question(question: &str, answers: &[String]) -> Result<RenderedQuestion, Vec<String>> {
+    let mut errors = Vec::new();
+    let question = render_text(question).unwrap_or_else(|e| {
+        errors.push(format!("question: {}", e));
+        String::new()
+    });
+    let answers = answers
+        .iter()
+        .enumerate()
+        .map(|(i, answer)| {
+            render_text(answer).unwrap_or_else(|e| {
+                errors.push(format!("answer {}: {}", i + 1, e));
+                String::new()
+            })
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(RenderedQuestion { question, answers })
+    } else {
+        Err(errors)
+    }
+}
+
+/// `Rendered` is a document together with its pre-rendered content.
+///
+/// Properties:
+///
+/// * `doc`: The document, its fields are inlined into the JSON.
+/// * `rendered`: The pre-rendered content, only present if it was requested.
+#[derive(Debug, Clone, Serialize)]
+pub struct Rendered<T, R> {
+    #[serde(flatten)]
+    pub doc: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendered: Option<R>,
+}
+
+/// It computes the version of a document, the hash of its JSON. Maps are serialized in no
+/// particular order, so object keys and lists of `[key, value]` pairs are sorted first, like
+/// `revision_model::snapshot` does, and two copies of the same document have the same version.
+pub fn document_version<T: Serialize>(doc: &T) -> String {
+    fn canonical(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.values_mut().for_each(canonical);
+                map.sort_keys();
+            }
+            serde_json::Value::Array(items) => {
+                items.iter_mut().for_each(canonical);
+                let is_pair = |item: &serde_json::Value| {
+                    matches!(item.as_array(), Some(pair) if pair.len() == 2)
+                };
+                if !items.is_empty() && items.iter().all(is_pair) {
+                    items.sort_by_key(|item| item[0].to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut value = serde_json::to_value(doc).unwrap_or_default();
+    canonical(&mut value);
+    sha256::digest(value.to_string())
+}
+
+/// `RenderCache` keeps rendered content per document. An entry is only used while the version of
+/// the document is the same, so every change of the document is rendered again.
+pub struct RenderCache<R> {
+    entries: Mutex<HashMap<String, (String, R)>>,
+}
+
+impl<R: Clone> RenderCache<R> {
+    /// It creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// It returns the cached rendering of the document or renders and caches it.
+    ///
+    /// Arguments:
+    ///
+    /// * `id`: The id of the document.
+    /// * `doc`: The document, see `document_version`.
+    /// * `render`: The function that renders the document.
+    ///
+    /// Returns:
+    ///
+    /// The rendered content
+    pub fn get_or_render<T: Serialize, E>(
+        &self,
+        id: &str,
+        doc: &T,
+        render: impl FnOnce(&T) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let version = document_version(doc);
+        if let Some((cached_version, rendered)) = self.entries.lock().unwrap().get(id) {
+            if *cached_version == version {
+                return Ok(rendered.clone());
+            }
+        }
+        let rendered = render(doc)?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), (version, rendered.clone()));
+        Ok(rendered)
+    }
+}
+
+impl<R: Clone> Default for RenderCache<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+
+    #[test]
+    fn fractions_and_scripts() {
+        let mathml = latex_to_mathml("\\frac{1}{2} + x^2_i", false).unwrap();
+        assert!(mathml.contains("<mfrac><mrow><mn>1</mn></mrow><mrow><mn>2</mn></mrow></mfrac>"));
+        assert!(mathml.contains("<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>"));
+    }
+
+    #[test]
+    fn roots_and_symbols() {
+        let mathml = latex_to_mathml("\\sqrt[3]{8} \\cdot \\pi \\le 10", true).unwrap();
+        assert!(mathml.contains("display=\"block\""));
+        assert!(mathml.contains("<mroot><mrow><mn>8</mn></mrow><mrow><mn>3</mn></mrow></mroot>"));
+        assert!(mathml.contains("<mo>⋅</mo><mi>π</mi><mo>≤</mo>"));
+    }
+
+    #[test]
+    fn formula_errors() {
+        assert!(latex_to_mathml("\\frac{1}", false).is_err());
+        assert!(latex_to_mathml("{x + 1", false).is_err());
+        assert!(latex_to_mathml("x}", false).is_err());
+        assert!(latex_to_mathml("\\unknown{x}", false).is_err());
+    }
+
+    #[test]
+    fn text_with_math() {
+        let html = render_text("Solve $x < 2$ for <b>").unwrap();
+        assert!(html.starts_with("Solve <math"));
+        assert!(html.contains("<mo>&lt;</mo>"));
+        assert!(html.ends_with("for &lt;b&gt;"));
+        assert!(render_text("costs \\$5").unwrap() == "costs $5");
+        assert!(render_text("broken $x").is_err());
+    }
+
+    #[test]
+    fn markdown_keeps_math() {
+        let html = render_markdown("**Area**: $a_1 * b_1$").unwrap();
+        assert!(html.contains("<strong>Area</strong>"));
+        assert!(html.contains("<msub><mi>a</mi><mn>1</mn></msub>"));
+    }
+
+    #[test]
+    fn markdown_escapes_raw_html() {
+        let text = "<script>alert(1)</script>\n\nSee <b onclick=\"x\">$x$</b>";
+        let html = render_markdown(text).unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("&lt;b onclick="));
+        assert!(html.contains("<math xmlns"));
+    }
+
+    #[test]
+    fn markdown_drops_unsafe_urls() {
+        let text = "[a](javascript:alert(1)) [b](JavaScript&#58;alert(1)) ![c](data:text/html,x)";
+        let html = render_markdown(text).unwrap();
+        assert!(!html.to_lowercase().contains("javascript"));
+        assert!(!html.contains("data:"));
+        assert!(html.contains("<a href=\"\">a</a>"));
+
+        let text = "[a](https://x.org) [b](/infos/1) [c](page?q=a:b) ![d](ipfs://Qm)";
+        let html = render_markdown(text).unwrap();
+        assert!(html.contains("href=\"https://x.org\""));
+        assert!(html.contains("href=\"/infos/1\""));
+        assert!(html.contains("href=\"page?q=a:b\""));
+        assert!(html.contains("src=\"ipfs://Qm\""));
+    }
+
+    #[test]
+    fn cache_renders_once_per_version() {
+        let cache: RenderCache<String> = RenderCache::new();
+        let mut calls = 0;
+        let mut render = |doc: &String| -> Result<String, ()> {
+            calls += 1;
+            Ok(doc.to_uppercase())
+        };
+        assert_eq!(cache.get_or_render("1", &"a".to_string(), &mut render), Ok("A".to_string()));
+        assert_eq!(cache.get_or_render("1", &"a".to_string(), &mut render), Ok("A".to_string()));
+        assert_eq!(cache.get_or_render("1", &"b".to_string(), &mut render), Ok("B".to_string()));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn version_ignores_map_order() {
+        let levels = vec![(1, "a"), (2, "b"), (3, "c")];
+        let mut shuffled = levels.clone();
+        shuffled.reverse();
+        assert_eq!(document_version(&levels), document_version(&shuffled));
+        assert_ne!(document_version(&levels), document_version(&vec![(1, "a")]));
+
+        let translations = serde_json::json!({"en": "x", "fr": "y"});
+        let reordered = serde_json::json!({"fr": "y", "en": "x"});
+        assert_eq!(document_version(&translations), document_version(&reordered));
+    }
+}
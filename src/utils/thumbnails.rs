@@ -0,0 +1,105 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, ImageOutputFormat, Rgba, RgbaImage};
+
+use crate::models::media_model::Rendition;
+
+/// The biggest side of a generated thumbnail in pixels.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// The color of the "success" variant of a thumbnail.
+const SUCCESS_COLOR: [u8; 3] = [76, 175, 80];
+
+/// How much of the success color is mixed into every pixel, from 0 to 1.
+const SUCCESS_TINT: f32 = 0.35;
+
+/// The width of the frame of the "success" variant relative to the biggest side.
+const SUCCESS_FRAME: f32 = 0.04;
+
+/// It generates a rendition of an image. Every rendition is a PNG that fits into
+/// `THUMBNAIL_SIZE` x `THUMBNAIL_SIZE` and keeps the aspect ratio of the source, smaller images
+/// are not scaled up.
+///
+/// Arguments:
+///
+/// * `source`: &[u8] - The content of the uploaded image.
+/// * `rendition`: Rendition - The rendition to generate.
+///
+/// Returns:
+///
+/// The PNG, or the reason the source can not be decoded.
+pub fn render_rendition(source: &[u8], rendition: Rendition) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(source).map_err(|e| e.to_string())?;
+    let mut thumbnail = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+    } else {
+        image
+    }
+    .to_rgba8();
+    if rendition == Rendition::Success {
+        tint_success(&mut thumbnail);
+    }
+
+    let mut png = Cursor::new(Vec::new());
+    thumbnail
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png.into_inner())
+}
+
+/// It mixes the success color into the image and draws a frame of that color around it, so a
+/// passed level can be told apart from a new one at a glance.
+fn tint_success(image: &mut RgbaImage) {
+    let (width, height) = image.dimensions();
+    let frame = ((width.max(height) as f32 * SUCCESS_FRAME).ceil() as u32).max(1);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let on_frame = x < frame || y < frame || x + frame >= width || y + frame >= height;
+        let Rgba([r, g, b, a]) = *pixel;
+        *pixel = if on_frame {
+            Rgba([SUCCESS_COLOR[0], SUCCESS_COLOR[1], SUCCESS_COLOR[2], 255])
+        } else {
+            let mix = |channel: u8, color: u8| {
+                (channel as f32 * (1.0 - SUCCESS_TINT) + color as f32 * SUCCESS_TINT).round() as u8
+            };
+            Rgba([
+                mix(r, SUCCESS_COLOR[0]),
+                mix(g, SUCCESS_COLOR[1]),
+                mix(b, SUCCESS_COLOR[2]),
+                a,
+            ])
+        };
+    }
+}
+
+#[cfg(test)]
+mod thumbnails_tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn thumbnail_keeps_aspect_ratio() {
+        let thumbnail = render_rendition(&png(1024, 512), Rendition::Thumbnail).unwrap();
+        let image = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((image.width(), image.height()), (256, 128));
+    }
+
+    #[test]
+    fn success_is_tinted() {
+        let success = render_rendition(&png(100, 100), Rendition::Success).unwrap();
+        let image = image::load_from_memory(&success).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(0, 0), &Rgba([76, 175, 80, 255]));
+        let center = image.get_pixel(50, 50);
+        assert!(center[0] < 255 && center[1] > center[0]);
+    }
+
+    #[test]
+    fn broken_source() {
+        assert!(render_rendition(b"not an image", Rendition::Thumbnail).is_err());
+    }
+}